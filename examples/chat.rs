@@ -0,0 +1,48 @@
+//! A chat server: every message sent by a client is broadcast to all the connected clients.
+//!
+//! Run with `cargo run --example chat` and connect with a websocket client, eg.
+//! `websocat ws://localhost:8888/chat/alice`
+use highnoon::ws::Broadcast;
+use highnoon::{App, Message, Request};
+use tracing::info;
+
+struct State {
+    chat: Broadcast<String>,
+}
+
+impl highnoon::State for State {
+    type Context = ();
+
+    fn new_context(&self) -> Self::Context {}
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt().compact().init();
+
+    let mut app = App::new(State {
+        chat: Broadcast::new(100),
+    });
+
+    app.at("/chat/:name")
+        .ws(|req: Request<State>, tx, mut rx| async move {
+            let name = req.param("name")?.to_owned();
+            let chat = &req.state().chat;
+
+            // send everyone's messages to this client, until the handler returns
+            let _forwarding = chat.forward(tx);
+            chat.send(format!("{} joined", name));
+
+            while let Some(msg) = rx.recv().await? {
+                if let Message::Text(text) = msg {
+                    chat.send(format!("{}: {}", name, text));
+                }
+            }
+
+            chat.send(format!("{} left", name));
+            Ok(())
+        });
+
+    info!("chat server listening on 0.0.0.0:8888");
+    app.listen("0.0.0.0:8888").await
+}
@@ -5,7 +5,6 @@ use highnoon::filter::Next;
 use highnoon::{App, Error, Json, Message, Request, Response, Result};
 use hyper::StatusCode;
 use serde_derive::Serialize;
-use tokio;
 use tracing::info;
 
 /// a fake database, in a real server this would be a pool connection
@@ -148,10 +147,8 @@ async fn main() -> Result<()> {
         highnoon::filter::session::SessionFilter::new(memstore)
             .with_cookie_name("simple_sid")
             .with_expiry(time::Duration::minutes(5))
-            .with_callback(|cookie| {
-                // for demo purposes - default is secure cookies
-                cookie.set_secure(false);
-            }),
+            // for demo purposes - default is secure cookies
+            .with_secure(false),
     );
 
     // setup routes
@@ -166,10 +163,7 @@ async fn main() -> Result<()> {
     // a route with a parameter, also uses session data
     app.at("/echo/:name")
         .get(|mut req: Request<State>| async move {
-            let seen = match req.session().get("seen") {
-                None => 0,
-                Some(s) => s.parse()?,
-            };
+            let seen: u32 = req.session().get_as("seen")?.unwrap_or(0);
 
             let greeting = if seen > 1 {
                 "You again!"
@@ -179,7 +173,7 @@ async fn main() -> Result<()> {
                 "Hello"
             };
 
-            req.session().set("seen".to_owned(), (seen + 1).to_string());
+            req.session().set_as("seen".to_owned(), &(seen + 1))?;
 
             let p = req.param("name");
             Ok(match p {
@@ -228,17 +222,17 @@ async fn main() -> Result<()> {
     });
 
     // create a sub-app with the auth filter
-    let mut api = App::new(ApiState::default());
+    let mut api = App::new(ApiState);
     api.with(AuthCheck);
 
     // check auth is working
-    api.at("check").get(|req: Request<ApiState>| async move {
+    api.at("/check").get(|req: Request<ApiState>| async move {
         println!("URI: {}", req.uri());
         println!("Bearer: {:?}", req.context().token);
         StatusCode::OK
     });
     // check that parameters get merged
-    api.at("user/:name").get(|req: Request<_>| async move {
+    api.at("/user/:name").get(|req: Request<_>| async move {
         println!("URI: {}", req.uri());
         println!("params: {:?}", req.params());
         StatusCode::OK
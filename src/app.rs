@@ -1,32 +1,96 @@
+#[cfg(feature = "static-files")]
+use crate::embedded::{Assets, EmbeddedFiles};
 use crate::endpoint::Endpoint;
+use crate::filter::timeout::TimeoutOverride;
 use crate::filter::{Filter, Next};
-use crate::router::{RouteTarget, Router};
+use crate::proxy::{ForwardedHeader, TrustedProxies, TrustedProxy};
+use crate::request::BodyRead;
+use crate::router::{RouteTarget, Routes, TrailingSlash};
 use crate::state::State;
-use crate::static_files::StaticFiles;
+#[cfg(feature = "static-files")]
+use crate::static_files::{StaticFiles, StaticFilesOptions};
+#[cfg(feature = "test-client")]
 use crate::test_client::TestClient;
+#[cfg(feature = "tls")]
+use crate::tls::{TlsIncoming, TlsInfo};
+#[cfg(feature = "websocket")]
 use crate::ws::{WebSocketReceiver, WebSocketSender};
-use crate::{Request, Responder, Response, Result};
+use crate::{Error, Request, Responder, Response, Result};
 use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use headers::ContentType;
+use hyper::body::HttpBody;
+use hyper::header::{HeaderValue, SERVER};
+use hyper::server::accept::Accept;
 use hyper::server::conn::{AddrIncoming, AddrStream};
-use hyper::server::Builder;
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Method};
+use hyper::{Body, Method, StatusCode};
+use mime::Mime;
+use std::collections::HashMap;
 use std::convert::Infallible;
-use std::future::Future;
+use std::future::{Future, IntoFuture};
 use std::net::SocketAddr;
+#[cfg(feature = "static-files")]
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::ToSocketAddrs;
-use tracing::info;
+use tokio::task::JoinSet;
+#[cfg(feature = "tls")]
+use tokio_rustls::rustls;
+use tracing::{info, warn};
+
+/// Name of the route parameter holding the rest of the path for a mounted app
+pub(crate) const PATH_REST_PARAM: &str = "-highnoon-path-rest-";
 
 /// The main entry point to highnoon. An `App` can be launched as a server
 /// or mounted into another `App`.
 /// Each `App` has a chain of [`Filters`](Filter)
 /// which are applied to each request.
+///
+/// ## HTTP versions
+///
+/// TLS servers accept both HTTP/1.1 and HTTP/2 on the same listener, with the version
+/// negotiated with ALPN (list `h2` in the `rustls::ServerConfig`'s `alpn_protocols`).
+/// Cleartext servers only accept HTTP/1.1 unless [App::enable_h2c] is called, which adds
+/// cleartext HTTP/2 (h2c) with "prior knowledge", where the client starts the connection with
+/// the HTTP/2 preface - this is what gRPC and most service meshes use.
+///
+/// The HTTP/1.1 `Upgrade: h2c` mechanism is *not* supported (hyper does not implement it), and
+/// the header is ignored so those requests are answered over HTTP/1.1, as RFC 7540 allows.
+/// This is separate from websocket upgrades, which are handled by [Route::ws] on a
+/// per-route basis.
 pub struct App<S: State> {
     state: S,
-    routes: Router<S>,
+    routes: Routes<S>,
     filters: Vec<Box<dyn Filter<S> + Send + Sync + 'static>>,
+    warn_unread_body: bool,
+    debug: bool,
+    pub(crate) detailed_body_errors: bool,
+    #[cfg(feature = "compression")]
+    max_decompressed_size: u64,
+    default_text_content_type: Option<Mime>,
+    error_pages: HashMap<StatusCode, ErrorPage>,
+    server_header: ServerHeader,
+    trusted_proxies: Vec<TrustedProxy>,
+    forwarded_header: ForwardedHeader,
+    h2c: bool,
+    spawner: Spawner,
+}
+
+/// What to do with the `Server` header of responses
+enum ServerHeader {
+    Unchanged,
+    Default(HeaderValue),
+    Remove,
+}
+
+/// A static body used for responses highnoon generates with a given status
+struct ErrorPage {
+    body: Bytes,
+    content_type: ContentType,
 }
 
 /// Returned by [App::at] and attaches method handlers to a route.
@@ -42,8 +106,30 @@ impl<'a, 'p, S: State> Route<'a, 'p, S> {
         self
     }
 
+    /// Attach one endpoint for several HTTP methods. The endpoint is shared, so it does not need
+    /// to be `Clone`.
+    /// ```
+    /// # use highnoon::{App, Method};
+    /// # let mut app = App::new(());
+    /// app.at("/search")
+    ///     .methods(&[Method::GET, Method::POST], |_req| async { "results" });
+    /// ```
+    pub fn methods(self, methods: &[Method], ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
+        self.app.routes.add_methods(methods, self.path, ep);
+        self
+    }
+
     /// Attach an endpoint for all HTTP methods. These will be checked only if no
     /// specific endpoint exists for the method.
+    ///
+    /// Routes are matched in this order:
+    /// 1. an endpoint for the request's method (eg. [Route::get]) with a matching path
+    /// 2. an `all` endpoint with a matching path
+    /// 3. if the path matches an endpoint for another method, `405 Method Not Allowed`
+    /// 4. the fallback endpoint (see [App::fallback]), or `404 Not Found`
+    ///
+    /// Within each step the most specific path wins (eg. `/user/me` beats `/user/:id`, which
+    /// beats `/user/*`), so an `all` endpoint on `/*` still only catches paths with no endpoint.
     pub fn all(self, ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
         self.app.routes.add_all(self.path, ep);
         self
@@ -69,16 +155,104 @@ impl<'a, 'p, S: State> Route<'a, 'p, S> {
         self.method(Method::DELETE, ep)
     }
 
+    /// Attach an endpoint for PATCH requests
+    pub fn patch(self, ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
+        self.method(Method::PATCH, ep)
+    }
+
+    /// Attach an endpoint for HEAD requests
+    pub fn head(self, ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
+        self.method(Method::HEAD, ep)
+    }
+
+    /// Attach an endpoint for OPTIONS requests.
+    ///
+    /// A server-wide `OPTIONS *` request is not routed, and instead gets `204 No Content` with
+    /// an `Allow` header listing the methods which have routes in the app. Paths without an
+    /// OPTIONS endpoint get a similar response listing the methods for the path, unless this
+    /// is turned off with [App::auto_options].
+    pub fn options(self, ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
+        self.method(Method::OPTIONS, ep)
+    }
+
+    /// Override the duration of the [Timeout](crate::filter::Timeout) filter for all endpoints
+    /// on this route (eg. for report generation or uploads which take longer than most requests).
+    /// ```
+    /// # use highnoon::{App, filter::Timeout};
+    /// # use std::time::Duration;
+    /// # let mut app = App::new(());
+    /// app.with(Timeout::new(Duration::from_secs(5)));
+    /// app.at("/report")
+    ///     .timeout(Duration::from_secs(60))
+    ///     .get(|_req| async { "a slow report" });
+    /// ```
+    /// Routes of a mounted app only affect the timeout filters of the mounted app.
+    pub fn timeout(self, duration: Duration) -> Self {
+        self.app
+            .routes
+            .set_timeout(self.path, TimeoutOverride::Duration(duration));
+        self
+    }
+
+    /// Disable the [Timeout](crate::filter::Timeout) filter for all endpoints on this route
+    pub fn no_timeout(self) -> Self {
+        self.app
+            .routes
+            .set_timeout(self.path, TimeoutOverride::Disabled);
+        self
+    }
+
     /// Serve static files located in the path `root`. The path should end with a wildcard segment
     /// (ie. `/*`). The wildcard portion of the URL will be appended to `root` to form the full
     /// path. The file extension is used to guess a mime type. Files outside of `root` will return
     /// a FORBIDDEN error code; `..` and `.` path segments are allowed as long as they do not navigate
-    /// outside of `root`.
+    /// outside of `root`. Path segments are percent-decoded after matching, and segments which
+    /// decode to contain a slash or to a dot segment (eg. `%2F` or `%2E%2E`) are FORBIDDEN.
+    /// Conditional requests are supported (see [Request::send_file]).
+    /// (Only available with the `static-files` feature)
+    #[cfg(feature = "static-files")]
     pub fn static_files(self, root: impl Into<PathBuf>) -> Self {
         let prefix = self.path.to_owned(); // TODO - borrow issue here
         self.method(Method::GET, StaticFiles::new(root, prefix))
     }
 
+    /// Serve static files like [Route::static_files], with extra options (eg. to list the
+    /// contents of directories). With [StaticFilesOptions::autoindex] the directory the route
+    /// is mounted at is listed too (eg. `/files/` for `/files/*`).
+    /// ```
+    /// use highnoon::{App, StaticFilesOptions};
+    ///
+    /// let mut app = App::new(());
+    /// app.at("/files/*")
+    ///     .static_files_with("public/", StaticFilesOptions::new().autoindex(true));
+    /// ```
+    /// (Only available with the `static-files` feature)
+    #[cfg(feature = "static-files")]
+    pub fn static_files_with(self, root: impl Into<PathBuf>, options: StaticFilesOptions) -> Self {
+        let root = root.into();
+        // the wildcard doesn't match an empty segment, so list the root directory separately
+        if options.autoindex {
+            if let Some(dir) = self.path.strip_suffix('*') {
+                let ep = StaticFiles::new(root.clone(), self.path).options(options.clone());
+                self.app.routes.add(Method::GET, dir, ep);
+            }
+        }
+        let prefix = self.path.to_owned();
+        self.method(Method::GET, StaticFiles::new(root, prefix).options(options))
+    }
+
+    /// Serve files held in memory (eg. embedded in the binary at compile time). The path should
+    /// end with a wildcard segment (ie. `/*`), and the wildcard portion of the URL (percent-decoded)
+    /// is looked up in `assets`. Like [Route::static_files] the content type is guessed from the
+    /// file extension, and conditional and range requests are supported, using a hash of the file
+    /// contents as the `ETag`.
+    /// (Only available with the `static-files` feature)
+    #[cfg(feature = "static-files")]
+    pub fn embedded_files(self, assets: impl Assets + Send + Sync + 'static) -> Self {
+        let prefix = self.path;
+        self.method(Method::GET, EmbeddedFiles::new(assets, prefix))
+    }
+
     /// Mount an app to handle all requests from this path.
     /// The path may contain parameters and these will be merged into
     /// the parameters from individual paths in the inner `App`.
@@ -90,12 +264,14 @@ impl<'a, 'p, S: State> Route<'a, 'p, S> {
         S2: State,
         S2::Context: From<S::Context>,
     {
-        let path = self.path.to_owned() + "/*-highnoon-path-rest-";
+        let path = format!("{}/*{}", self.path, PATH_REST_PARAM);
         let mounted = MountedApp { app: Arc::new(app) };
         self.app.at(&path).all(mounted);
     }
 
     /// Attach a websocket handler to this route
+    /// (Only available with the `websocket` feature)
+    #[cfg(feature = "websocket")]
     pub fn ws<H, F>(self, handler: H)
     where
         H: Send + Sync + 'static + Fn(Request<S>, WebSocketSender, WebSocketReceiver) -> F,
@@ -103,6 +279,42 @@ impl<'a, 'p, S: State> Route<'a, 'p, S> {
     {
         self.method(Method::GET, crate::ws::endpoint(handler));
     }
+
+    /// Attach a websocket handler, calling `accept_headers` to add headers to the
+    /// `101 Switching Protocols` response (see [WsEndpoint::accept_headers](crate::ws::WsEndpoint::accept_headers)).
+    /// (Only available with the `websocket` feature)
+    #[cfg(feature = "websocket")]
+    pub fn ws_with_headers<A, H, F>(self, accept_headers: A, handler: H)
+    where
+        A: Fn(&mut Response) + Send + Sync + 'static,
+        H: Send + Sync + 'static + Fn(Request<S>, WebSocketSender, WebSocketReceiver) -> F,
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.method(
+            Method::GET,
+            crate::ws::endpoint(handler).accept_headers(accept_headers),
+        );
+    }
+
+    /// Attach a websocket handler with the given [WsConfig](crate::ws::WsConfig) (eg. to send
+    /// keepalive pings).
+    /// (Only available with the `websocket` feature)
+    #[cfg(feature = "websocket")]
+    pub fn ws_with<H, F>(self, config: crate::ws::WsConfig, handler: H)
+    where
+        H: Send + Sync + 'static + Fn(Request<S>, WebSocketSender, WebSocketReceiver) -> F,
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.method(Method::GET, crate::ws::endpoint(handler).config(config));
+    }
+
+    /// Attach a websocket handler which echoes text and binary messages back to the client
+    /// (see [ws::echo](crate::ws::echo)).
+    /// (Only available with the `websocket` feature)
+    #[cfg(feature = "websocket")]
+    pub fn ws_echo(self) {
+        self.method(Method::GET, crate::ws::echo());
+    }
 }
 
 impl<S: State> App<S> {
@@ -112,14 +324,28 @@ impl<S: State> App<S> {
     pub fn new(state: S) -> Self {
         Self {
             state,
-            routes: Router::new(),
+            routes: Routes::new(),
             filters: vec![],
+            warn_unread_body: cfg!(debug_assertions),
+            debug: false,
+            detailed_body_errors: true,
+            #[cfg(feature = "compression")]
+            max_decompressed_size: crate::decompress::MAX_DECOMPRESSED_SIZE,
+            default_text_content_type: None,
+            error_pages: HashMap::new(),
+            server_header: ServerHeader::Unchanged,
+            trusted_proxies: Vec::new(),
+            forwarded_header: ForwardedHeader::default(),
+            h2c: false,
+            spawner: Spawner(None),
         }
     }
 
     /// Create a test client by consuming this App. The test client can be used to send fake
     /// requests to the App and receive responses back. This can be used in unit and
     /// integration tests.
+    /// (Only available with the `test-client` feature)
+    #[cfg(feature = "test-client")]
     pub fn test(self) -> TestClient<S> {
         TestClient::new(self)
     }
@@ -130,7 +356,11 @@ impl<S: State> App<S> {
     }
 
     /// Append a filter to the chain. Filters are applied to all endpoints in this app, and are
-    /// applied in the order they are registered.
+    /// applied in the order they are registered (the first filter registered is the outermost,
+    /// and sees the request first and the response last).
+    ///
+    /// Filters apply to every route in the app regardless of whether the route was added
+    /// before or after the filter.
     pub fn with<F>(&mut self, filter: F)
     where
         F: Filter<S> + Send + Sync + 'static,
@@ -138,41 +368,422 @@ impl<S: State> App<S> {
         self.filters.push(Box::new(filter));
     }
 
+    /// Append several filters to the chain, in iteration order. See [App::with]
+    pub fn with_all<I, F>(&mut self, filters: I)
+    where
+        I: IntoIterator<Item = F>,
+        F: Filter<S> + Send + Sync + 'static,
+    {
+        for filter in filters {
+            self.with(filter);
+        }
+    }
+
+    /// Set the endpoint to run when no route matches the request path, instead of returning
+    /// `404 Not Found`. Unlike an `all` endpoint on `/*`, this only runs once every other route
+    /// has been tried (see [Route::all] for the full order). This is useful for single page
+    /// apps, or proxying unknown paths to another server.
+    ///
+    /// Filters still apply to the fallback endpoint.
+    pub fn fallback(&mut self, ep: impl Endpoint<S> + Send + Sync + 'static) {
+        self.routes.set_fallback(ep);
+    }
+
+    /// Set the endpoint to run when no route matches the request path, eg. to render a custom
+    /// `404 Not Found` page. This is the same as [App::fallback], and replaces any fallback
+    /// already set.
+    /// ```
+    /// # use highnoon::{App, Response, StatusCode};
+    /// # use highnoon::headers::ContentType;
+    /// let mut app = App::new(());
+    /// app.not_found(|_req| async {
+    ///     Response::status(StatusCode::NOT_FOUND)
+    ///         .header(ContentType::html())
+    ///         .body("<h1>Nothing here</h1>")
+    /// });
+    /// ```
+    /// For a static page use [App::error_page] instead, which also applies to `404` responses
+    /// generated elsewhere (eg. for missing static files).
+    pub fn not_found(&mut self, ep: impl Endpoint<S> + Send + Sync + 'static) {
+        self.fallback(ep);
+    }
+
+    /// Set the endpoint to run when the request path has routes, but not for the request's
+    /// method, instead of returning `405 Method Not Allowed`. Filters apply to this endpoint like
+    /// any other. The `Allow` header listing the path's methods is added to the response unless
+    /// the endpoint sets it.
+    ///
+    /// `OPTIONS` requests are still answered automatically unless [App::auto_options] is
+    /// turned off.
+    pub fn method_not_allowed(&mut self, ep: impl Endpoint<S> + Send + Sync + 'static) {
+        self.routes.set_method_not_allowed(ep);
+    }
+
+    /// Handle all requests for `hostname` (from the `Host` header) with another app, eg. to
+    /// serve several domains from one listener. The port and case of the `Host` header are
+    /// ignored. Requests for other hosts, or without a `Host` header, are routed by this app
+    /// as usual.
+    /// ```
+    /// # use highnoon::App;
+    /// let mut api = App::new(());
+    /// api.at("/users").get(|_req| async { "users" });
+    ///
+    /// let mut app = App::new(());
+    /// app.at("/").get(|_req| async { "home page" });
+    /// app.host("api.example.com", api);
+    /// ```
+    /// Like [Route::mount] this app's filters run before the hosted app's filters, and the
+    /// hosted app may have a different state type if its `Context` implements `From` this
+    /// app's `Context`. Requests with more than one `Host` header get `400 Bad Request`.
+    pub fn host<S2>(&mut self, hostname: &str, app: App<S2>)
+    where
+        S2: State,
+        S2::Context: From<S::Context>,
+    {
+        let hosted = HostedApp { app: Arc::new(app) };
+        self.routes.add_host(hostname, hosted);
+    }
+
+    /// Enable debug mode. In debug mode internal errors are returned to the client in the body of
+    /// the `500 Internal Server Error` response, including the chain of causes and the backtrace
+    /// (if captured). **Never enable this in production** as errors may contain sensitive details.
+    pub fn debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    /// Set whether `400 Bad Request` responses for request bodies which fail to parse (eg. from
+    /// [Request::body_json]) describe the error. The default is `true`, which helps API clients
+    /// fix their requests, but the messages can reveal field names and types, so production
+    /// servers may prefer to respond with a generic message. The detailed error is logged
+    /// either way.
+    pub fn detailed_body_errors(&mut self, detailed: bool) {
+        self.detailed_body_errors = detailed;
+    }
+
+    /// Set the maximum size of a compressed request body once it is decompressed (the default
+    /// is 16MiB). Bodies are decompressed as handlers read them, and reading fails with
+    /// `413 Payload Too Large` as soon as the limit is passed, so a small compressed body can't
+    /// expand to fill the server's memory.
+    /// (Only available with the `compression` feature)
+    #[cfg(feature = "compression")]
+    pub fn max_decompressed_size(&mut self, max: u64) {
+        self.max_decompressed_size = max;
+    }
+
+    /// Answer `OPTIONS` requests for paths which have no `OPTIONS` endpoint with
+    /// `204 No Content` and an `Allow` header listing the path's methods (the default). When
+    /// turned off these requests get `405 Method Not Allowed` like any other method without an
+    /// endpoint.
+    pub fn auto_options(&mut self, enabled: bool) {
+        self.routes.auto_options = enabled;
+    }
+
+    /// Set how requests are handled when their path only matches a route once a trailing slash
+    /// is added or removed, eg. `/users/` when the route is `/users` (see [TrailingSlash]).
+    /// The default is [TrailingSlash::Strict], which treats them as different paths.
+    /// ```
+    /// # use highnoon::{App, TrailingSlash};
+    /// let mut app = App::new(());
+    /// app.trailing_slash(TrailingSlash::Redirect);
+    /// // `GET /users/?page=2` is redirected to `/users?page=2`
+    /// app.at("/users").get(|_req| async { "users" });
+    /// ```
+    /// Paths with a route of their own are never redirected. Mounted apps have their own
+    /// setting.
+    pub fn trailing_slash(&mut self, mode: TrailingSlash) {
+        self.routes.trailing_slash = mode;
+    }
+
+    /// Set the content type of responses whose body is a string (eg. endpoints returning `String`
+    /// or `&str`), for apps which mostly serve one type of text (eg. `text/html`). Responses
+    /// which set their own content type are not changed.
+    /// ```
+    /// # use highnoon::App;
+    /// let mut app = App::new(());
+    /// app.default_text_content_type(mime::TEXT_HTML_UTF_8);
+    /// app.at("/").get(|_req| async { "<h1>Hello</h1>" });
+    /// ```
+    /// In a mounted app this only applies to responses from that app's routes, and takes
+    /// precedence over the parent app's default.
+    pub fn default_text_content_type(&mut self, mime: Mime) {
+        self.default_text_content_type = Some(mime);
+    }
+
+    /// Set the body used for responses with the given status which highnoon generates itself,
+    /// such as `404 Not Found` and `405 Method Not Allowed` from the router, `413 Payload Too
+    /// Large` from the body size limits, `503 Service Unavailable` from the
+    /// [Timeout](crate::filter::Timeout) filter, and `500 Internal Server Error` for internal
+    /// errors.
+    /// ```
+    /// # use highnoon::{App, StatusCode};
+    /// let mut app = App::new(());
+    /// app.error_page(StatusCode::NOT_FOUND, "<h1>Nothing here</h1>", mime::TEXT_HTML_UTF_8);
+    /// ```
+    /// Responses returned by endpoints and filters are never changed, even if they have the
+    /// same status. A [fallback](App::fallback) endpoint handles unmatched paths itself, so
+    /// it takes precedence over a `404` page. In a mounted app, the mounted app's pages take
+    /// precedence over the parent app's.
+    pub fn error_page(&mut self, status: StatusCode, body: impl Into<Bytes>, content_type: Mime) {
+        self.error_pages.insert(
+            status,
+            ErrorPage {
+                body: body.into(),
+                content_type: content_type.into(),
+            },
+        );
+    }
+
+    /// Send a `Server` header with this value on every response (hyper doesn't send one).
+    /// Responses which set their own `Server` header are not changed.
+    /// ```
+    /// # use highnoon::App;
+    /// # use hyper::header::HeaderValue;
+    /// let mut app = App::new(());
+    /// app.server_header(HeaderValue::from_static("example/1.0"));
+    /// ```
+    /// In a mounted app this applies to responses from that app's routes, and takes precedence
+    /// over the parent app's value (but not over the parent's [App::no_server_header]).
+    pub fn server_header(&mut self, value: HeaderValue) {
+        self.server_header = ServerHeader::Default(value);
+    }
+
+    /// Remove the `Server` header from every response, including ones set by endpoints and
+    /// filters, to avoid revealing what the server is running.
+    pub fn no_server_header(&mut self) {
+        self.server_header = ServerHeader::Remove;
+    }
+
+    /// Trust the forwarding header (see [App::forwarded_header]) of requests from these
+    /// proxies, so that [Request::real_ip] finds the address of the client behind them. Each
+    /// proxy is an address or a network in CIDR notation. Can be called more than once to add
+    /// more proxies.
+    /// ```
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use highnoon::App;
+    /// let mut app = App::new(());
+    /// app.trust_proxies(["10.0.0.0/8".parse()?, "127.0.0.1".parse()?]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    /// Forwarding headers can be set by anyone, so only list proxies which are known to
+    /// replace or append to them. This only has an effect on the app which accepts the
+    /// connection, mounted apps use the same proxies.
+    pub fn trust_proxies(&mut self, proxies: impl IntoIterator<Item = TrustedProxy>) {
+        self.trusted_proxies.extend(proxies);
+    }
+
+    /// Choose which header trusted proxies put the client's address in. The default is
+    /// `X-Forwarded-For`. Other forwarding headers are ignored, since proxies usually pass them
+    /// through from the client unchanged.
+    /// ```
+    /// # use highnoon::{App, ForwardedHeader};
+    /// let mut app = App::new(());
+    /// app.forwarded_header(ForwardedHeader::Forwarded);
+    /// ```
+    pub fn forwarded_header(&mut self, header: ForwardedHeader) {
+        self.forwarded_header = header;
+    }
+
+    /// Accept cleartext HTTP/2 (h2c) connections using "prior knowledge" as well as HTTP/1.1
+    /// (see [HTTP versions](App#http-versions)). TLS connections always allow HTTP/2.
+    /// ```
+    /// # use highnoon::App;
+    /// let mut app = App::new(());
+    /// app.enable_h2c();
+    /// ```
+    /// This only has an effect on the app which accepts the connection.
+    pub fn enable_h2c(&mut self) {
+        self.h2c = true;
+    }
+
+    /// Apply settings to a response from this app's routes
+    fn finish_response(&self, resp: &mut Response) {
+        if resp.take_generated() {
+            if let Some(page) = self.error_pages.get(&resp.get_status()) {
+                resp.set_body(page.body.clone());
+                resp.set_header(page.content_type.clone());
+            }
+        }
+
+        if let Some(mime) = &self.default_text_content_type {
+            resp.set_default_text_content_type(mime);
+        }
+
+        match &self.server_header {
+            ServerHeader::Unchanged => {}
+            ServerHeader::Default(value) => {
+                resp.headers_mut()
+                    .entry(SERVER)
+                    .or_insert_with(|| value.clone());
+            }
+            ServerHeader::Remove => {
+                resp.headers_mut().remove(SERVER);
+            }
+        }
+    }
+
+    /// Log a warning when a request completes without the handler reading a non-empty request
+    /// body. Unread bodies must be drained by hyper before the connection can be reused, so this
+    /// helps find handlers that forgot to read (or limit) the body.
+    /// This is enabled by default in debug builds only.
+    pub fn warn_unread_body(&mut self, warn: bool) {
+        self.warn_unread_body = warn;
+    }
+
+    /// Spawn tasks on the given runtime instead of the runtime the server was started on.
+    /// This includes the task for each connection (and so the route handlers), and the
+    /// detached tasks running websocket handlers.
+    ///
+    /// The accept loop still runs wherever the future returned by [App::listen] is polled.
+    /// (Spawned tasks must be `Send`, so running on a `LocalSet` is not supported).
+    pub fn with_spawner(&mut self, handle: tokio::runtime::Handle) {
+        self.spawner = Spawner(Some(handle));
+    }
+
+    pub(crate) fn spawner(&self) -> Spawner {
+        self.spawner.clone()
+    }
+
+    pub(crate) fn router(&self) -> &Routes<S> {
+        &self.routes
+    }
+
     /// Create a route at the given path. Returns a [Route] object on which you can
-    /// attach handlers for each HTTP method
+    /// attach handlers for each HTTP method.
+    ///
+    /// Paths which look like mistakes (see [validate_path](crate::router::validate_path)) are
+    /// logged as a warning, but the route is still added.
     pub fn at<'a, 'p>(&'a mut self, path: &'p str) -> Route<'a, 'p, S> {
+        if let Err(err) = crate::router::validate_path(path) {
+            warn!(%path, "suspicious route path: {}", err);
+        }
         Route { path, app: self }
     }
 
     /// Start a server listening on the given address (See [ToSocketAddrs] from tokio)
-    /// This method only returns if there is an error. (Graceful shutdown is TODO)
+    /// This method only returns if there is an error (see [App::listen_with_shutdown] for a
+    /// server which can be stopped).
     pub async fn listen(self, host: impl ToSocketAddrs) -> anyhow::Result<()> {
-        let mut addrs = tokio::net::lookup_host(host).await?;
-        let addr = addrs
-            .next()
-            .ok_or_else(|| anyhow::Error::msg("host lookup returned no hosts"))?;
+        let incoming = bind(host).await?;
+        self.internal_serve(incoming, None).await
+    }
+
+    /// Start a server listening on the given address, which shuts down gracefully when
+    /// `signal` completes: it stops accepting connections, waits for in-flight requests to
+    /// finish, and then returns `Ok(())`.
+    ///
+    /// Await the returned [GracefulServer] to run the server. Use
+    /// [GracefulServer::drain_timeout] to limit how long it waits for requests to finish, and
+    /// [GracefulServer::tls] to accept TLS connections.
+    /// ```no_run
+    /// # use highnoon::App;
+    /// # use std::time::Duration;
+    /// # async fn run() -> anyhow::Result<()> {
+    /// // eg. sent from a ctrl-c handler
+    /// let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    ///
+    /// let app = App::new(());
+    /// app.listen_with_shutdown("0.0.0.0:8000", async {
+    ///     stopped.await.ok();
+    /// })
+    /// .drain_timeout(Duration::from_secs(30))
+    /// .await
+    /// # }
+    /// ```
+    /// Websocket connections are handed off to their own task once the handshake completes,
+    /// so they are not waited for (or closed by the drain timeout). They keep running until
+    /// the handler returns or the runtime shuts down, so long lived handlers should watch for
+    /// the shutdown themselves (eg. with a `tokio::sync::watch` channel in the state).
+    pub fn listen_with_shutdown<A, F>(self, host: A, signal: F) -> GracefulServer<S>
+    where
+        A: ToSocketAddrs + Send + 'static,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        GracefulServer::new(self, Box::pin(bind(host)), signal)
+    }
 
-        let builder = hyper::Server::try_bind(&addr)?;
-        self.internal_serve(builder).await
+    /// Start a server listening on the provided [std::net::TcpListener], which shuts down
+    /// gracefully when `signal` completes (see [App::listen_with_shutdown])
+    pub fn listen_on_with_shutdown<F>(
+        self,
+        tcp: std::net::TcpListener,
+        signal: F,
+    ) -> GracefulServer<S>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        GracefulServer::new(self, Box::pin(async move { from_tcp(tcp) }), signal)
     }
 
     /// Start a server listening on the provided [std::net::TcpListener]
-    /// This method only returns if there is an error. (Graceful shutdown is TODO)
+    /// This method only returns if there is an error (see [App::listen_on_with_shutdown] for
+    /// a server which can be stopped).
     pub async fn listen_on(self, tcp: std::net::TcpListener) -> anyhow::Result<()> {
-        let builder = hyper::Server::from_tcp(tcp)?;
-        self.internal_serve(builder).await
+        let incoming = from_tcp(tcp)?;
+        self.internal_serve(incoming, None).await
+    }
+
+    /// Start a server accepting TLS connections on the given address.
+    /// This method only returns if there is an error (see [GracefulServer::tls] for a server
+    /// which can be stopped).
+    ///
+    /// If the `config` requests client certificates they are available to handlers
+    /// through [Request::client_cert]. Use [tls_config_from_pem](crate::tls_config_from_pem)
+    /// to build a config from certificate and key files.
+    ///
+    /// Only available with the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub async fn listen_tls(
+        self,
+        host: impl ToSocketAddrs,
+        config: rustls::ServerConfig,
+    ) -> anyhow::Result<()> {
+        let incoming = bind(host).await?;
+        self.internal_serve(TlsIncoming::new(incoming, config), None)
+            .await
+    }
+
+    /// Start a server accepting TLS connections on the provided [std::net::TcpListener]
+    /// This method only returns if there is an error (see [GracefulServer::tls] for a server
+    /// which can be stopped).
+    ///
+    /// Only available with the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub async fn listen_on_tls(
+        self,
+        tcp: std::net::TcpListener,
+        config: rustls::ServerConfig,
+    ) -> anyhow::Result<()> {
+        let incoming = from_tcp(tcp)?;
+        self.internal_serve(TlsIncoming::new(incoming, config), None)
+            .await
     }
 
-    async fn internal_serve(self, builder: Builder<AddrIncoming>) -> anyhow::Result<()> {
+    async fn internal_serve<I>(self, incoming: I, shutdown: Option<Shutdown>) -> anyhow::Result<()>
+    where
+        I: Accept + Listener,
+        I::Conn: Connection + AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
         let app = Arc::new(self);
+        let local_addr = incoming.local_addr();
+        let http1_only = !app.h2c && !incoming.is_tls();
 
-        let make_svc = make_service_fn(|addr_stream: &AddrStream| {
+        #[cfg_attr(not(feature = "tls"), allow(unused_mut))]
+        let make_svc = make_service_fn(|conn: &I::Conn| {
             let app = app.clone();
-            let addr = addr_stream.remote_addr();
+            let addr = conn.remote_addr();
+            #[cfg(feature = "tls")]
+            let tls = conn.tls_info();
 
             async move {
-                Ok::<_, Infallible>(service_fn(move |req: hyper::Request<Body>| {
+                Ok::<_, Infallible>(service_fn(move |mut req: hyper::Request<Body>| {
                     let app = app.clone();
+                    #[cfg(feature = "tls")]
+                    if let Some(tls) = &tls {
+                        req.extensions_mut().insert(tls.clone());
+                    }
+
                     async move {
                         App::serve_one_req(app, req, addr)
                             .await
@@ -182,31 +793,140 @@ impl<S: State> App<S> {
             }
         });
 
-        let server = builder.serve(make_svc);
-        info!("server listening on {}", server.local_addr());
-        server.await?;
+        // connection tasks are only tracked if they may need to be aborted
+        let tasks = shutdown
+            .as_ref()
+            .and_then(|shutdown| shutdown.drain_timeout)
+            .map(|_| Arc::new(Mutex::new(JoinSet::new())));
+
+        let server = hyper::Server::builder(incoming)
+            .http1_only(http1_only)
+            .executor(ConnSpawner {
+                spawner: app.spawner(),
+                tasks: tasks.clone(),
+            })
+            .serve(make_svc);
+        info!("server listening on {}", local_addr);
+
+        let Shutdown {
+            signal,
+            drain_timeout,
+        } = match shutdown {
+            Some(shutdown) => shutdown,
+            None => {
+                server.await?;
+                return Ok(());
+            }
+        };
+
+        let (draining_tx, draining_rx) = tokio::sync::oneshot::channel();
+        let server = server.with_graceful_shutdown(async move {
+            signal.await;
+            info!("shutting down, waiting for in-flight requests");
+            let _ = draining_tx.send(());
+        });
+
+        let drain_expired = async move {
+            match (draining_rx.await, drain_timeout) {
+                (Ok(()), Some(timeout)) => tokio::time::sleep(timeout).await,
+                _ => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            result = server => result?,
+            () = drain_expired => {
+                warn!("drain timeout expired, closing the remaining connections");
+                if let Some(tasks) = tasks {
+                    tasks.lock().expect("lock poisoned").abort_all();
+                }
+            }
+        }
+
+        info!("server stopped");
         Ok(())
     }
 
     pub(crate) async fn serve_one_req(
         app: Arc<App<S>>,
-        req: hyper::Request<Body>,
+        mut req: hyper::Request<Body>,
         addr: SocketAddr,
     ) -> Result<hyper::Response<Body>> {
-        let RouteTarget { ep, params } = app.routes.lookup(req.method(), req.uri().path());
+        #[cfg(feature = "compression")]
+        if let Err(err) =
+            crate::decompress::decode_request_body(&mut req, app.max_decompressed_size)
+        {
+            return err.into_response().map(|resp| resp.into_inner());
+        }
+
+        if !app.trusted_proxies.is_empty() {
+            req.extensions_mut().insert(TrustedProxies {
+                proxies: app.trusted_proxies.as_slice().into(),
+                header: app.forwarded_header,
+            });
+        }
+
+        let RouteTarget {
+            ep,
+            params,
+            timeout,
+            allowed,
+        } = app.routes.route(&req, req.uri().path());
+
+        if let Some(timeout) = timeout {
+            req.extensions_mut().insert(timeout);
+        }
+        if let Some(allowed) = allowed {
+            req.extensions_mut().insert(allowed);
+        }
+
+        let unread_body = if app.warn_unread_body && !req.body().is_end_stream() {
+            let body_read = BodyRead::default();
+            req.extensions_mut().insert(body_read.clone());
+            Some((req.method().clone(), req.uri().clone(), body_read))
+        } else {
+            None
+        };
 
         let ctx = app.state.new_context();
         let req = Request::new(app.clone(), req, params, addr, ctx);
 
         let next = Next {
             ep,
-            rest: &*app.filters,
+            rest: &app.filters,
         };
 
-        next.next(req)
+        let resp = next
+            .next(req)
             .await
-            .or_else(|err| err.into_response())
-            .map(|resp| resp.into_inner())
+            .or_else(|err| app.render_error(err))
+            .map(|mut resp| {
+                app.finish_response(&mut resp);
+                if let Some(task) = resp.take_trailers_task() {
+                    app.spawner().spawn(task);
+                }
+                resp.into_inner()
+            });
+
+        if let Some((method, uri, body_read)) = unread_body {
+            if !body_read.is_read() {
+                warn!(%method, %uri, "request body was not read by the handler");
+            }
+        }
+
+        resp
+    }
+
+    /// Convert an error which reached the top of the filter chain into a response
+    fn render_error(&self, err: Error) -> Result<Response> {
+        match err {
+            Error::Internal(err) if self.debug => {
+                Ok(Response::status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header(headers::ContentType::text_utf8())
+                    .body(format!("{:?}", err)))
+            }
+            err => err.into_response(),
+        }
     }
 }
 
@@ -224,26 +944,302 @@ where
         let (inner, params, remote_addr, context) = req.into_parts();
         // get the part of the path still to be routed
         let path_rest = params
-            .find("-highnoon-path-rest-")
-            .expect("-highnoon-path-rest- is missing!");
-        // lookup the target for the request in the nested app
-        let RouteTarget {
-            ep,
-            params: params2,
-        } = self.app.routes.lookup(inner.method(), path_rest);
+            .find(PATH_REST_PARAM)
+            .expect("-highnoon-path-rest- is missing!")
+            .to_owned();
 
-        // construct a new request for the inner state type
-        let mut req2 = Request::new(self.app.clone(), inner, params, remote_addr, context.into());
+        call_nested(
+            &self.app,
+            inner,
+            params,
+            &path_rest,
+            remote_addr,
+            context.into(),
+        )
+        .await
+    }
+}
 
-        // merge the inner params
-        req2.merge_params(params2);
+/// An app handling all requests for a host, added with [App::host]
+struct HostedApp<S: State> {
+    app: Arc<App<S>>,
+}
 
-        // start the filter chain for the nested app
-        let next = Next {
-            ep,
-            rest: &*self.app.filters,
+#[async_trait]
+impl<S: State, S2: State> Endpoint<S> for HostedApp<S2>
+where
+    S2::Context: From<S::Context>,
+{
+    async fn call(&self, req: Request<S>) -> Result<Response> {
+        let (inner, params, remote_addr, context) = req.into_parts();
+        let path = inner.uri().path().to_owned();
+
+        call_nested(&self.app, inner, params, &path, remote_addr, context.into()).await
+    }
+}
+
+/// Route a request from an outer app through a nested app, running the nested app's filters
+async fn call_nested<S: State>(
+    app: &Arc<App<S>>,
+    mut inner: hyper::Request<Body>,
+    params: route_recognizer::Params,
+    path: &str,
+    remote_addr: SocketAddr,
+    context: S::Context,
+) -> Result<Response> {
+    // lookup the target for the request in the nested app
+    let RouteTarget {
+        ep,
+        params: params2,
+        timeout,
+        allowed,
+    } = app.routes.route(&inner, path);
+
+    // construct a new request for the inner state type
+    if let Some(timeout) = timeout {
+        inner.extensions_mut().insert(timeout);
+    }
+    if let Some(allowed) = allowed {
+        inner.extensions_mut().insert(allowed);
+    }
+    let mut req2 = Request::new(app.clone(), inner, params, remote_addr, context);
+
+    // merge the inner params
+    req2.merge_params(params2);
+
+    // start the filter chain for the nested app
+    let next = Next {
+        ep,
+        rest: &app.filters,
+    };
+
+    match next.next(req2).await {
+        Ok(mut resp) => {
+            app.finish_response(&mut resp);
+            Ok(resp)
+        }
+        Err(Error::Http(mut resp)) => {
+            app.finish_response(&mut resp);
+            Err(Error::Http(resp))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// A server which shuts down gracefully, created with [App::listen_with_shutdown] or
+/// [App::listen_on_with_shutdown]. Await it to run the server.
+pub struct GracefulServer<S: State> {
+    app: App<S>,
+    incoming: BoxFuture<'static, anyhow::Result<AddrIncoming>>,
+    signal: BoxFuture<'static, ()>,
+    drain_timeout: Option<Duration>,
+    #[cfg(feature = "tls")]
+    tls: Option<rustls::ServerConfig>,
+}
+
+impl<S: State> GracefulServer<S> {
+    fn new(
+        app: App<S>,
+        incoming: BoxFuture<'static, anyhow::Result<AddrIncoming>>,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> Self {
+        Self {
+            app,
+            incoming,
+            signal: Box::pin(signal),
+            drain_timeout: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Set the longest time to wait for in-flight requests once the shutdown signal completes.
+    /// After this the remaining connections are closed (aborting their requests) and the
+    /// server returns. By default the server waits for as long as it takes.
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = Some(timeout);
+        self
+    }
+
+    /// Accept TLS connections using `config`, like [App::listen_tls]
+    /// (Only available with the `tls` feature)
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: rustls::ServerConfig) -> Self {
+        self.tls = Some(config);
+        self
+    }
+}
+
+impl<S: State> IntoFuture for GracefulServer<S> {
+    type Output = anyhow::Result<()>;
+    type IntoFuture = BoxFuture<'static, anyhow::Result<()>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let incoming = self.incoming.await?;
+            let shutdown = Some(Shutdown {
+                signal: self.signal,
+                drain_timeout: self.drain_timeout,
+            });
+
+            #[cfg(feature = "tls")]
+            if let Some(config) = self.tls {
+                return self
+                    .app
+                    .internal_serve(TlsIncoming::new(incoming, config), shutdown)
+                    .await;
+            }
+
+            self.app.internal_serve(incoming, shutdown).await
+        })
+    }
+}
+
+/// When and how [App::internal_serve] should stop
+struct Shutdown {
+    signal: BoxFuture<'static, ()>,
+    drain_timeout: Option<Duration>,
+}
+
+/// The executor for a server's connections. If the server has a drain timeout the tasks are
+/// kept in a `JoinSet` so they can be aborted when it expires.
+#[derive(Clone)]
+struct ConnSpawner {
+    spawner: Spawner,
+    tasks: Option<Arc<Mutex<JoinSet<()>>>>,
+}
+
+impl<F> hyper::rt::Executor<F> for ConnSpawner
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, fut: F) {
+        let tasks = match &self.tasks {
+            Some(tasks) => tasks,
+            None => {
+                self.spawner.spawn(fut);
+                return;
+            }
+        };
+
+        let mut tasks = tasks.lock().expect("lock poisoned");
+        // forget about finished tasks so the set doesn't grow forever
+        while tasks.try_join_next().is_some() {}
+
+        let fut = async move {
+            fut.await;
+        };
+        match &self.spawner.0 {
+            Some(handle) => tasks.spawn_on(fut, handle),
+            None => tasks.spawn(fut),
         };
+    }
+}
+
+/// Spawns tasks on a specific runtime if one was set with [App::with_spawner],
+/// otherwise on the current runtime
+#[derive(Clone)]
+pub(crate) struct Spawner(Option<tokio::runtime::Handle>);
+
+impl Spawner {
+    pub(crate) fn spawn<F>(&self, fut: F) -> tokio::task::JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match &self.0 {
+            Some(handle) => handle.spawn(fut),
+            None => tokio::spawn(fut),
+        }
+    }
+
+    pub(crate) fn spawn_blocking<F, R>(&self, f: F) -> tokio::task::JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        match &self.0 {
+            Some(handle) => handle.spawn_blocking(f),
+            None => tokio::task::spawn_blocking(f),
+        }
+    }
+}
+
+impl<F> hyper::rt::Executor<F> for Spawner
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, fut: F) {
+        self.spawn(fut);
+    }
+}
+
+async fn bind(host: impl ToSocketAddrs) -> anyhow::Result<AddrIncoming> {
+    let mut addrs = tokio::net::lookup_host(host).await?;
+    let addr = addrs
+        .next()
+        .ok_or_else(|| anyhow::Error::msg("host lookup returned no hosts"))?;
+
+    Ok(AddrIncoming::bind(&addr)?)
+}
+
+fn from_tcp(tcp: std::net::TcpListener) -> anyhow::Result<AddrIncoming> {
+    tcp.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(tcp)?;
+    Ok(AddrIncoming::from_listener(listener)?)
+}
+
+/// A source of incoming connections for [App::internal_serve]
+trait Listener {
+    fn local_addr(&self) -> SocketAddr;
+
+    fn is_tls(&self) -> bool {
+        false
+    }
+}
+
+impl Listener for AddrIncoming {
+    fn local_addr(&self) -> SocketAddr {
+        AddrIncoming::local_addr(self)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Listener for TlsIncoming {
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr()
+    }
+
+    fn is_tls(&self) -> bool {
+        true
+    }
+}
+
+/// Per-connection details which are passed on to each request
+trait Connection {
+    fn remote_addr(&self) -> SocketAddr;
+
+    #[cfg(feature = "tls")]
+    fn tls_info(&self) -> Option<TlsInfo> {
+        None
+    }
+}
+
+impl Connection for AddrStream {
+    fn remote_addr(&self) -> SocketAddr {
+        AddrStream::remote_addr(self)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Connection for tokio_rustls::server::TlsStream<AddrStream> {
+    fn remote_addr(&self) -> SocketAddr {
+        self.get_ref().0.remote_addr()
+    }
 
-        next.next(req2).await
+    fn tls_info(&self) -> Option<TlsInfo> {
+        Some(TlsInfo::from_stream(self))
     }
 }
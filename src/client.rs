@@ -0,0 +1,246 @@
+use crate::{Error, Result};
+use futures_util::future::BoxFuture;
+use hyper::client::connect::{Connected, Connection};
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::{Body, Method, Request, Response, Uri};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error as StdError;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+#[cfg(feature = "tls")]
+use tokio_rustls::{client::TlsStream, rustls, TlsConnector};
+
+/// A small HTTP client for calling other services from endpoints, wrapping a connection pooled
+/// `hyper::Client`.
+///
+/// Cloning the client is cheap and clones share the connection pool, so create one when the
+/// app starts and keep it in the app's state.
+///
+/// (Only available with the `client` feature)
+/// ```
+/// use highnoon::{App, HttpClient, Request, Result};
+///
+/// struct State {
+///     client: HttpClient,
+/// }
+///
+/// impl highnoon::State for State {
+///     type Context = ();
+///     fn new_context(&self) {}
+/// }
+///
+/// async fn weather(req: Request<State>) -> Result<hyper::Response<hyper::Body>> {
+///     req.state().client.get("http://weather.example.com/today").await
+/// }
+///
+/// let mut app = App::new(State { client: HttpClient::new() });
+/// app.at("/weather").get(weather);
+/// ```
+/// Only `http` URIs are supported unless the client is created with [HttpClient::with_tls]
+/// (which needs the `tls` feature). Requests which fail to connect or get a response return
+/// an internal error.
+#[derive(Clone)]
+pub struct HttpClient {
+    inner: hyper::Client<Connector, Body>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpClient {
+    /// Create a client which supports `http` URIs only
+    pub fn new() -> Self {
+        Self::from_connector(Connector::new())
+    }
+
+    /// Create a client which also supports `https` URIs, using the given rustls config (for
+    /// example with a `RootCertStore` loaded from the system or the `webpki-roots` crate).
+    /// Only HTTP/1.1 is used over TLS.
+    /// (Only available with the `tls` feature)
+    #[cfg(feature = "tls")]
+    pub fn with_tls(config: rustls::ClientConfig) -> Self {
+        let mut connector = Connector::new();
+        connector.tls = Some(TlsConnector::from(std::sync::Arc::new(config)));
+        Self::from_connector(connector)
+    }
+
+    fn from_connector(connector: Connector) -> Self {
+        Self {
+            inner: hyper::Client::builder().build(connector),
+        }
+    }
+
+    /// Send a request and return the response, whatever its status
+    pub async fn request(&self, req: Request<Body>) -> Result<Response<Body>> {
+        Ok(self.inner.request(req).await?)
+    }
+
+    /// Send a `GET` request
+    pub async fn get(&self, uri: &str) -> Result<Response<Body>> {
+        let req = Request::get(uri).body(Body::empty())?;
+        self.request(req).await
+    }
+
+    /// Send a request with a JSON body
+    pub async fn send_json(
+        &self,
+        method: Method,
+        uri: &str,
+        body: &impl Serialize,
+    ) -> Result<Response<Body>> {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(body)?))?;
+        self.request(req).await
+    }
+
+    /// Send a `GET` request and deserialize the JSON response. Responses without a success
+    /// status return an internal error.
+    pub async fn get_json<T: DeserializeOwned>(&self, uri: &str) -> Result<T> {
+        let resp = self.get(uri).await?;
+        if !resp.status().is_success() {
+            return Err(Error::Internal(anyhow::anyhow!(
+                "request to {} returned {}",
+                uri,
+                resp.status()
+            )));
+        }
+
+        let body = hyper::body::to_bytes(resp.into_body()).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+/// Connects over TCP, and then performs a TLS handshake for `https` URIs
+#[derive(Clone)]
+struct Connector {
+    http: HttpConnector,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConnector>,
+}
+
+impl Connector {
+    fn new() -> Self {
+        let mut http = HttpConnector::new();
+        // allow https URIs through, the scheme is checked when connecting
+        http.enforce_http(false);
+        Self {
+            http,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+impl Service<Uri> for Connector {
+    type Response = Stream;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, std::result::Result<Stream, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), BoxError>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let https = uri.scheme() == Some(&hyper::http::uri::Scheme::HTTPS);
+        let host = uri.host().unwrap_or_default().to_owned();
+        let connecting = self.http.call(uri);
+        #[cfg(feature = "tls")]
+        let tls = self.tls.clone();
+
+        Box::pin(async move {
+            let tcp = connecting.await?;
+            if !https {
+                return Ok(Stream::Plain(tcp));
+            }
+
+            #[cfg(feature = "tls")]
+            if let Some(tls) = tls {
+                // IPv6 addresses are bracketed in URIs
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                let name = rustls::ServerName::try_from(host)?;
+                let stream = tls.connect(name, tcp).await?;
+                return Ok(Stream::Tls(Box::new(stream)));
+            }
+
+            Err(format!(
+                "can't connect to {}: https is not enabled on this client",
+                host
+            )
+            .into())
+        })
+    }
+}
+
+/// A connection made by [Connector]
+enum Stream {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        match self {
+            Stream::Plain(tcp) => tcp.connected(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(tls) => tls.get_ref().0.connected(),
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(tcp) => Pin::new(tcp).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(tls) => Pin::new(tls).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(tcp) => Pin::new(tcp).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(tls) => Pin::new(tls).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(tcp) => Pin::new(tcp).poll_flush(cx),
+            #[cfg(feature = "tls")]
+            Stream::Tls(tls) => Pin::new(tls).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(tcp) => Pin::new(tcp).poll_shutdown(cx),
+            #[cfg(feature = "tls")]
+            Stream::Tls(tls) => Pin::new(tls).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::Result;
+use cookie::{Cookie, CookieJar, Key};
+
+/// Signs and verifies cookies using HMAC, so the client cannot tamper with their values.
+/// (Values are *not* encrypted and can still be read by the client).
+///
+/// This is useful for cookies which are not part of the session (e.g. remember-me tokens or CSRF
+/// tokens). See also [Request::signed_cookie](crate::Request::signed_cookie) and
+/// [Response::set_cookie](crate::Response::set_cookie).
+/// ```
+/// # use highnoon::{SignedCookies, Request, Response, Result};
+/// # use highnoon::cookie::Cookie;
+/// async fn remember_me(req: Request<()>, signer: &SignedCookies) -> Result<Response> {
+///     let user = match req.signed_cookie(signer, "user")? {
+///         Some(cookie) => cookie.value().to_owned(),
+///         None => "anonymous".to_owned(),
+///     };
+///
+///     let mut resp = Response::ok().body(format!("Hello {}", user));
+///     resp.set_cookie(signer.sign(Cookie::new("user", "steve")))?;
+///     Ok(resp)
+/// }
+/// ```
+#[derive(Clone)]
+pub struct SignedCookies {
+    key: Key,
+}
+
+impl SignedCookies {
+    /// Create a signer from a secret key. The key must be cryptographically random and at least
+    /// 64 bytes long, otherwise an error is returned.
+    pub fn new(key: &[u8]) -> Result<Self> {
+        Ok(Self {
+            key: Key::try_from(key)?,
+        })
+    }
+
+    /// Create a signer using a randomly generated key. Cookies signed with this key will not
+    /// verify after the server is restarted.
+    pub fn generate() -> Self {
+        Self {
+            key: Key::generate(),
+        }
+    }
+
+    /// Sign a cookie. The signature is prepended to the cookie's value.
+    pub fn sign(&self, cookie: Cookie<'static>) -> Cookie<'static> {
+        let name = cookie.name().to_owned();
+        let mut jar = CookieJar::new();
+        jar.signed_mut(&self.key).add(cookie);
+        jar.get(&name)
+            .cloned()
+            .expect("signed cookie missing from jar")
+    }
+
+    /// Verify a signed cookie. Returns the cookie with the signature removed from the value,
+    /// or `None` if the signature is missing or invalid.
+    pub fn verify(&self, cookie: Cookie<'static>) -> Option<Cookie<'static>> {
+        CookieJar::new().signed(&self.key).verify(cookie)
+    }
+
+    pub(crate) fn key(&self) -> &Key {
+        &self.key
+    }
+}
@@ -0,0 +1,100 @@
+/// Transparent decompression of request bodies based on the `Content-Encoding` header.
+///
+/// Only compiled with the `compression` feature.
+use crate::{Error, Result};
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
+use futures_util::{StreamExt, TryStreamExt};
+use hyper::header::{CONTENT_ENCODING, CONTENT_LENGTH};
+use hyper::{Body, StatusCode};
+use std::io;
+use tokio::io::{AsyncBufRead, BufReader};
+use tokio_util::io::{ReaderStream, StreamReader};
+use tracing::debug;
+
+/// Default maximum size of a request body after decompression. Compressed bodies which expand
+/// beyond this size are rejected to protect against decompression bombs.
+pub(crate) const MAX_DECOMPRESSED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The error produced by the body stream when the decompressed body passes the limit. Reading
+/// the body turns this into `413 Payload Too Large`.
+#[derive(Debug)]
+pub(crate) struct DecompressedTooLarge(pub(crate) u64);
+
+impl std::fmt::Display for DecompressedTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "decompressed request body is larger than the limit of {} bytes",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for DecompressedTooLarge {}
+
+type DynBufRead = Box<dyn AsyncBufRead + Send + Unpin + 'static>;
+
+/// If the request has a `Content-Encoding` header, replace the body with a stream that
+/// decompresses it on the fly. The `Content-Encoding` and `Content-Length` headers are removed
+/// since they no longer describe the body seen by handlers.
+///
+/// The decompressed size is counted as chunks are produced, and the stream fails with
+/// [DecompressedTooLarge] once it passes `max`.
+///
+/// Returns `415 Unsupported Media Type` if any of the encodings are not understood.
+pub(crate) fn decode_request_body(req: &mut hyper::Request<Body>, max: u64) -> Result<()> {
+    let mut encodings = vec![];
+    for value in req.headers().get_all(CONTENT_ENCODING) {
+        for encoding in value.to_str()?.split(',') {
+            let encoding = encoding.trim().to_ascii_lowercase();
+            match encoding.as_str() {
+                "" | "identity" => {}
+                "gzip" | "x-gzip" | "deflate" | "br" => encodings.push(encoding),
+                other => {
+                    return Err(Error::http((
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        format!("unsupported content encoding: {}", other),
+                    )))
+                }
+            }
+        }
+    }
+
+    if encodings.is_empty() {
+        return Ok(());
+    }
+
+    debug!(?encodings, "decompressing request body");
+
+    let body = std::mem::take(req.body_mut());
+    let mut reader: DynBufRead = Box::new(StreamReader::new(body.map_err(io::Error::other)));
+
+    // encodings are listed in the order they were applied, so undo them in reverse
+    for encoding in encodings.iter().rev() {
+        reader = match encoding.as_str() {
+            "gzip" | "x-gzip" => Box::new(BufReader::new(GzipDecoder::new(reader))),
+            "deflate" => Box::new(BufReader::new(ZlibDecoder::new(reader))),
+            _ => Box::new(BufReader::new(BrotliDecoder::new(reader))),
+        };
+    }
+
+    let mut total = 0;
+    let stream = ReaderStream::new(reader).map(move |chunk| {
+        let chunk = chunk?;
+        total += chunk.len() as u64;
+        if total > max {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                DecompressedTooLarge(max),
+            ))
+        } else {
+            Ok(chunk)
+        }
+    });
+
+    *req.body_mut() = Body::wrap_stream(stream);
+    req.headers_mut().remove(CONTENT_ENCODING);
+    req.headers_mut().remove(CONTENT_LENGTH);
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use crate::endpoint::Endpoint;
+use crate::state::State;
+use crate::{files, Request, Response, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use headers::ETag;
+use hyper::StatusCode;
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::marker::PhantomData;
+use tracing::debug;
+
+/// A source of files held in memory, for serving with
+/// [Route::embedded_files](crate::Route::embedded_files).
+///
+/// This is implemented for `HashMap<&'static str, &'static [u8]>` (eg. built from
+/// `include_bytes!`), and with the `rust-embed` feature for any `rust_embed::RustEmbed` type
+/// using the [RustEmbedAssets] wrapper.
+pub trait Assets {
+    /// Get the contents of the file at `path`. The path is relative (it has no leading slash)
+    fn get(&self, path: &str) -> Option<Cow<'static, [u8]>>;
+
+    /// Get a hash of the file contents, which is used as the file's `ETag`. The default
+    /// implementation hashes `data` on every request, so override this if the hash is known
+    /// ahead of time.
+    fn hash(&self, _path: &str, data: &[u8]) -> String {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(data);
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+impl Assets for HashMap<&'static str, &'static [u8]> {
+    fn get(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        HashMap::get(self, path).map(|data| Cow::Borrowed(*data))
+    }
+}
+
+/// Serves assets from a type deriving `rust_embed::RustEmbed`.
+/// ```ignore
+/// #[derive(rust_embed::RustEmbed)]
+/// #[folder = "static/"]
+/// struct Static;
+///
+/// app.at("/static/*").embedded_files(RustEmbedAssets::<Static>::new());
+/// ```
+/// Only available with the `rust-embed` feature.
+#[cfg(feature = "rust-embed")]
+pub struct RustEmbedAssets<T>(PhantomData<fn() -> T>);
+
+#[cfg(feature = "rust-embed")]
+impl<T> RustEmbedAssets<T> {
+    /// Create the wrapper
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[cfg(feature = "rust-embed")]
+impl<T> Default for RustEmbedAssets<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "rust-embed")]
+impl<T: rust_embed::RustEmbed> Assets for RustEmbedAssets<T> {
+    fn get(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        T::get(path).map(|file| file.data)
+    }
+
+    fn hash(&self, path: &str, data: &[u8]) -> String {
+        // rust-embed computes the hash at compile time
+        match T::get(path) {
+            Some(file) => file
+                .metadata
+                .sha256_hash()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            None => format!("{:x}", data.len()),
+        }
+    }
+}
+
+/// Endpoint serving files from an [Assets] implementation
+pub(crate) struct EmbeddedFiles<A, S> {
+    assets: A,
+    prefix: String,
+    _phantom: PhantomData<S>,
+}
+
+impl<A, S> EmbeddedFiles<A, S> {
+    pub(crate) fn new(assets: A, prefix: &str) -> Self {
+        // remove the final wildcard path segment
+        let prefix = match prefix.rfind('/') {
+            Some(idx) => prefix[..idx].to_owned(),
+            None => String::new(),
+        };
+
+        Self {
+            assets,
+            prefix,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<A, S> Endpoint<S> for EmbeddedFiles<A, S>
+where
+    A: Assets + Send + Sync + 'static,
+    S: State,
+{
+    async fn call(&self, req: Request<S>) -> Result<Response> {
+        let raw = req
+            .uri()
+            .path()
+            .strip_prefix(&self.prefix)
+            .unwrap_or_default()
+            .trim_start_matches('/');
+
+        let path = match percent_decode_str(raw).decode_utf8() {
+            Ok(path) => path,
+            Err(_) => return Ok(Response::generated(StatusCode::BAD_REQUEST)),
+        };
+
+        let data = match self.assets.get(&path) {
+            Some(data) => data,
+            None => {
+                debug!("no embedded file {:?}", path);
+                return Ok(Response::generated(StatusCode::NOT_FOUND));
+            }
+        };
+
+        let etag: Option<ETag> = format!("\"{}\"", self.assets.hash(&path, &data))
+            .parse()
+            .ok();
+
+        let data = match data {
+            Cow::Borrowed(data) => Bytes::from_static(data),
+            Cow::Owned(data) => Bytes::from(data),
+        };
+
+        files::send_data(req.headers(), &path, data, etag)
+    }
+}
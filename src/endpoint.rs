@@ -1,10 +1,13 @@
+//! Exposes the `Endpoint` trait if you want to implement it for custom types.
+//!
+//! This is not usually necessary since it's implemented for function types already.
 use crate::state::State;
-/// Exposes the `Endpoint` trait if you want to implement it for custom types.
-///
-/// This is not usually necessary since it's implemented for function types already.
-use crate::{Request, Responder, Response, Result};
+use crate::{Error, Request, Responder, Response, Result};
 use async_trait::async_trait;
+use headers::Allow;
+use hyper::{Method, StatusCode};
 use std::future::Future;
+use std::sync::Arc;
 
 /// Implement `Endpoint` for a type to be used as a method handler.
 ///
@@ -43,3 +46,210 @@ where
         (self)(req).await.into_response()
     }
 }
+
+/// An endpoint wrapping a handler which returns `anyhow::Result`.
+/// Created with [anyhow_endpoint].
+pub struct AnyhowEndpoint<F> {
+    handler: F,
+}
+
+/// Wrap a handler returning `anyhow::Result` so it can be used as an endpoint. Errors are
+/// treated as internal errors (see [Error::Internal](crate::Error::Internal)).
+///
+/// (`Responder` is not implemented for `anyhow::Result` directly because closures returning
+/// `Ok(...)` would no longer be able to infer their error type).
+/// ```
+/// use highnoon::{anyhow_endpoint, App, Request};
+///
+/// async fn double(req: Request<()>) -> anyhow::Result<String> {
+///     let value: u32 = req.uri().query().unwrap_or_default().parse()?;
+///     Ok((value * 2).to_string())
+/// }
+///
+/// let mut app = App::new(());
+/// app.at("/double").get(anyhow_endpoint(double));
+/// ```
+pub fn anyhow_endpoint<F>(handler: F) -> AnyhowEndpoint<F> {
+    AnyhowEndpoint { handler }
+}
+
+#[async_trait]
+impl<S, F, Fut, R> Endpoint<S> for AnyhowEndpoint<F>
+where
+    F: Send + Sync + 'static + Fn(Request<S>) -> Fut,
+    Fut: Future<Output = anyhow::Result<R>> + Send + 'static,
+    R: Responder + 'static,
+    S: State,
+{
+    async fn call(&self, req: Request<S>) -> Result<Response> {
+        (self.handler)(req).await?.into_response()
+    }
+}
+
+/// An endpoint running a synchronous handler on the blocking thread pool.
+/// Created with [blocking].
+pub struct BlockingEndpoint<F> {
+    handler: Arc<F>,
+}
+
+/// Wrap a synchronous handler so it runs on tokio's blocking thread pool (with
+/// `spawn_blocking`) instead of the async runtime. Use this for handlers doing CPU heavy work
+/// or calling blocking APIs (eg. synchronous database drivers), which would otherwise stall
+/// other requests.
+/// ```
+/// use highnoon::{App, Request};
+/// use highnoon::endpoint::blocking;
+///
+/// let mut app = App::new(());
+/// app.at("/checksum").post(blocking(|mut req: Request<()>| {
+///     let handle = tokio::runtime::Handle::current();
+///     let body = handle.block_on(req.body_bytes())?;
+///     highnoon::Result::Ok(body.iter().map(|b| *b as u32).sum::<u32>().to_string())
+/// }));
+/// ```
+/// The handler receives the request, so the body can be read by blocking on the async body
+/// methods as above. If the handler panics the client gets `500 Internal Server Error`.
+/// Handlers run on the app's [spawner](crate::App::with_spawner) runtime if one is set.
+pub fn blocking<F>(handler: F) -> BlockingEndpoint<F> {
+    BlockingEndpoint {
+        handler: Arc::new(handler),
+    }
+}
+
+#[async_trait]
+impl<S, F, R> Endpoint<S> for BlockingEndpoint<F>
+where
+    F: Send + Sync + 'static + Fn(Request<S>) -> R,
+    R: Responder + 'static,
+    S: State,
+{
+    async fn call(&self, req: Request<S>) -> Result<Response> {
+        let handler = self.handler.clone();
+        req.app()
+            .spawner()
+            .spawn_blocking(move || (handler)(req).into_response())
+            .await
+            .map_err(|err| Error::Internal(err.into()))?
+    }
+}
+
+/// Endpoints can be shared by wrapping them in an `Arc`. This allows registering one endpoint
+/// instance on several routes, or storing endpoints as `Arc<dyn Endpoint<S> + Send + Sync>`.
+/// ```
+/// # use highnoon::{App, Endpoint, Request};
+/// # use std::sync::Arc;
+/// let mut app = App::new(());
+/// let hello: Arc<dyn Endpoint<()> + Send + Sync> = Arc::new(|_req: Request<()>| async { "hello" });
+/// app.at("/hello").get(hello.clone());
+/// app.at("/hi").get(hello);
+/// ```
+#[async_trait]
+impl<S, E> Endpoint<S> for Arc<E>
+where
+    S: State,
+    E: Endpoint<S> + Send + Sync + ?Sized,
+{
+    async fn call(&self, req: Request<S>) -> Result<Response> {
+        (**self).call(req).await
+    }
+}
+
+/// Start building an endpoint which dispatches to other endpoints by the request method. This
+/// is useful when implementing a resource as one endpoint, eg. to register it with
+/// [Route::all](crate::Route::all) or to [mount](crate::Route::mount) it somewhere else.
+/// ```
+/// # use highnoon::{App, Request};
+/// # use highnoon::endpoint::by_method;
+/// let users = by_method()
+///     .get(|_req: Request<()>| async { "list users" })
+///     .post(|_req: Request<()>| async { "create user" })
+///     .build();
+///
+/// let mut app = App::new(());
+/// app.at("/users").all(users);
+/// ```
+/// Requests with any other method get `405 Method Not Allowed`, with an `Allow` header listing
+/// the methods which are handled.
+pub fn by_method<S: State>() -> ByMethod<S> {
+    ByMethod {
+        endpoints: Vec::new(),
+    }
+}
+
+pub(crate) type DynEndpoint<S> = dyn Endpoint<S> + Send + Sync + 'static;
+
+/// Builder for a [MethodDispatch] endpoint, created with [by_method]
+pub struct ByMethod<S: State> {
+    endpoints: Vec<(Method, Box<DynEndpoint<S>>)>,
+}
+
+impl<S: State> ByMethod<S> {
+    /// Handle a specific HTTP method. Adding the same method again replaces the endpoint.
+    pub fn method(mut self, method: Method, ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
+        self.endpoints.retain(|(existing, _)| *existing != method);
+        self.endpoints.push((method, Box::new(ep)));
+        self
+    }
+
+    /// Handle the GET method
+    pub fn get(self, ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
+        self.method(Method::GET, ep)
+    }
+
+    /// Handle the POST method
+    pub fn post(self, ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
+        self.method(Method::POST, ep)
+    }
+
+    /// Handle the PUT method
+    pub fn put(self, ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
+        self.method(Method::PUT, ep)
+    }
+
+    /// Handle the DELETE method
+    pub fn delete(self, ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
+        self.method(Method::DELETE, ep)
+    }
+
+    /// Handle the PATCH method
+    pub fn patch(self, ep: impl Endpoint<S> + Send + Sync + 'static) -> Self {
+        self.method(Method::PATCH, ep)
+    }
+
+    /// Build the endpoint
+    pub fn build(self) -> MethodDispatch<S> {
+        let allow = self
+            .endpoints
+            .iter()
+            .map(|(method, _)| method.clone())
+            .collect();
+
+        MethodDispatch {
+            endpoints: self.endpoints,
+            allow,
+        }
+    }
+}
+
+/// An endpoint which dispatches to other endpoints by the request method. Build it with
+/// [by_method].
+pub struct MethodDispatch<S: State> {
+    endpoints: Vec<(Method, Box<DynEndpoint<S>>)>,
+    allow: Allow,
+}
+
+#[async_trait]
+impl<S: State> Endpoint<S> for MethodDispatch<S> {
+    async fn call(&self, req: Request<S>) -> Result<Response> {
+        match self
+            .endpoints
+            .iter()
+            .find(|(method, _)| method == req.method())
+        {
+            Some((_, ep)) => ep.call(req).await,
+            None => {
+                Ok(Response::generated(StatusCode::METHOD_NOT_ALLOWED).header(self.allow.clone()))
+            }
+        }
+    }
+}
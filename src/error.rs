@@ -1,5 +1,6 @@
 use crate::{Responder, Response, Result};
 use hyper::StatusCode;
+use serde::Serialize;
 use std::error::Error as StdError;
 use std::fmt::Formatter;
 
@@ -44,6 +45,25 @@ impl Error {
     pub fn bad_request(resp: impl Responder) -> Self {
         Self::http((StatusCode::BAD_REQUEST, resp))
     }
+
+    /// Create an Error with the given status code and a JSON body. This is useful for APIs which
+    /// return structured error details:
+    /// ```
+    /// # use highnoon::{Error, Request, Result, StatusCode};
+    /// # use serde_json::json;
+    /// async fn create_user(_: Request<()>) -> Result<()> {
+    ///     Err(Error::json(
+    ///         StatusCode::UNPROCESSABLE_ENTITY,
+    ///         json!({ "message": "invalid user", "fields": ["email"] }),
+    ///     ))
+    /// }
+    /// ```
+    pub fn json(status: StatusCode, body: impl Serialize) -> Self {
+        match Response::json_with_status(status, body) {
+            Ok(r) => Self::Http(r),
+            Err(e) => e,
+        }
+    }
 }
 
 impl Responder for Error {
@@ -52,7 +72,7 @@ impl Responder for Error {
             Error::Http(resp) => Ok(resp),
             Error::Internal(_err) => {
                 //log::error!("internal server error: {}", err);
-                Ok(Response::status(StatusCode::INTERNAL_SERVER_ERROR))
+                Ok(Response::generated(StatusCode::INTERNAL_SERVER_ERROR))
             }
         }
     }
@@ -0,0 +1,166 @@
+//! Extractors let an endpoint take typed arguments parsed from the request, instead of taking
+//! the [Request] itself. Wrap the handler with [extract] to use it as an endpoint:
+//! ```
+//! use highnoon::{App, Json};
+//! use highnoon::extract::{extract, Param};
+//! use serde_json::Value;
+//!
+//! let mut app = App::new(());
+//! app.at("/items/:id").put(extract(
+//!     |Param(id): Param<u32>, Json(item): Json<Value>| async move {
+//!         format!("updated item {}: {}", id, item)
+//!     },
+//! ));
+//! ```
+//! Arguments are extracted in order, and if any extractor fails its error is returned without
+//! calling the handler. Only one argument can read the request body.
+//!
+//! Implement [FromRequest] to write new extractors.
+use crate::app::PATH_REST_PARAM;
+use crate::state::State;
+use crate::{Error, Json, Request, Responder, Response, Result};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+/// A type which can be created from a request, for use as an argument of a handler wrapped
+/// with [extract].
+///
+/// Extractors which read the body take it from the request, so later extractors will see an
+/// empty body.
+#[async_trait]
+pub trait FromRequest<S: State>: Sized {
+    /// Extract the value, or return an error to respond with (usually `400 Bad Request`)
+    async fn from_request(req: &mut Request<S>) -> Result<Self>;
+}
+
+/// Extracts the whole request body as bytes
+pub struct Bytes(pub bytes::Bytes);
+
+#[async_trait]
+impl<S: State> FromRequest<S> for Bytes {
+    async fn from_request(req: &mut Request<S>) -> Result<Self> {
+        Ok(Bytes(req.body_contiguous().await?))
+    }
+}
+
+/// Extracts the whole request body as text. Returns `400 Bad Request` if it is not valid UTF-8.
+#[async_trait]
+impl<S: State> FromRequest<S> for String {
+    async fn from_request(req: &mut Request<S>) -> Result<Self> {
+        let bytes = req.body_contiguous().await?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| Error::bad_request("request body is not valid UTF-8"))
+    }
+}
+
+/// Extracts the request body as JSON, like [Request::body_json]
+#[async_trait]
+impl<S: State, T: DeserializeOwned + Send> FromRequest<S> for Json<T> {
+    async fn from_request(req: &mut Request<S>) -> Result<Self> {
+        Ok(Json(req.body_json().await?))
+    }
+}
+
+/// Extracts the raw query string, or `None` if the URI has no query
+pub struct RawQuery(pub Option<String>);
+
+#[async_trait]
+impl<S: State> FromRequest<S> for RawQuery {
+    async fn from_request(req: &mut Request<S>) -> Result<Self> {
+        Ok(RawQuery(req.uri().query().map(str::to_owned)))
+    }
+}
+
+/// Extracts the route's path parameter, parsed with `FromStr`. Returns `400 Bad Request` if it
+/// fails to parse.
+///
+/// The route must have exactly one parameter - use [Request::param] for routes with more.
+pub struct Param<T>(pub T);
+
+#[async_trait]
+impl<S: State, T: FromStr> FromRequest<S> for Param<T> {
+    async fn from_request(req: &mut Request<S>) -> Result<Self> {
+        // mounted apps add a parameter for the rest of the path, which isn't the route's
+        let mut params = req
+            .params()
+            .iter()
+            .filter(|(name, _)| *name != PATH_REST_PARAM);
+        let value = match (params.next(), params.next()) {
+            (Some((_, value)), None) => value,
+            _ => {
+                return Err(Error::Internal(anyhow::Error::msg(
+                    "Param can only be used on routes with exactly one parameter",
+                )))
+            }
+        };
+
+        let value = percent_encoding::percent_decode_str(value).decode_utf8_lossy();
+        value
+            .parse()
+            .map(Param)
+            .map_err(|_| Error::bad_request(format!("invalid path parameter: {}", value)))
+    }
+}
+
+/// Implemented for functions whose arguments are all [FromRequest] types, so they can be
+/// wrapped with [extract]. `Args` is a tuple of the argument types.
+#[async_trait]
+pub trait Handler<S: State, Args>: Send + Sync + 'static {
+    /// Extract the arguments and call the handler
+    async fn call(&self, req: Request<S>) -> Result<Response>;
+}
+
+macro_rules! impl_handler {
+    ($($arg:ident),+) => {
+        #[async_trait]
+        impl<S, F, Fut, R, $($arg),+> Handler<S, ($($arg,)+)> for F
+        where
+            S: State,
+            F: Fn($($arg),+) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = R> + Send + 'static,
+            R: Responder + 'static,
+            $($arg: FromRequest<S> + Send + 'static,)+
+        {
+            #[allow(non_snake_case)]
+            async fn call(&self, mut req: Request<S>) -> Result<Response> {
+                $(let $arg = $arg::from_request(&mut req).await?;)+
+                (self)($($arg),+).await.into_response()
+            }
+        }
+    };
+}
+
+impl_handler!(A);
+impl_handler!(A, B);
+impl_handler!(A, B, C);
+impl_handler!(A, B, C, D);
+impl_handler!(A, B, C, D, E);
+
+/// An endpoint wrapping a handler which takes extractors. Created with [extract].
+pub struct Extract<H, Args> {
+    handler: H,
+    _args: PhantomData<fn() -> Args>,
+}
+
+/// Wrap a handler taking up to five [FromRequest] arguments so it can be used as an endpoint
+pub fn extract<H, Args>(handler: H) -> Extract<H, Args> {
+    Extract {
+        handler,
+        _args: PhantomData,
+    }
+}
+
+#[async_trait]
+impl<S, H, Args> crate::Endpoint<S> for Extract<H, Args>
+where
+    S: State,
+    H: Handler<S, Args>,
+    Args: 'static,
+{
+    async fn call(&self, req: Request<S>) -> Result<Response> {
+        self.handler.call(req).await
+    }
+}
@@ -0,0 +1,233 @@
+/// File serving shared by [StaticFiles](crate::static_files::StaticFiles),
+/// [EmbeddedFiles](crate::embedded::EmbeddedFiles) and [Request::send_file](crate::Request::send_file).
+use crate::{Response, Result};
+#[cfg(feature = "static-files")]
+use bytes::Bytes;
+use headers::{
+    AcceptRanges, ContentLength, ContentRange, ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch,
+    IfRange, LastModified, Range,
+};
+use hyper::{HeaderMap, StatusCode};
+use std::io::{self, SeekFrom};
+use std::ops::Bound;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::debug;
+
+/// The part of a file requested by the `Range` header
+enum RangeRequest {
+    Full,
+    /// Inclusive start and end byte offsets
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Validators used for conditional requests
+struct Validators {
+    etag: Option<ETag>,
+    last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// Derive validators from a file's size and modification time
+    fn from_metadata(metadata: &std::fs::Metadata) -> Self {
+        let last_modified = metadata.modified().ok();
+
+        let etag = last_modified.and_then(|modified| {
+            let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+            format!(
+                "\"{:x}-{:x}.{:x}\"",
+                metadata.len(),
+                since_epoch.as_secs(),
+                since_epoch.subsec_nanos()
+            )
+            .parse()
+            .ok()
+        });
+
+        Self {
+            etag,
+            last_modified,
+        }
+    }
+
+    /// Check the request's conditional headers. If-None-Match takes precedence over
+    /// If-Modified-Since when both are present (RFC 7232 section 6).
+    fn not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = headers.typed_get::<IfNoneMatch>() {
+            return match &self.etag {
+                Some(etag) => !if_none_match.precondition_passes(etag),
+                None => false,
+            };
+        }
+
+        match (headers.typed_get::<IfModifiedSince>(), self.last_modified) {
+            (Some(since), Some(modified)) => !since.is_modified(modified),
+            _ => false,
+        }
+    }
+
+    /// Work out which part of the file to send. Multiple ranges are not supported, so those
+    /// requests get the full file (which RFC 7233 allows).
+    fn range(&self, headers: &HeaderMap, len: u64) -> RangeRequest {
+        let range = match headers.typed_get::<Range>() {
+            Some(range) => range,
+            None => return RangeRequest::Full,
+        };
+
+        // If-Range means only send part of the file if it hasn't changed
+        if let Some(if_range) = headers.typed_get::<IfRange>() {
+            let last_modified = self.last_modified.map(LastModified::from);
+            if if_range.is_modified(self.etag.as_ref(), last_modified.as_ref()) {
+                return RangeRequest::Full;
+            }
+        }
+
+        let mut ranges = range.iter();
+        let bounds = match (ranges.next(), ranges.next()) {
+            (Some(bounds), None) => bounds,
+            _ => return RangeRequest::Full,
+        };
+
+        if len == 0 {
+            return RangeRequest::Unsatisfiable;
+        }
+
+        let (start, end) = match bounds {
+            (Bound::Included(start), Bound::Included(end)) => (start, end.min(len - 1)),
+            (Bound::Included(start), Bound::Unbounded) => (start, len - 1),
+            // suffix range - the last N bytes of the file
+            (Bound::Unbounded, Bound::Included(suffix)) if suffix > 0 => {
+                (len.saturating_sub(suffix), len - 1)
+            }
+            _ => return RangeRequest::Unsatisfiable,
+        };
+
+        if start > end || start >= len {
+            RangeRequest::Unsatisfiable
+        } else {
+            RangeRequest::Partial(start, end)
+        }
+    }
+
+    fn apply(&self, mut resp: Response) -> Response {
+        if let Some(etag) = &self.etag {
+            resp.set_header(etag.clone());
+        }
+        if let Some(modified) = self.last_modified {
+            resp.set_header(LastModified::from(modified));
+        }
+        resp
+    }
+}
+
+/// How the body of a whole file response is produced
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) enum FileBody {
+    /// Read the file in chunks
+    #[default]
+    Read,
+    /// Memory map large files, see [StaticFilesOptions::mmap](crate::StaticFilesOptions::mmap)
+    #[cfg(all(feature = "mmap", feature = "static-files"))]
+    Mmap,
+}
+
+/// Respond with the contents of a file, honouring the conditional and range headers of the
+/// request.
+pub(crate) async fn send_file(
+    headers: &HeaderMap,
+    path: &Path,
+    file_body: FileBody,
+) -> Result<Response> {
+    let mut file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(Response::generated(StatusCode::NOT_FOUND))
+        }
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            return Ok(Response::generated(StatusCode::FORBIDDEN))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Ok(Response::generated(StatusCode::NOT_FOUND));
+    }
+
+    let validators = Validators::from_metadata(&metadata);
+
+    if validators.not_modified(headers) {
+        debug!("file {:?} not modified", path);
+        return Ok(validators.apply(Response::status(StatusCode::NOT_MODIFIED)));
+    }
+
+    let len = metadata.len();
+    let resp = validators
+        .apply(Response::ok())
+        .header(AcceptRanges::bytes());
+
+    match validators.range(headers, len) {
+        RangeRequest::Full => {
+            let resp = resp.header(ContentLength(len));
+            match file_body {
+                FileBody::Read => resp.file(file, path).await,
+                #[cfg(all(feature = "mmap", feature = "static-files"))]
+                FileBody::Mmap => resp.file_mmap(file, path).await,
+            }
+        }
+        RangeRequest::Partial(start, end) => {
+            debug!("sending bytes {}-{} of file {:?}", start, end, path);
+            file.seek(SeekFrom::Start(start)).await?;
+            let mut resp = resp
+                .guess_content_type(path)
+                .header(ContentRange::bytes(start..=end, len)?)
+                .header(ContentLength(end - start + 1))
+                .reader(file.take(end - start + 1));
+            resp.set_status(StatusCode::PARTIAL_CONTENT);
+            Ok(resp)
+        }
+        RangeRequest::Unsatisfiable => Ok(Response::status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(ContentRange::unsatisfied_bytes(len))),
+    }
+}
+
+/// Respond with file contents held in memory, honouring the conditional and range headers of
+/// the request. `path` is only used to guess the content type.
+#[cfg(feature = "static-files")]
+pub(crate) fn send_data(
+    headers: &HeaderMap,
+    path: &str,
+    data: Bytes,
+    etag: Option<ETag>,
+) -> Result<Response> {
+    let validators = Validators {
+        etag,
+        last_modified: None,
+    };
+
+    if validators.not_modified(headers) {
+        debug!("data for {} not modified", path);
+        return Ok(validators.apply(Response::status(StatusCode::NOT_MODIFIED)));
+    }
+
+    let len = data.len() as u64;
+    let resp = validators
+        .apply(Response::ok())
+        .header(AcceptRanges::bytes())
+        .guess_content_type(Path::new(path));
+
+    match validators.range(headers, len) {
+        RangeRequest::Full => Ok(resp.header(ContentLength(len)).body(data)),
+        RangeRequest::Partial(start, end) => {
+            let mut resp = resp
+                .header(ContentRange::bytes(start..=end, len)?)
+                .header(ContentLength(end - start + 1))
+                .body(data.slice(start as usize..=end as usize));
+            resp.set_status(StatusCode::PARTIAL_CONTENT);
+            Ok(resp)
+        }
+        RangeRequest::Unsatisfiable => Ok(Response::status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(ContentRange::unsatisfied_bytes(len))),
+    }
+}
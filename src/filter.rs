@@ -6,14 +6,30 @@ use crate::{Request, Response, Result, State};
 use async_trait::async_trait;
 use std::future::Future;
 
+mod audit;
+mod cors;
 mod log;
+mod rate_limit;
+#[cfg(feature = "sessions")]
 pub mod session; // TODO - export the needed bits of this
+#[cfg(feature = "signature")]
+mod signature;
+pub(crate) mod timeout;
 
-pub use self::log::Log;
+pub use self::audit::{AuditEntry, AuditLog};
+pub use self::cors::Cors;
+pub use self::log::{Log, LogBodies};
+pub use self::rate_limit::RateLimit;
+#[cfg(feature = "signature")]
+pub use self::signature::{SignatureAlgorithm, VerifySignature};
+pub use self::timeout::Timeout;
 
 /// Represents either the next Filter in the chain, or the actual endpoint if the chain is
 /// empty or completed. Use its `next` method to call the next filter/endpoint if the
 /// request should continue to be processed.
+///
+/// `Next` is `Copy`, so a filter can call it more than once, eg. to retry a request made with
+/// [Request::try_clone](crate::Request::try_clone).
 pub struct Next<'a, S>
 where
     S: Send + Sync + 'static,
@@ -22,6 +38,14 @@ where
     pub(crate) rest: &'a [Box<dyn Filter<S> + Send + Sync + 'static>],
 }
 
+impl<S: State> Clone for Next<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S: State> Copy for Next<'_, S> {}
+
 impl<S: State> Next<'_, S> {
     /// Call either the next filter in the chain, or the actual endpoint if there are no more
     /// filters. Filters are not required to call next (eg. to return a Forbidden status instead)
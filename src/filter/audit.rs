@@ -0,0 +1,124 @@
+use crate::filter::{Filter, Next};
+use crate::state::State;
+use crate::{Error, Request, Response, Result};
+use async_trait::async_trait;
+use headers::HeaderMapExt;
+use hyper::body::HttpBody;
+use hyper::{Method, StatusCode};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// A structured record of a completed request, produced by the [AuditLog] filter
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    /// Address of the remote peer
+    pub remote_addr: SocketAddr,
+    /// Address of the client, which differs from the peer behind a trusted proxy (see
+    /// [Request::real_ip])
+    pub real_ip: IpAddr,
+    /// The authenticated user, as determined by the callback given to [AuditLog::with_user]
+    pub user: Option<String>,
+    /// Request method
+    pub method: Method,
+    /// Request path (without the query string)
+    pub path: String,
+    /// Response status code
+    pub status: StatusCode,
+    /// Size of the response body, if known ahead of time
+    pub bytes: Option<u64>,
+    /// Time taken to produce the response
+    pub duration: Duration,
+    /// The `User-Agent` request header
+    pub user_agent: Option<String>,
+    /// The `Referer` request header
+    pub referer: Option<String>,
+}
+
+type DynAuditCallback = dyn Fn(AuditEntry) + Send + Sync + 'static;
+type DynUserCallback<S> = dyn Fn(&Request<S>) -> Option<String> + Send + Sync + 'static;
+
+/// A filter which builds an [AuditEntry] for every request and passes it to a callback.
+///
+/// Unlike the [Log](crate::filter::Log) filter this is intended for structured sinks
+/// (e.g. a database or SIEM). The callback is called synchronously after the response is
+/// produced, so it should hand the entry off (e.g. over a channel) rather than block.
+pub struct AuditLog<S: State> {
+    callback: Box<DynAuditCallback>,
+    user: Option<Box<DynUserCallback<S>>>,
+}
+
+impl<S: State> AuditLog<S> {
+    /// Create an audit log filter which passes each entry to `callback`
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(AuditEntry) + Send + Sync + 'static,
+    {
+        Self {
+            callback: Box::new(callback),
+            user: None,
+        }
+    }
+
+    /// Set a callback to determine the authenticated user from the request (typically from the
+    /// Context). This is called before the request is passed down the chain, so the audit log
+    /// filter must be registered *after* any filter which authenticates the user.
+    pub fn with_user<F>(mut self, user: F) -> Self
+    where
+        F: Fn(&Request<S>) -> Option<String> + Send + Sync + 'static,
+    {
+        self.user = Some(Box::new(user));
+        self
+    }
+}
+
+fn response_size(resp: &Response) -> Option<u64> {
+    let resp = resp.as_ref();
+    resp.body().size_hint().exact().or_else(|| {
+        resp.headers()
+            .typed_get::<headers::ContentLength>()
+            .map(|cl| cl.0)
+    })
+}
+
+#[async_trait]
+impl<S: State> Filter<S> for AuditLog<S> {
+    async fn apply(&self, req: Request<S>, next: Next<'_, S>) -> Result<Response> {
+        let start = Instant::now();
+
+        let remote_addr = *req.remote_addr();
+        let real_ip = req.real_ip();
+        let user = self.user.as_ref().and_then(|user| user(&req));
+        let method = req.method().clone();
+        let path = req.uri().path().to_owned();
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_owned())
+        };
+        let user_agent = header(hyper::header::USER_AGENT);
+        let referer = header(hyper::header::REFERER);
+
+        let result = next.next(req).await;
+
+        let (status, bytes) = match &result {
+            Ok(resp) | Err(Error::Http(resp)) => (resp.get_status(), response_size(resp)),
+            Err(Error::Internal(_)) => (StatusCode::INTERNAL_SERVER_ERROR, None),
+        };
+
+        (self.callback)(AuditEntry {
+            remote_addr,
+            real_ip,
+            user,
+            method,
+            path,
+            status,
+            bytes,
+            duration: start.elapsed(),
+            user_agent,
+            referer,
+        });
+
+        result
+    }
+}
@@ -0,0 +1,198 @@
+use crate::filter::{Filter, Next};
+use crate::state::State;
+use crate::{Error, Request, Response, Result};
+use async_trait::async_trait;
+use hyper::header::{
+    HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
+    ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+};
+use hyper::{Method, StatusCode};
+use std::time::Duration;
+use tracing::debug;
+
+/// Which origins may make cross-origin requests
+enum Origins {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+/// A filter implementing Cross-Origin Resource Sharing (CORS), so browsers allow scripts on
+/// other origins to call the app.
+///
+/// Preflight requests (an `OPTIONS` request with `Access-Control-Request-Method`) are answered
+/// with `204 No Content` without calling the rest of the chain. Other requests from an allowed
+/// origin get the CORS headers added to their response (including error responses). Requests
+/// from other origins pass through without CORS headers, so the browser blocks them.
+/// ```
+/// use highnoon::filter::Cors;
+/// use highnoon::{App, Method};
+///
+/// let mut app = App::new(());
+/// app.with(
+///     Cors::new()
+///         .allow_origin("https://example.com")
+///         .allow_methods([Method::GET, Method::POST])
+///         .allow_credentials(true),
+/// );
+/// ```
+pub struct Cors {
+    origins: Origins,
+    methods: HeaderValue,
+    headers: Option<HeaderValue>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cors {
+    /// Create a CORS filter which allows no origins, and the `GET`, `HEAD` and `POST` methods
+    pub fn new() -> Self {
+        Self {
+            origins: Origins::List(vec![]),
+            methods: HeaderValue::from_static("GET, HEAD, POST"),
+            headers: None,
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allow requests from `origin` (eg. `https://example.com`). Call this once for each
+    /// allowed origin.
+    ///
+    /// Panics if `origin` is not a valid header value.
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        let origin = HeaderValue::from_str(origin).expect("invalid origin");
+        match &mut self.origins {
+            Origins::List(origins) => origins.push(origin),
+            Origins::Any => self.origins = Origins::List(vec![origin]),
+        }
+        self
+    }
+
+    /// Allow requests from any origin
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = Origins::Any;
+        self
+    }
+
+    /// Set the methods cross-origin requests may use
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        let methods: Vec<_> = methods.into_iter().map(|m| m.to_string()).collect();
+        self.methods = HeaderValue::from_str(&methods.join(", ")).expect("invalid method");
+        self
+    }
+
+    /// Set the request headers cross-origin requests may send (beyond the ones browsers
+    /// always allow, like `Accept`)
+    pub fn allow_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        let headers: Vec<_> = headers.into_iter().map(|h| h.to_string()).collect();
+        self.headers = Some(HeaderValue::from_str(&headers.join(", ")).expect("invalid header"));
+        self
+    }
+
+    /// Allow cross-origin requests to include credentials (cookies and HTTP authentication).
+    /// Browsers don't accept a wildcard origin for these, so the request's origin is always
+    /// sent back instead.
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.credentials = allow;
+        self
+    }
+
+    /// Set how long browsers may cache the result of a preflight request
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// The `Access-Control-Allow-Origin` value for a request from `origin`, or `None` if the
+    /// origin is not allowed
+    fn allowed_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        match &self.origins {
+            Origins::Any if !self.credentials => Some(HeaderValue::from_static("*")),
+            Origins::Any => Some(origin.clone()),
+            Origins::List(origins) if origins.contains(origin) => Some(origin.clone()),
+            Origins::List(_) => None,
+        }
+    }
+
+    /// Whether responses depend on the request's origin, which is the case unless any origin
+    /// gets the wildcard
+    fn varies_by_origin(&self) -> bool {
+        matches!(self.origins, Origins::List(_)) || self.credentials
+    }
+
+    /// Add the CORS headers if the origin is allowed, and `Vary: Origin` if the response
+    /// depends on the origin - even when no CORS headers were added, otherwise a cache could
+    /// reuse this response for an origin which should get different headers.
+    fn add_headers(&self, resp: &mut Response, allow_origin: Option<HeaderValue>) {
+        if let Some(allow_origin) = allow_origin {
+            let headers = resp.headers_mut();
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+            if self.credentials {
+                headers.insert(
+                    ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                    HeaderValue::from_static("true"),
+                );
+            }
+        }
+        if self.varies_by_origin() {
+            resp.add_vary(ORIGIN);
+        }
+    }
+
+    fn preflight(&self, allow_origin: HeaderValue) -> Response {
+        let mut resp = Response::status(StatusCode::NO_CONTENT);
+        self.add_headers(&mut resp, Some(allow_origin));
+        let headers = resp.headers_mut();
+        headers.insert(ACCESS_CONTROL_ALLOW_METHODS, self.methods.clone());
+        if let Some(allow_headers) = &self.headers {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allow_headers.clone());
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+        }
+        resp
+    }
+}
+
+#[async_trait]
+impl<S: State> Filter<S> for Cors {
+    async fn apply(&self, req: Request<S>, next: Next<'_, S>) -> Result<Response> {
+        let allow_origin = match req.headers().get(ORIGIN) {
+            Some(origin) => {
+                let allowed = self.allowed_origin(origin);
+                if allowed.is_none() {
+                    debug!(?origin, "origin not allowed by CORS");
+                }
+                allowed
+            }
+            None => None,
+        };
+
+        let is_preflight = req.method() == Method::OPTIONS
+            && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+        if is_preflight {
+            if let Some(allow_origin) = allow_origin {
+                return Ok(self.preflight(allow_origin));
+            }
+        }
+
+        match next.next(req).await {
+            Ok(mut resp) => {
+                self.add_headers(&mut resp, allow_origin);
+                Ok(resp)
+            }
+            Err(Error::Http(mut resp)) => {
+                self.add_headers(&mut resp, allow_origin);
+                Err(Error::Http(resp))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -1,22 +1,75 @@
 use crate::filter::{Filter, Next};
 use crate::{Error, Request, Response, Result};
 use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures_util::stream::{self, StreamExt};
+use headers::{ContentLength, ContentType, HeaderMapExt};
+use hyper::body::HttpBody;
+use hyper::{Body, HeaderMap};
+use mime::Mime;
 
 use crate::state::State;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, trace, warn, Level};
 
 /// A logging filter. Logs all requests at debug level, and logs responses at error, warn or info
-/// level depending on the status code (5xx, 4xx, and everything else)
+/// level depending on the status code (5xx, 4xx, and everything else).
+///
+/// Responses are logged with the request's `Content-Length` (as `request_size`) and the size of
+/// the response body (as `response_size`). Either is left out when the size isn't known up
+/// front, eg. for streaming bodies.
 pub struct Log;
 
-fn log_response(method: String, uri: String, resp: &Response) {
+impl Log {
+    /// Create a logging filter which also logs up to `max_len` bytes of the request and response
+    /// bodies at trace level. This is intended for debugging APIs.
+    ///
+    /// Bodies are only captured in debug builds when trace logging is enabled, and only for
+    /// textual content types - multipart and binary bodies are never logged. Reading the body is
+    /// capped at `max_len` bytes so streaming bodies are not buffered in full.
+    pub fn with_bodies(max_len: usize) -> LogBodies {
+        LogBodies { max_len }
+    }
+}
+
+/// A logging filter which also logs request and response bodies, created with [Log::with_bodies]
+pub struct LogBodies {
+    max_len: usize,
+}
+
+/// The size of a body from its `Content-Length` header, or failing that the body's exact size
+/// hint (which is known for bodies created from a buffer)
+fn body_size(headers: &HeaderMap, body: &Body) -> Option<u64> {
+    headers
+        .typed_get::<ContentLength>()
+        .map(|len| len.0)
+        .or_else(|| body.size_hint().exact())
+}
+
+fn log_response(method: &str, uri: &str, request_size: Option<u64>, resp: &Response) {
     let status = resp.as_ref().status();
+    let response_size = body_size(resp.as_ref().headers(), resp.as_ref().body());
     if status.is_server_error() {
-        error!(%method, %uri, %status, "response");
+        error!(%method, %uri, %status, request_size, response_size, "response");
     } else if status.is_client_error() {
-        warn!(%method, %uri, %status, "response");
+        warn!(%method, %uri, %status, request_size, response_size, "response");
     } else {
-        info!(%method, %uri, %status, "response");
+        info!(%method, %uri, %status, request_size, response_size, "response");
+    }
+}
+
+fn log_result(method: &str, uri: &str, request_size: Option<u64>, result: &Result<Response>) {
+    match result {
+        Ok(resp) => log_response(method, uri, request_size, resp),
+        Err(Error::Http(resp)) => log_response(method, uri, request_size, resp),
+        Err(Error::Internal(err)) => {
+            error!(%method,
+                %uri,
+                request_size,
+                error=%err,
+                backtrace=?err,
+               "internal server error"
+            );
+        }
     }
 }
 
@@ -25,24 +78,122 @@ impl<S: State> Filter<S> for Log {
     async fn apply(&self, req: Request<S>, next: Next<'_, S>) -> Result<Response> {
         let method = req.method().to_string();
         let uri = req.uri().to_string();
+        let request_size = req.header::<ContentLength>().map(|len| len.0);
 
         debug!(%method, %uri, "request");
 
         let result = next.next(req).await;
+        log_result(&method, &uri, request_size, &result);
+        result
+    }
+}
 
-        match &result {
-            Ok(resp) => log_response(method, uri, resp),
-            Err(Error::Http(resp)) => log_response(method, uri, resp),
-            Err(Error::Internal(err)) => {
-                error!(%method,
-                    %uri,
-                    error=%err,
-                    backtrace=?err,
-                   "internal server error"
-                );
-            }
+#[async_trait]
+impl<S: State> Filter<S> for LogBodies {
+    async fn apply(&self, mut req: Request<S>, next: Next<'_, S>) -> Result<Response> {
+        let method = req.method().to_string();
+        let uri = req.uri().to_string();
+        let request_size = req.header::<ContentLength>().map(|len| len.0);
+
+        debug!(%method, %uri, "request");
+
+        let capture = cfg!(debug_assertions) && tracing::enabled!(Level::TRACE);
+
+        if capture && is_text(req.headers()) {
+            // take the body directly so the handler is still considered not to have read it
+            let body = std::mem::take(req.as_inner_mut().body_mut());
+            let (captured, truncated, body) = capture_body(body, self.max_len).await;
+            *req.as_inner_mut().body_mut() = body;
+            trace!(%method, %uri, body=%String::from_utf8_lossy(&captured), truncated, "request body");
+        }
+
+        let mut result = next.next(req).await;
+        log_result(&method, &uri, request_size, &result);
+
+        if let Ok(resp) = result {
+            let resp = if capture && is_text(resp.as_ref().headers()) && !has_trailers(&resp) {
+                let (parts, body) = resp.into_inner().into_parts();
+                let (captured, truncated, body) = capture_body(body, self.max_len).await;
+                trace!(%method, %uri, body=%String::from_utf8_lossy(&captured), truncated, "response body");
+                Response::from(hyper::Response::from_parts(parts, body))
+            } else {
+                resp
+            };
+            result = Ok(resp);
         }
 
         result
     }
 }
+
+/// Check if the content type is one we can log. Anything not known to be text is skipped,
+/// along with event streams which never end.
+fn is_text(headers: &HeaderMap) -> bool {
+    let mime: Mime = match headers.typed_get::<ContentType>() {
+        Some(content_type) => content_type.into(),
+        None => return false,
+    };
+
+    match (mime.type_(), mime.subtype()) {
+        (mime::TEXT, mime::EVENT_STREAM) => false,
+        (mime::TEXT, _) => true,
+        (
+            mime::APPLICATION,
+            mime::JSON | mime::XML | mime::JAVASCRIPT | mime::WWW_FORM_URLENCODED,
+        ) => true,
+        (mime::APPLICATION, _) => matches!(mime.suffix(), Some(mime::JSON | mime::XML)),
+        _ => false,
+    }
+}
+
+/// Bodies with trailers can't be put back together, so they are not captured
+fn has_trailers(resp: &Response) -> bool {
+    resp.as_ref().headers().contains_key(hyper::header::TRAILER)
+}
+
+/// Read up to `max_len` bytes from the start of a body. Returns the captured bytes, whether the
+/// body was truncated, and a new body which replays the captured chunks followed by the rest
+/// of the original body.
+async fn capture_body(mut body: Body, max_len: usize) -> (Bytes, bool, Body) {
+    let mut chunks = Vec::new();
+    let mut len = 0;
+    let mut complete = false;
+
+    while len < max_len {
+        match body.data().await {
+            Some(Ok(chunk)) => {
+                len += chunk.len();
+                chunks.push(Ok(chunk));
+            }
+            Some(Err(err)) => {
+                // replay the error to the reader
+                chunks.push(Err(err));
+                break;
+            }
+            None => {
+                complete = true;
+                break;
+            }
+        }
+    }
+
+    let mut captured = BytesMut::with_capacity(len.min(max_len));
+    for chunk in chunks.iter().flatten() {
+        let remaining = max_len - captured.len();
+        captured.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+    }
+    let truncated = !complete || len > max_len;
+
+    let body = if complete {
+        // the whole body was read, so send it as one buffer (keeping the exact size hint)
+        let mut all = BytesMut::with_capacity(len);
+        for chunk in chunks.into_iter().flatten() {
+            all.extend_from_slice(&chunk);
+        }
+        Body::from(all.freeze())
+    } else {
+        Body::wrap_stream(stream::iter(chunks).chain(body))
+    };
+
+    (captured.freeze(), truncated, body)
+}
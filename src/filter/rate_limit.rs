@@ -0,0 +1,140 @@
+use crate::filter::{Filter, Next};
+use crate::state::State;
+use crate::{Request, Response, Result};
+use async_trait::async_trait;
+use headers::RetryAfter;
+use hyper::StatusCode;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+type KeyFn<S> = dyn Fn(&Request<S>) -> String + Send + Sync + 'static;
+
+/// A client's token bucket
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    last_sweep: Instant,
+}
+
+/// A filter which limits the rate of requests from each client using a token bucket.
+///
+/// Each client starts with `capacity` tokens, each request takes one, and tokens are refilled
+/// at `refill_per_second` up to the capacity. Once a client runs out, requests get a
+/// `429 Too Many Requests` response with a `Retry-After` header until a token is available.
+///
+/// Clients are identified by [Request::real_ip], so behind a proxy make sure it is trusted
+/// with [App::trust_proxies](crate::App::trust_proxies). Use [RateLimit::with_key_fn] to key
+/// by something else (eg. an API token header).
+/// ```
+/// use highnoon::filter::RateLimit;
+/// use highnoon::App;
+///
+/// let mut app = App::new(());
+/// app.with(RateLimit::new(10, 1.0).with_key_fn(|req| {
+///     req.headers()
+///         .get("x-api-token")
+///         .and_then(|token| token.to_str().ok())
+///         .unwrap_or_default()
+///         .to_owned()
+/// }));
+/// ```
+pub struct RateLimit<S: State> {
+    capacity: f64,
+    refill_per_second: f64,
+    key: Box<KeyFn<S>>,
+    buckets: Mutex<Buckets>,
+}
+
+impl<S: State> RateLimit<S> {
+    /// Create a rate limit allowing bursts of `capacity` requests, refilled at
+    /// `refill_per_second` requests per second.
+    ///
+    /// Panics if `refill_per_second` is not positive.
+    pub fn new(capacity: u32, refill_per_second: f64) -> Self {
+        assert!(
+            refill_per_second > 0.0,
+            "rate limit refill rate must be positive"
+        );
+        Self {
+            capacity: capacity as f64,
+            refill_per_second,
+            key: Box::new(|req| req.real_ip().to_string()),
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+
+    /// Set the function used to work out which client a request is from
+    pub fn with_key_fn(
+        mut self,
+        key: impl Fn(&Request<S>) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.key = Box::new(key);
+        self
+    }
+
+    /// How long an empty bucket takes to fill
+    fn fill_time(&self) -> Duration {
+        Duration::from_secs_f64(self.capacity / self.refill_per_second)
+    }
+
+    /// Take a token from the client's bucket, or return how long until one is available
+    fn take(&self, key: String) -> std::result::Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limit lock poisoned");
+
+        // a full bucket is the same as no bucket, so drop those now and again to bound the
+        // size of the map
+        if now.duration_since(buckets.last_sweep) >= self.fill_time() {
+            let (capacity, rate) = (self.capacity, self.refill_per_second);
+            buckets.buckets.retain(|_, bucket| {
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens + elapsed * rate < capacity
+            });
+            buckets.last_sweep = now;
+        }
+
+        let bucket = buckets.buckets.entry(key).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_per_second,
+            ))
+        }
+    }
+}
+
+#[async_trait]
+impl<S: State> Filter<S> for RateLimit<S> {
+    async fn apply(&self, req: Request<S>, next: Next<'_, S>) -> Result<Response> {
+        let key = (self.key)(&req);
+        match self.take(key) {
+            Ok(()) => next.next(req).await,
+            Err(wait) => {
+                debug!(real_ip = %req.real_ip(), "rate limit exceeded");
+                // Retry-After is in whole seconds, so round up
+                let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                Ok(Response::generated(StatusCode::TOO_MANY_REQUESTS)
+                    .header(RetryAfter::delay(Duration::from_secs(secs))))
+            }
+        }
+    }
+}
@@ -1,36 +1,48 @@
 use crate::filter::{Filter, Next};
-use crate::{Request, Response, Result};
+use crate::{Error, Request, Response, Result};
 
 use crate::state::State;
 use async_trait::async_trait;
-use cookie::Cookie;
-use headers::{Header, SetCookie};
+use cookie::{Cookie, CookieJar, Key};
+use hyper::header::{HeaderValue, SET_COOKIE};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
-use tokio::sync::Mutex as AsyncMutex;
-use tracing::debug;
+use tracing::{debug, warn};
 use uuid::Uuid;
 
+#[cfg(feature = "redis")]
+mod redis_store;
+
+pub use cookie::SameSite;
+#[cfg(feature = "redis")]
+pub use redis_store::RedisSessionStore;
+
 /// Trait for session storage
+///
+/// The methods take `&self` and are called concurrently for different requests, so stores
+/// need interior mutability (eg. a `Mutex`, or a connection pool).
 #[async_trait]
 pub trait SessionStore {
     /// Get the data associated with session
     async fn get(&self, id: &str) -> Result<Option<String>>;
     /// Set the data for a session
-    async fn set(&mut self, id: String, value: String) -> Result<()>;
+    async fn set(&self, id: String, value: String) -> Result<()>;
     /// Clear data for a session
-    async fn clear(&mut self, id: &str) -> Result<()>;
+    async fn clear(&self, id: &str) -> Result<()>;
 }
 
 /// Memory backed implementation of session storage.
 /// NOTE this is only meant for demos and examples. In a real server
-/// you would store sessions externally (e.g. in redis or a database)
+/// you would store sessions externally (e.g. in redis or a database, see `RedisSessionStore`
+/// with the `redis` feature)
 #[derive(Default)]
 pub struct MemorySessionStore {
-    data: HashMap<String, String>,
+    data: Mutex<HashMap<String, String>>,
 }
 
 impl MemorySessionStore {
@@ -44,24 +56,72 @@ impl MemorySessionStore {
 impl SessionStore for MemorySessionStore {
     async fn get(&self, id: &str) -> Result<Option<String>> {
         debug!(id, "memory store get");
-        Ok(self.data.get(id).cloned())
+        Ok(self.data.lock().unwrap().get(id).cloned())
     }
 
-    async fn set(&mut self, id: String, value: String) -> Result<()> {
+    async fn set(&self, id: String, value: String) -> Result<()> {
         debug!(%id, %value, "memory store set");
-        self.data.insert(id, value);
+        self.data.lock().unwrap().insert(id, value);
         Ok(())
     }
 
-    async fn clear(&mut self, id: &str) -> Result<()> {
+    async fn clear(&self, id: &str) -> Result<()> {
         debug!(id, "memory store clear");
-        self.data.remove(id);
+        self.data.lock().unwrap().remove(id);
         Ok(())
     }
 }
 
+/// Trait for the format used to serialize session data before it is sent to the store
+pub trait SessionFormat {
+    /// Encode the session data
+    fn encode(&self, data: &HashMap<String, String>) -> Result<String>;
+    /// Decode session data previously encoded by this format
+    fn decode(&self, raw: &str) -> Result<HashMap<String, String>>;
+}
+
+/// Store session data as a JSON object (this is the default format)
+#[derive(Default)]
+pub struct JsonFormat;
+
+impl SessionFormat for JsonFormat {
+    fn encode(&self, data: &HashMap<String, String>) -> Result<String> {
+        Ok(serde_json::to_string(data)?)
+    }
+
+    fn decode(&self, raw: &str) -> Result<HashMap<String, String>> {
+        Ok(serde_json::from_str(raw)?)
+    }
+}
+
+/// Store session data URL encoded (eg. `key=value&other=value`). This was the format used
+/// before [JsonFormat] became the default.
+#[derive(Default)]
+pub struct UrlEncodedFormat;
+
+impl SessionFormat for UrlEncodedFormat {
+    fn encode(&self, data: &HashMap<String, String>) -> Result<String> {
+        Ok(serde_urlencoded::to_string(data)?)
+    }
+
+    fn decode(&self, raw: &str) -> Result<HashMap<String, String>> {
+        Ok(serde_urlencoded::from_str(raw)?)
+    }
+}
+
 pub const DEFAULT_COOKIE_NAME: &str = "sid";
 
+/// Browsers ignore cookies bigger than this (including the name and attributes)
+const MAX_COOKIE_SIZE: usize = 4096;
+
+/// Where the session data is kept
+enum Storage {
+    /// In a store, keyed by the session ID held in the cookie
+    Store(Box<dyn SessionStore + Send + Sync + 'static>),
+    /// Encrypted in the cookie itself
+    Cookie(Key),
+}
+
 type DynCookieCallback = dyn Fn(&mut Cookie) + Send + Sync + 'static;
 
 /// A filter for implementing basic session support
@@ -69,20 +129,56 @@ type DynCookieCallback = dyn Fn(&mut Cookie) + Send + Sync + 'static;
 /// This filter requires that the Context implements HasSession
 pub struct SessionFilter {
     cookie_name: Cow<'static, str>,
+    session_name: Option<Cow<'static, str>>,
     expiry: time::Duration,
+    secure: bool,
+    http_only: bool,
+    same_site: SameSite,
+    path: Option<Cow<'static, str>>,
+    domain: Option<Cow<'static, str>>,
     cookie_callback: Option<Box<DynCookieCallback>>,
-    store: AsyncMutex<Box<dyn SessionStore + Send + Sync + 'static>>,
+    format: Box<dyn SessionFormat + Send + Sync + 'static>,
+    storage: Storage,
 }
 
 impl SessionFilter {
     /// Create a new session filter using the provided store
-    /// The default cookie name is [DEFAULT_COOKIE_NAME] and expiry is set to one hour
+    /// The default cookie name is [DEFAULT_COOKIE_NAME] and expiry is set to one hour.
+    /// Session data is stored as JSON by default.
+    /// The cookie is `Secure`, `HttpOnly` and `SameSite=Strict` by default.
     pub fn new(store: impl SessionStore + Send + Sync + 'static) -> SessionFilter {
+        Self::with_storage(Storage::Store(Box::new(store)))
+    }
+
+    /// Create a new session filter which keeps the session data in the cookie itself, rather
+    /// than in a store on the server. The data is encrypted and authenticated with `key`, so the
+    /// client can neither read nor tamper with it. The key must be cryptographically random and
+    /// at least 64 bytes long, otherwise an error is returned.
+    ///
+    /// The defaults are the same as [SessionFilter::new]. The time the session was last saved is
+    /// encrypted along with the data, and cookies older than the expiry are ignored, so a copied
+    /// cookie stops working once it expires even if the client keeps sending it.
+    ///
+    /// Browsers limit cookies to 4KB, so requests which store more data than fits return an
+    /// internal error. There is also no way to revoke a session before it expires, since the
+    /// server keeps no record of it.
+    pub fn new_encrypted_cookie(key: &[u8]) -> Result<SessionFilter> {
+        Ok(Self::with_storage(Storage::Cookie(Key::try_from(key)?)))
+    }
+
+    fn with_storage(storage: Storage) -> SessionFilter {
         SessionFilter {
             cookie_name: Cow::Borrowed(DEFAULT_COOKIE_NAME),
+            session_name: None,
             expiry: time::Duration::hours(1),
+            secure: true,
+            http_only: true,
+            same_site: SameSite::Strict,
+            path: None,
+            domain: None,
             cookie_callback: None,
-            store: AsyncMutex::new(Box::new(store)),
+            format: Box::new(JsonFormat),
+            storage,
         }
     }
 
@@ -92,12 +188,66 @@ impl SessionFilter {
         self
     }
 
-    /// Set the expiry time set on the session ID cookie
+    /// Use a named session rather than the default session. The filter will load and save the
+    /// session returned by [HasSession::session_named], so the Context must implement that
+    /// method (usually by storing a [Sessions] map).
+    /// This allows installing several session filters in one App (e.g. a user session and an
+    /// admin session) - each filter must also be given a distinct cookie name.
+    pub fn with_name(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.session_name = Some(name.into());
+        self
+    }
+
+    /// Set the expiry time set on the session ID cookie. Encrypted cookie sessions also reject
+    /// cookies older than this.
     pub fn with_expiry(mut self, expiry: time::Duration) -> Self {
         self.expiry = expiry;
         self
     }
 
+    /// Set the `Secure` attribute of the session ID cookie (default `true`).
+    /// Browsers will not send secure cookies over plain HTTP, so this may need to be
+    /// disabled during development.
+    pub fn with_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Set the `HttpOnly` attribute of the session ID cookie (default `true`)
+    pub fn with_http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Set the `SameSite` attribute of the session ID cookie (default `Strict`)
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Set the `Path` attribute of the session ID cookie. If not set the browser defaults to
+    /// the path of the request which created the session, which is usually wrong for sessions
+    /// shared across an app (e.g. use `/` to share the session with all paths)
+    pub fn with_path(mut self, path: impl Into<Cow<'static, str>>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Set the `Domain` attribute of the session ID cookie (e.g. `example.com` to share the
+    /// session with all subdomains). If not set the cookie is only sent to the exact host.
+    pub fn with_domain(mut self, domain: impl Into<Cow<'static, str>>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Set the format used to serialize session data for the store (default [JsonFormat]).
+    /// If stored data can't be decoded (eg. after changing the format) the session starts
+    /// out empty instead of failing the request.
+    pub fn with_format(mut self, format: impl SessionFormat + Send + Sync + 'static) -> Self {
+        self.format = Box::new(format);
+        self
+    }
+
     /// Set a callback function to be used to customise the session ID cookie.
     /// The callback is called with the cookie before it is stored in the headers so you can change
     /// most settings (changing the name or value of the cookie may prevent sessions from working,
@@ -111,12 +261,67 @@ impl SessionFilter {
     }
 }
 
+impl SessionFilter {
+    /// Encrypt session data into a cookie value, along with the time it was issued
+    fn encrypt(&self, key: &Key, raw_data: String) -> String {
+        let name = self.cookie_name.to_string();
+        let issued = time::OffsetDateTime::now_utc().unix_timestamp();
+        let mut jar = CookieJar::new();
+        jar.private_mut(key).add(Cookie::new(
+            name.clone(),
+            format!("{}:{}", issued, raw_data),
+        ));
+        jar.get(&name)
+            .expect("encrypted cookie missing from jar")
+            .value()
+            .to_owned()
+    }
+
+    /// Decrypt session data from a cookie value. Returns `None` if it has been tampered with (or
+    /// was encrypted with another key), or was issued longer ago than the expiry.
+    fn decrypt(&self, key: &Key, value: &str) -> Option<String> {
+        let cookie = Cookie::new(self.cookie_name.to_string(), value.to_owned());
+        let cookie = match CookieJar::new().private(key).decrypt(cookie) {
+            Some(cookie) => cookie,
+            None => {
+                warn!("failed to decrypt session cookie, ignoring it");
+                return None;
+            }
+        };
+
+        let issued_and_data = cookie
+            .value()
+            .split_once(':')
+            .and_then(|(issued, raw_data)| Some((issued.parse::<i64>().ok()?, raw_data)));
+        let (issued, raw_data) = match issued_and_data {
+            Some(parsed) => parsed,
+            None => {
+                warn!("session cookie has no issue time, ignoring it");
+                return None;
+            }
+        };
+
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        if time::Duration::seconds(now - issued) > self.expiry {
+            debug!("session cookie has expired, ignoring it");
+            return None;
+        }
+
+        Some(raw_data.to_owned())
+    }
+}
+
 #[derive(Default)]
 struct SessionInner {
     modified: AtomicBool,
     data: Mutex<HashMap<String, String>>,
+    /// Keys of flash values loaded from storage, which are removed at the end of the request
+    stale_flashes: Mutex<HashSet<String>>,
 }
 
+/// Prefix added to the keys of flash values in the session data
+const FLASH_PREFIX: &str = "_flash:";
+
 /// A session
 #[derive(Default)]
 pub struct Session {
@@ -136,11 +341,48 @@ impl SessionInner {
         self.modified.store(true, Ordering::Relaxed);
     }
 
+    fn flash(&self, key: &str, value: String) {
+        let key = format!("{}{}", FLASH_PREFIX, key);
+        self.stale_flashes.lock().unwrap().remove(&key);
+        self.set(key, value);
+    }
+
+    fn take_flash(&self, key: &str) -> Option<String> {
+        let key = format!("{}{}", FLASH_PREFIX, key);
+        self.stale_flashes.lock().unwrap().remove(&key);
+        let value = self.data.lock().unwrap().remove(&key);
+        if value.is_some() {
+            debug!(%key, "session flash taken");
+            self.modified.store(true, Ordering::Relaxed);
+        }
+        value
+    }
+
+    /// Remove flash values which were loaded with the session but not read or replaced
+    fn remove_stale_flashes(&self) {
+        let stale = std::mem::take(&mut *self.stale_flashes.lock().unwrap());
+        if stale.is_empty() {
+            return;
+        }
+
+        let mut data = self.data.lock().unwrap();
+        for key in stale {
+            debug!(%key, "session flash expired");
+            data.remove(&key);
+        }
+        self.modified.store(true, Ordering::Relaxed);
+    }
+
     fn is_modified(&self) -> bool {
         self.modified.load(Ordering::Relaxed)
     }
 
     fn load(&self, data: HashMap<String, String>) {
+        *self.stale_flashes.lock().unwrap() = data
+            .keys()
+            .filter(|key| key.starts_with(FLASH_PREFIX))
+            .cloned()
+            .collect();
         *self.data.lock().unwrap() = data;
 
         // we just loaded fresh data into the session, so clear modified flag to
@@ -160,17 +402,91 @@ impl Session {
         self.inner.set(key, value)
     }
 
+    /// Store a flash value: a one-time message (eg. "item saved") for the next request, usually
+    /// set before redirecting. Read it with [Session::take_flash].
+    ///
+    /// Flash values survive one redirect but not two - if the next request doesn't take the
+    /// value it is removed anyway when that request completes.
+    pub fn flash(&self, key: &str, value: String) {
+        self.inner.flash(key, value)
+    }
+
+    /// Take a flash value stored by [Session::flash], removing it from the session
+    pub fn take_flash(&self, key: &str) -> Option<String> {
+        self.inner.take_flash(key)
+    }
+
+    /// Get a typed value from the session. The value is stored as JSON (see [Session::set_as]).
+    /// Returns an error if the stored value can't be decoded as a `T`.
+    pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.inner.get(key) {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a typed value into the session, encoded as JSON. Note this means strings stored
+    /// with this method are quoted, so read them back with [Session::get_as], not [Session::get].
+    pub fn set_as<T: Serialize + ?Sized>(&self, key: String, value: &T) -> Result<()> {
+        self.inner.set(key, serde_json::to_string(value)?);
+        Ok(())
+    }
+
     /// Determine if the session has been modified
     pub fn is_modified(&self) -> bool {
         self.inner.is_modified()
     }
 }
 
+/// A collection of sessions for Contexts that need more than one session. This holds the
+/// default session and any number of named sessions (created on first access).
+#[derive(Default)]
+pub struct Sessions {
+    default: Session,
+    named: HashMap<String, Session>,
+}
+
+impl Sessions {
+    /// Get the default session
+    pub fn session(&mut self) -> &mut Session {
+        &mut self.default
+    }
+
+    /// Get a named session
+    pub fn named(&mut self, name: &str) -> &mut Session {
+        self.named.entry(name.to_owned()).or_default()
+    }
+}
+
 /// This trait must be implemented by the Context type in order to use the
 /// SessionFilter
 pub trait HasSession {
     /// Get a reference to the Session for this current request
     fn session(&mut self) -> &mut Session;
+
+    /// Get a reference to a named Session for this current request. This is only needed when
+    /// using [SessionFilter::with_name], and the default implementation returns `None`, which
+    /// makes the filter fail the request with an internal error. Contexts supporting named
+    /// sessions can delegate to a [Sessions] map:
+    /// ```
+    /// # use highnoon::filter::session::{HasSession, Session, Sessions};
+    /// struct Context {
+    ///     sessions: Sessions,
+    /// }
+    ///
+    /// impl HasSession for Context {
+    ///     fn session(&mut self) -> &mut Session {
+    ///         self.sessions.session()
+    ///     }
+    ///
+    ///     fn session_named(&mut self, name: &str) -> Option<&mut Session> {
+    ///         Some(self.sessions.named(name))
+    ///     }
+    /// }
+    /// ```
+    fn session_named(&mut self, _name: &str) -> Option<&mut Session> {
+        None
+    }
 }
 
 /// Implement HasSession on requests where the Context has sessions
@@ -182,6 +498,10 @@ where
     fn session(&mut self) -> &mut Session {
         self.context_mut().session()
     }
+
+    fn session_named(&mut self, name: &str) -> Option<&mut Session> {
+        self.context_mut().session_named(name)
+    }
 }
 
 #[async_trait]
@@ -191,41 +511,77 @@ where
     S::Context: HasSession,
 {
     async fn apply(&self, mut req: Request<S>, next: Next<'_, S>) -> Result<Response> {
-        let session = Arc::clone(&req.session().inner);
+        let session = match self.session_name {
+            None => Arc::clone(&req.session().inner),
+            Some(ref name) => match req.session_named(name) {
+                Some(session) => Arc::clone(&session.inner),
+                None => {
+                    return Err(Error::Internal(anyhow::anyhow!(
+                    "named session {} requested but HasSession::session_named is not implemented",
+                    name
+                )))
+                }
+            },
+        };
 
-        let maybe_sid = req
+        let cookie_value = req
             .cookies()?
             .get(self.cookie_name.as_ref())
             .map(|c| c.value().to_owned());
 
-        let sid = if let Some(sid) = maybe_sid {
-            debug!(%sid, "request has session cookie");
-
-            let store = self.store.lock().await;
-            let raw_data = store.get(&sid).await?.unwrap_or_default();
-            let data = serde_urlencoded::from_str(&raw_data)?;
-            session.load(data);
-            sid
-        } else {
-            debug!("request has no session cookie");
-            Uuid::new_v4().to_string()
+        let raw_data = match (&self.storage, &cookie_value) {
+            (Storage::Store(store), Some(sid)) => {
+                debug!(%sid, "request has session cookie");
+                store.get(sid).await?
+            }
+            (Storage::Cookie(key), Some(value)) => {
+                debug!("request has session cookie");
+                self.decrypt(key, value)
+            }
+            (_, None) => {
+                debug!("request has no session cookie");
+                None
+            }
         };
 
+        if let Some(raw_data) = raw_data {
+            match self.format.decode(&raw_data) {
+                Ok(data) => session.load(data),
+                Err(err) => warn!("failed to decode session data, ignoring it: {}", err),
+            }
+        }
+
         let mut resp = next.next(req).await?;
 
+        session.remove_stale_flashes();
         if session.is_modified() {
             debug!("session was modified");
 
-            let mut store = self.store.lock().await;
             let raw_data = {
                 let data = session.data.lock().unwrap();
-                serde_urlencoded::to_string(&*data)?
+                self.format.encode(&data)?
             };
 
-            let mut cookie = Cookie::new(self.cookie_name.as_ref(), &sid);
-            cookie.set_http_only(true);
-            cookie.set_secure(true);
-            cookie.set_same_site(cookie::SameSite::Strict);
+            let value = match &self.storage {
+                Storage::Store(store) => {
+                    let sid = cookie_value.unwrap_or_else(|| Uuid::new_v4().to_string());
+                    store.set(sid.clone(), raw_data).await?;
+                    sid
+                }
+                Storage::Cookie(key) => self.encrypt(key, raw_data),
+            };
+
+            let mut cookie = Cookie::new(self.cookie_name.as_ref(), value);
+            cookie.set_http_only(self.http_only);
+            cookie.set_secure(self.secure);
+            cookie.set_same_site(self.same_site);
+
+            if let Some(ref path) = self.path {
+                cookie.set_path(path.as_ref());
+            }
+            if let Some(ref domain) = self.domain {
+                cookie.set_domain(domain.as_ref());
+            }
 
             let expiry = time::OffsetDateTime::now_utc() + self.expiry;
             cookie.set_expires(expiry);
@@ -234,9 +590,18 @@ where
                 callback(&mut cookie);
             }
 
-            resp.set_raw_header(SetCookie::name(), cookie.to_string())?;
+            let cookie = cookie.to_string();
+            if cookie.len() > MAX_COOKIE_SIZE {
+                return Err(Error::Internal(anyhow::anyhow!(
+                    "session cookie is {} bytes, which is over the {} byte limit - store less data in the session",
+                    cookie.len(),
+                    MAX_COOKIE_SIZE
+                )));
+            }
 
-            store.set(sid, raw_data).await?;
+            // append, so cookies from other session filters and the endpoint are kept
+            resp.headers_mut()
+                .append(SET_COOKIE, HeaderValue::try_from(cookie)?);
         }
 
         Ok(resp)
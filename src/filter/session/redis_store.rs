@@ -0,0 +1,93 @@
+use super::SessionStore;
+use crate::Result;
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::borrow::Cow;
+use std::time::Duration;
+use tracing::debug;
+
+/// Redis backed session storage. (Only available with the `redis` feature)
+///
+/// Each session is stored under the key prefix (default `session:`) followed by the session
+/// ID, and expires after the TTL. Set the TTL to match the
+/// [SessionFilter](super::SessionFilter) expiry (both default to one hour) so the data lives as
+/// long as the cookie.
+/// ```no_run
+/// use highnoon::filter::session::{RedisSessionStore, SessionFilter};
+/// use std::time::Duration;
+///
+/// # async fn run() -> highnoon::Result<()> {
+/// let store = RedisSessionStore::connect("redis://127.0.0.1/")
+///     .await?
+///     .with_ttl(Duration::from_secs(8 * 3600));
+/// let filter = SessionFilter::new(store).with_expiry(time::Duration::hours(8));
+/// # Ok(())
+/// # }
+/// ```
+pub struct RedisSessionStore {
+    conn: ConnectionManager,
+    prefix: Cow<'static, str>,
+    ttl: Duration,
+}
+
+impl RedisSessionStore {
+    /// Connect to Redis at `url` (eg. `redis://127.0.0.1/`)
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url)?;
+        Ok(Self::from_connection(ConnectionManager::new(client).await?))
+    }
+
+    /// Use an existing connection. The connection manager multiplexes requests and reconnects
+    /// when the connection drops, so it can be shared with the rest of the app.
+    pub fn from_connection(conn: ConnectionManager) -> Self {
+        Self {
+            conn,
+            prefix: Cow::Borrowed("session:"),
+            ttl: Duration::from_secs(3600),
+        }
+    }
+
+    /// Set the prefix added to session IDs to form the Redis key
+    pub fn with_prefix(mut self, prefix: impl Into<Cow<'static, str>>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set how long session data is kept after it was last saved
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn key(&self, id: &str) -> String {
+        format!("{}{}", self.prefix, id)
+    }
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn get(&self, id: &str) -> Result<Option<String>> {
+        debug!(id, "redis store get");
+        let mut conn = self.conn.clone();
+        Ok(conn.get(self.key(id)).await?)
+    }
+
+    async fn set(&self, id: String, value: String) -> Result<()> {
+        debug!(%id, "redis store set");
+        // SETEX needs a whole number of seconds, and rejects zero
+        let ttl = self.ttl.as_secs().max(1) as usize;
+        let key = self.key(&id);
+        let mut conn = self.conn.clone();
+        conn.set_ex::<_, _, ()>(key, value, ttl).await?;
+        Ok(())
+    }
+
+    async fn clear(&self, id: &str) -> Result<()> {
+        debug!(id, "redis store clear");
+        let key = self.key(id);
+        let mut conn = self.conn.clone();
+        conn.del::<_, ()>(key).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use crate::filter::{Filter, Next};
+use crate::state::State;
+use crate::{Request, Response, Result};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use hyper::header::HeaderName;
+use hyper::StatusCode;
+use sha2::{Sha256, Sha512};
+use tracing::warn;
+
+/// The hash function used for the HMAC checked by [VerifySignature]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    /// HMAC-SHA256, sent as `sha256=<hex>` (eg. GitHub's `X-Hub-Signature-256`)
+    Sha256,
+    /// HMAC-SHA512, sent as `sha512=<hex>`
+    Sha512,
+}
+
+impl SignatureAlgorithm {
+    /// The prefix used for this algorithm in the signature header
+    fn name(self) -> &'static str {
+        match self {
+            SignatureAlgorithm::Sha256 => "sha256",
+            SignatureAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// Check the signature of `body` in constant time
+    fn verify(self, secret: &[u8], body: &[u8], signature: &[u8]) -> bool {
+        match self {
+            SignatureAlgorithm::Sha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret)
+                    .expect("HMAC accepts keys of any length");
+                mac.update(body);
+                mac.verify_slice(signature).is_ok()
+            }
+            SignatureAlgorithm::Sha512 => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(secret)
+                    .expect("HMAC accepts keys of any length");
+                mac.update(body);
+                mac.verify_slice(signature).is_ok()
+            }
+        }
+    }
+}
+
+/// A filter which checks an HMAC signature of the request body, as sent by webhook providers.
+/// Requests with a missing or wrong signature get `401 Unauthorized` without reaching the
+/// endpoint.
+///
+/// The signature header holds the hex encoded HMAC, optionally prefixed by the algorithm name
+/// (eg. `sha256=4f2a...`). The body is buffered to check it (see
+/// [Request::buffer_body](crate::Request::buffer_body)), so the endpoint can still read it.
+///
+/// (Only available with the `signature` feature)
+/// ```
+/// use highnoon::App;
+/// use highnoon::filter::{SignatureAlgorithm, VerifySignature};
+///
+/// let mut app = App::new(());
+/// app.with(VerifySignature::new(
+///     "webhook secret",
+///     "x-hub-signature-256",
+///     SignatureAlgorithm::Sha256,
+/// ));
+/// ```
+pub struct VerifySignature {
+    secret: Vec<u8>,
+    header: HeaderName,
+    algorithm: SignatureAlgorithm,
+    max_body_size: usize,
+}
+
+impl VerifySignature {
+    /// Create a filter checking the signature in `header`, made with `secret`.
+    ///
+    /// Panics if `header` is not a valid header name.
+    pub fn new(secret: impl Into<Vec<u8>>, header: &str, algorithm: SignatureAlgorithm) -> Self {
+        Self {
+            secret: secret.into(),
+            header: HeaderName::from_bytes(header.as_bytes()).expect("invalid header name"),
+            algorithm,
+            max_body_size: 1024 * 1024,
+        }
+    }
+
+    /// Set the largest body which will be buffered to check its signature (the default is 1MiB).
+    /// Larger bodies get `413 Payload Too Large`.
+    pub fn max_body_size(mut self, max: usize) -> Self {
+        self.max_body_size = max;
+        self
+    }
+
+    /// Get the signature bytes from the header value
+    fn parse_signature(&self, value: &str) -> Option<Vec<u8>> {
+        let hex = match value.split_once('=') {
+            Some((name, hex)) if name.eq_ignore_ascii_case(self.algorithm.name()) => hex,
+            Some(_) => return None,
+            None => value,
+        };
+        decode_hex(hex.trim())
+    }
+}
+
+/// Decode a hex string, returning `None` if it is malformed (including an odd length)
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[async_trait]
+impl<S: State> Filter<S> for VerifySignature {
+    async fn apply(&self, mut req: Request<S>, next: Next<'_, S>) -> Result<Response> {
+        let signature = req
+            .headers()
+            .get(&self.header)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| self.parse_signature(value));
+
+        let signature = match signature {
+            Some(signature) => signature,
+            None => {
+                warn!(header = %self.header, "request signature is missing or malformed");
+                return Ok(Response::generated(StatusCode::UNAUTHORIZED));
+            }
+        };
+
+        let body = req.buffer_body_limited(self.max_body_size).await?;
+        if !self.algorithm.verify(&self.secret, &body, &signature) {
+            warn!(header = %self.header, "request signature does not match");
+            return Ok(Response::generated(StatusCode::UNAUTHORIZED));
+        }
+
+        next.next(req).await
+    }
+}
@@ -0,0 +1,75 @@
+use crate::filter::{Filter, Next};
+use crate::state::State;
+use crate::{Request, Response, Result};
+use async_trait::async_trait;
+use hyper::StatusCode;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// The deadline for the current request, stored in the request extensions
+#[derive(Clone, Copy)]
+pub(crate) struct Deadline(pub(crate) Instant);
+
+/// A per-route override of the timeout, set with [Route::timeout](crate::Route::timeout) or
+/// [Route::no_timeout](crate::Route::no_timeout) and stored in the request extensions
+#[derive(Clone, Copy)]
+pub(crate) enum TimeoutOverride {
+    Duration(Duration),
+    Disabled,
+}
+
+/// A filter which limits the time taken to process a request. If the rest of the chain does not
+/// complete in time it is cancelled and a `503 Service Unavailable` is returned instead.
+///
+/// The deadline is available to handlers via [Request::deadline] so they can pass the remaining
+/// time on to other operations (e.g. a database query).
+///
+/// Routes which legitimately take longer can override the duration with
+/// [Route::timeout](crate::Route::timeout), or opt out with
+/// [Route::no_timeout](crate::Route::no_timeout).
+///
+/// Upgrade requests (eg. websockets) are passed through without a deadline, since the upgraded
+/// connection outlives the request.
+pub struct Timeout {
+    duration: Duration,
+}
+
+impl Timeout {
+    /// Create a new timeout filter
+    pub fn new(duration: Duration) -> Self {
+        Self { duration }
+    }
+}
+
+#[async_trait]
+impl<S: State> Filter<S> for Timeout {
+    async fn apply(&self, mut req: Request<S>, next: Next<'_, S>) -> Result<Response> {
+        if req.is_upgrade() {
+            return next.next(req).await;
+        }
+
+        let duration = match req.extensions().get::<TimeoutOverride>() {
+            Some(TimeoutOverride::Duration(duration)) => *duration,
+            Some(TimeoutOverride::Disabled) => return next.next(req).await,
+            None => self.duration,
+        };
+
+        let mut deadline = Instant::now() + duration;
+        // keep an earlier deadline set by an outer filter
+        if let Some(existing) = req.deadline() {
+            deadline = deadline.min(existing);
+        }
+        req.extensions_mut().insert(Deadline(deadline));
+
+        let method = req.method().clone();
+        let uri = req.uri().clone();
+
+        match tokio::time::timeout_at(deadline.into(), next.next(req)).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!(%method, %uri, "request timed out");
+                Ok(Response::generated(StatusCode::SERVICE_UNAVAILABLE))
+            }
+        }
+    }
+}
@@ -1,27 +1,63 @@
+pub use bytes::{Buf, Bytes};
+pub use cookie;
 pub use headers;
 pub use hyper::{Method, StatusCode};
 pub use mime::Mime;
+#[cfg(feature = "tls")]
+pub use tokio_rustls::rustls;
+#[cfg(feature = "websocket")]
 pub use tokio_tungstenite::tungstenite::Message;
 
 mod app;
-mod endpoint;
+#[cfg(feature = "client")]
+mod client;
+mod cookies;
+#[cfg(feature = "compression")]
+mod decompress;
+#[cfg(feature = "static-files")]
+mod embedded;
+pub mod endpoint;
 mod error;
+pub mod extract;
+mod files;
 pub mod filter;
+#[cfg(feature = "multipart")]
+pub mod multipart;
+mod proxy;
 mod request;
 mod responder;
 mod response;
-mod router;
+pub mod router;
+pub mod sse;
 mod state;
+#[cfg(feature = "static-files")]
 mod static_files;
+#[cfg(feature = "test-client")]
 mod test_client;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "websocket")]
 pub mod ws;
 
-pub use app::{App, Route};
-pub use endpoint::Endpoint;
+pub use app::{App, GracefulServer, Route};
+#[cfg(feature = "client")]
+pub use client::HttpClient;
+pub use cookies::SignedCookies;
+#[cfg(feature = "static-files")]
+pub use embedded::Assets;
+#[cfg(feature = "rust-embed")]
+pub use embedded::RustEmbedAssets;
+pub use endpoint::{anyhow_endpoint, AnyhowEndpoint, Endpoint};
 pub use error::Error;
+pub use proxy::{ForwardedHeader, TrustedProxy};
 pub use request::Request;
-pub use responder::{Form, Json, Responder};
-pub use response::Response;
+pub use responder::{CanonicalJson, Form, Json, JsonArrayStream, Responder};
+pub use response::{BodySender, CacheControl, Response};
+pub use router::TrailingSlash;
 pub use state::State;
+#[cfg(feature = "static-files")]
+pub use static_files::StaticFilesOptions;
+#[cfg(feature = "tls")]
+pub use tls::{tls_config_from_pem, CertificateInfo};
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,220 @@
+//! Parsing of `multipart/form-data` request bodies (eg. file uploads).
+//!
+//! Fields are read one at a time as the body arrives, so uploaded files can be streamed to disk
+//! without buffering them in memory:
+//! ```
+//! use highnoon::{Request, Result};
+//!
+//! async fn upload(mut req: Request<()>) -> Result<String> {
+//!     let mut multipart = req.multipart()?;
+//!     let mut saved = vec![];
+//!
+//!     while let Some(field) = multipart.next_field().await? {
+//!         if field.name() == Some("file") {
+//!             let path = format!("/tmp/upload-{}", saved.len());
+//!             let info = field.save_to(&path).await?;
+//!             saved.push(format!("{:?} ({} bytes)", info.file_name, info.size));
+//!         }
+//!     }
+//!
+//!     Ok(saved.join(", "))
+//! }
+//! ```
+//!
+//! Only available with the `multipart` feature.
+use crate::{Error, Response, Result};
+use bytes::Bytes;
+use futures_util::stream::{Stream, StreamExt};
+use hyper::{Body, HeaderMap, StatusCode};
+use mime::Mime;
+use std::path::Path;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::warn;
+
+/// Limits applied while parsing a multipart body, given to
+/// [Request::multipart_with](crate::Request::multipart_with). Exceeding any limit returns
+/// `413 Payload Too Large`.
+///
+/// All limits are disabled by default, so set them for any endpoint open to the public:
+/// ```
+/// # use highnoon::{Request, Result};
+/// # use highnoon::multipart::MultipartLimits;
+/// async fn upload(mut req: Request<()>) -> Result<()> {
+///     let mut multipart = req.multipart_with(MultipartLimits {
+///         max_fields: Some(10),
+///         max_field_size: Some(10 * 1024 * 1024),
+///         ..MultipartLimits::default()
+///     })?;
+///     // ...
+/// #   Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MultipartLimits {
+    /// The maximum number of fields (including files)
+    pub max_fields: Option<usize>,
+    /// The maximum size of any single field, in bytes
+    pub max_field_size: Option<u64>,
+    /// The maximum size of the whole body, in bytes
+    pub max_total_size: Option<u64>,
+}
+
+/// A `multipart/form-data` request body, created with
+/// [Request::multipart](crate::Request::multipart)
+pub struct Multipart {
+    inner: multer::Multipart<'static>,
+    max_fields: Option<usize>,
+    fields: usize,
+}
+
+impl Multipart {
+    pub(crate) fn new(body: Body, boundary: String, limits: MultipartLimits) -> Self {
+        let mut size_limit = multer::SizeLimit::new();
+        if let Some(limit) = limits.max_field_size {
+            size_limit = size_limit.per_field(limit);
+        }
+        if let Some(limit) = limits.max_total_size {
+            size_limit = size_limit.whole_stream(limit);
+        }
+        let constraints = multer::Constraints::new().size_limit(size_limit);
+
+        Self {
+            inner: multer::Multipart::with_constraints(body, boundary, constraints),
+            max_fields: limits.max_fields,
+            fields: 0,
+        }
+    }
+
+    /// Get the next field of the body, or `None` once all fields have been read.
+    ///
+    /// Each field must be read (or dropped) before asking for the next one.
+    pub async fn next_field(&mut self) -> Result<Option<Field>> {
+        let field = match self.inner.next_field().await.map_err(multipart_error)? {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+
+        self.fields += 1;
+        if let Some(max_fields) = self.max_fields {
+            if self.fields > max_fields {
+                let msg = format!("too many multipart fields (limit is {})", max_fields);
+                return Err(Error::Http(
+                    Response::generated(StatusCode::PAYLOAD_TOO_LARGE).body(msg),
+                ));
+            }
+        }
+
+        Ok(Some(Field { inner: field }))
+    }
+}
+
+/// A single field of a multipart body. The field data is not read until requested, using
+/// one of the methods below.
+pub struct Field {
+    inner: multer::Field<'static>,
+}
+
+impl Field {
+    /// The name of the field (from the `Content-Disposition` header)
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// The file name given by the client for file fields (from the `Content-Disposition`
+    /// header). This is untrusted, so don't use it as a path without sanitising it.
+    pub fn file_name(&self) -> Option<&str> {
+        self.inner.file_name()
+    }
+
+    /// The content type of the field, if the client sent one
+    pub fn content_type(&self) -> Option<&Mime> {
+        self.inner.content_type()
+    }
+
+    /// All headers sent for this field
+    pub fn headers(&self) -> &HeaderMap {
+        self.inner.headers()
+    }
+
+    /// Read the next chunk of the field data, or `None` at the end of the field
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>> {
+        self.inner.chunk().await.map_err(multipart_error)
+    }
+
+    /// Convert the field into a stream of chunks
+    pub fn stream(self) -> impl Stream<Item = Result<Bytes>> + Send {
+        self.inner.map(|chunk| chunk.map_err(multipart_error))
+    }
+
+    /// Read the whole field into memory. Avoid this for file uploads, which may be large.
+    pub async fn bytes(self) -> Result<Bytes> {
+        self.inner.bytes().await.map_err(multipart_error)
+    }
+
+    /// Read the whole field into memory as text
+    pub async fn text(self) -> Result<String> {
+        self.inner.text().await.map_err(multipart_error)
+    }
+
+    /// Write the field data to `writer` as it arrives, without buffering the whole field.
+    pub async fn write_to<W>(mut self, mut writer: W) -> Result<UploadInfo>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut info = UploadInfo {
+            file_name: self.file_name().map(str::to_owned),
+            content_type: self.content_type().cloned(),
+            size: 0,
+        };
+
+        while let Some(chunk) = self.chunk().await? {
+            writer.write_all(&chunk).await?;
+            info.size += chunk.len() as u64;
+        }
+        writer.flush().await?;
+
+        Ok(info)
+    }
+
+    /// Save the field data to a file at `path`, creating or truncating it. The partial file is
+    /// removed if the upload fails.
+    pub async fn save_to(self, path: impl AsRef<Path>) -> Result<UploadInfo> {
+        let path = path.as_ref();
+        let file = tokio::fs::File::create(path).await?;
+
+        let result = self.write_to(file).await;
+        if result.is_err() {
+            if let Err(err) = tokio::fs::remove_file(path).await {
+                warn!("failed to remove partial upload {:?}: {}", path, err);
+            }
+        }
+        result
+    }
+}
+
+/// Details of a field written with [Field::write_to] or [Field::save_to]
+#[derive(Debug, Clone)]
+pub struct UploadInfo {
+    /// The file name given by the client (untrusted)
+    pub file_name: Option<String>,
+    /// The content type given by the client
+    pub content_type: Option<Mime>,
+    /// The number of bytes written
+    pub size: u64,
+}
+
+/// Convert a multipart parsing error into an `Error`. Malformed bodies are the client's fault
+/// and return `400 Bad Request`.
+fn multipart_error(err: multer::Error) -> Error {
+    match err {
+        multer::Error::FieldSizeExceeded { .. } | multer::Error::StreamSizeExceeded { .. } => {
+            Error::Http(Response::generated(StatusCode::PAYLOAD_TOO_LARGE).body(err.to_string()))
+        }
+        // size limits on the whole stream are reported as a read failure
+        multer::Error::StreamReadFailed(cause) => match cause.downcast::<multer::Error>() {
+            Ok(inner) => multipart_error(*inner),
+            Err(cause) => Error::Internal(multer::Error::StreamReadFailed(cause).into()),
+        },
+        err => Error::bad_request(err.to_string()),
+    }
+}
@@ -0,0 +1,201 @@
+/// Finding the client's address from the forwarding headers added by trusted proxies, for
+/// [Request::real_ip](crate::Request::real_ip).
+use hyper::header::{HeaderName, FORWARDED};
+use hyper::HeaderMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+static X_REAL_IP: HeaderName = HeaderName::from_static("x-real-ip");
+
+/// An address or network (in CIDR notation) of proxies whose forwarding headers are trusted.
+/// See [App::trust_proxies](crate::App::trust_proxies).
+/// ```
+/// use highnoon::TrustedProxy;
+///
+/// let proxy: TrustedProxy = "10.0.0.0/8".parse().unwrap();
+/// assert!(proxy.contains("10.1.2.3".parse().unwrap()));
+/// assert!(!proxy.contains("192.168.1.1".parse().unwrap()));
+///
+/// // a single address is the same as a /32 (or /128 for IPv6)
+/// let proxy: TrustedProxy = "::1".parse().unwrap();
+/// assert!(proxy.contains("::1".parse().unwrap()));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrustedProxy {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl TrustedProxy {
+    /// Check if `ip` is in this network. IPv4 addresses mapped into IPv6 (eg.
+    /// `::ffff:10.0.0.1`, as seen on dual stack listeners) are checked against IPv4 networks.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = if self.addr.is_ipv4() {
+            ip.to_canonical()
+        } else {
+            ip
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(&net.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(&net.octets(), &ip.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Compare the first `prefix` bits of two addresses
+fn prefix_matches(net: &[u8], ip: &[u8], prefix: u8) -> bool {
+    let full = (prefix / 8) as usize;
+    if net[..full] != ip[..full] {
+        return false;
+    }
+
+    let rest = prefix % 8;
+    if rest == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - rest);
+    net[full] & mask == ip[full] & mask
+}
+
+impl FromStr for TrustedProxy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid proxy address: {}", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(|| anyhow::anyhow!("invalid proxy network prefix: {}", s))?,
+            None => max,
+        };
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for TrustedProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// The forwarding header which trusted proxies set with the client's address.
+/// See [App::forwarded_header](crate::App::forwarded_header).
+///
+/// Only one header is ever read: a proxy usually passes the headers it doesn't manage through
+/// untouched, so a client could use those to pick its own address.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For: 192.0.2.60, 10.0.0.2` (the default, set by most proxies)
+    #[default]
+    XForwardedFor,
+    /// `Forwarded: for=192.0.2.60;proto=http, for=10.0.0.2` (RFC 7239)
+    Forwarded,
+    /// `X-Real-IP: 192.0.2.60`
+    XRealIp,
+}
+
+/// The trusted proxies of the app which accepted a request, stored in the request extensions
+/// so mounted apps use the same list
+#[derive(Clone)]
+pub(crate) struct TrustedProxies {
+    pub(crate) proxies: Arc<[TrustedProxy]>,
+    pub(crate) header: ForwardedHeader,
+}
+
+impl TrustedProxies {
+    fn trusted(&self, ip: IpAddr) -> bool {
+        self.proxies.iter().any(|proxy| proxy.contains(ip))
+    }
+
+    /// Find the client's address by walking back through the forwarding headers from the
+    /// peer, until reaching an address which isn't a trusted proxy.
+    pub(crate) fn real_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = peer.to_canonical();
+        if !self.trusted(peer) {
+            return peer;
+        }
+
+        let mut client = peer;
+        // the closest proxy appends its peer to the end of the list
+        for hop in forwarded_for(headers, self.header).into_iter().rev() {
+            match hop {
+                Some(ip) => client = ip.to_canonical(),
+                // an obfuscated or invalid address can't be followed any further
+                None => break,
+            }
+            if !self.trusted(client) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// The addresses from the forwarding header, in order, using `None` for entries which aren't
+/// IP addresses
+fn forwarded_for(headers: &HeaderMap, header: ForwardedHeader) -> Vec<Option<IpAddr>> {
+    let values = |name: &HeaderName| -> Vec<String> {
+        headers
+            .get_all(name)
+            .iter()
+            // an unreadable header can't be trusted at all, so give a single invalid entry
+            .map(|value| value.to_str().unwrap_or("unknown").to_owned())
+            .collect()
+    };
+
+    match header {
+        ForwardedHeader::XForwardedFor => values(&X_FORWARDED_FOR)
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(parse_node)
+            .collect(),
+        ForwardedHeader::Forwarded => values(&FORWARDED)
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_node(value))
+            })
+            .collect(),
+        ForwardedHeader::XRealIp => values(&X_REAL_IP)
+            .iter()
+            .map(|value| parse_node(value))
+            .collect(),
+    }
+}
+
+/// Parse a node from a forwarding header, which is an IP address optionally with a port (and
+/// quoted and bracketed in `Forwarded`, eg. `"[2001:db8::1]:4711"`)
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    node.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
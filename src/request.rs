@@ -1,16 +1,48 @@
+use crate::filter::timeout::{Deadline, TimeoutOverride};
+#[cfg(feature = "multipart")]
+use crate::multipart::{Multipart, MultipartLimits};
+use crate::proxy::TrustedProxies;
 use crate::state::State;
-use crate::{App, Error, Result};
+#[cfg(feature = "tls")]
+use crate::tls::{CertificateInfo, TlsInfo};
+use crate::{files, App, Error, Response, Result, SignedCookies};
+use bytes::{Bytes, BytesMut};
 use cookie::{Cookie, CookieJar};
+use futures_util::{Stream, TryStreamExt};
 use headers::{Header, HeaderMapExt};
+use hyper::body::{Buf, HttpBody};
 use hyper::header::HeaderValue;
-use hyper::{body::Buf, Body, HeaderMap, StatusCode};
+use hyper::http::Extensions;
+use hyper::{Body, HeaderMap, StatusCode};
+use percent_encoding::percent_decode_str;
 use route_recognizer::Params;
 use serde::de::DeserializeOwned;
-use std::io::Read;
-use std::net::SocketAddr;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::error::Error as StdError;
+use std::future::Future;
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::OnceCell;
 use tracing::error;
 
+/// Holds a value computed by [Request::get_or_init] in the request extensions
+struct Lazy<T>(OnceCell<T>);
+
+/// Flag stored in the request extensions to record that the body has been accessed
+#[derive(Clone, Default)]
+pub(crate) struct BodyRead(Arc<AtomicBool>);
+
+impl BodyRead {
+    pub(crate) fn is_read(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
 /// An incoming request
 pub struct Request<S: State> {
     app: Arc<App<S>>,
@@ -18,6 +50,8 @@ pub struct Request<S: State> {
     params: Params,
     inner: hyper::Request<Body>,
     remote_addr: SocketAddr,
+    /// The body read by [Request::buffer_body], which is given to every later read
+    buffered: Option<Bytes>,
 }
 
 impl<S: State> Request<S> {
@@ -34,10 +68,14 @@ impl<S: State> Request<S> {
             inner,
             params,
             remote_addr,
+            buffered: None,
         }
     }
 
-    pub(crate) fn into_parts(self) -> (hyper::Request<Body>, Params, SocketAddr, S::Context) {
+    pub(crate) fn into_parts(mut self) -> (hyper::Request<Body>, Params, SocketAddr, S::Context) {
+        if let Some(buffered) = self.buffered {
+            *self.inner.body_mut() = Body::from(buffered);
+        }
         (self.inner, self.params, self.remote_addr, self.context)
     }
 
@@ -52,6 +90,10 @@ impl<S: State> Request<S> {
         self.app.state()
     }
 
+    pub(crate) fn app(&self) -> &App<S> {
+        &self.app
+    }
+
     /// Get a reference to the request's context
     pub fn context(&self) -> &S::Context {
         &self.context
@@ -72,10 +114,46 @@ impl<S: State> Request<S> {
         self.inner.uri()
     }
 
+    /// Get the scheme of the request (eg. `http`). Requests sent to a proxy use an absolute URI
+    /// (`GET http://example.com/path`) which includes the scheme, otherwise it is `https` for
+    /// TLS connections and `http` for everything else.
+    pub fn uri_scheme(&self) -> &str {
+        if let Some(scheme) = self.inner.uri().scheme_str() {
+            return scheme;
+        }
+
+        #[cfg(feature = "tls")]
+        if self.inner.extensions().get::<TlsInfo>().is_some() {
+            return "https";
+        }
+
+        "http"
+    }
+
+    /// Get the host the request was sent to, without the port. This comes from the URI for
+    /// absolute URIs (ie. requests sent to a proxy, and HTTP/2 requests), and from the `Host`
+    /// header otherwise. Returns `None` if neither has a host.
+    pub fn uri_host(&self) -> Option<&str> {
+        if let Some(host) = self.inner.uri().host() {
+            return Some(host);
+        }
+
+        let host = self
+            .inner
+            .headers()
+            .get(hyper::header::HOST)?
+            .to_str()
+            .ok()?;
+        Some(crate::router::normalise_host(host)).filter(|host| !host.is_empty())
+    }
+
     /// Parse the URI query string into an instance of `T` that derives `Deserialize`.
     ///
     /// (To get the raw query string access it via `req.uri().query()`).
     /// If there is no query string, deserialize an empty string.
+    ///
+    /// Repeated parameters (eg. `?tag=a&tag=b`) can't be deserialized into a `Vec` field, use
+    /// [Request::query_pairs] to get every value.
     pub fn query<T: DeserializeOwned>(&self) -> Result<T> {
         // if there is no query string we can default to empty string
         // serde_urlencode will work if T has all optional fields
@@ -85,31 +163,214 @@ impl<S: State> Request<S> {
         Ok(t)
     }
 
+    /// Parse the URI query string into `T` like [Request::query], but never fail. Parameters
+    /// which can't be deserialized (eg. `page=abc` for a number) or which are repeated are
+    /// skipped, and `T`'s defaults are used for anything missing.
+    /// ```
+    /// # use highnoon::Request;
+    /// #[derive(Default, serde_derive::Deserialize)]
+    /// #[serde(default)]
+    /// struct Page {
+    ///     page: u32,
+    ///     sort: Option<String>,
+    /// }
+    ///
+    /// async fn list(req: Request<()>) -> String {
+    ///     // `?page=abc&sort=name&utm_source=mail` gives page 0, sorted by name
+    ///     let page: Page = req.query_lenient();
+    ///     format!("page {}", page.page)
+    /// }
+    /// ```
+    /// `T` should use `#[serde(default)]` so that skipped parameters get their default value,
+    /// otherwise a single bad parameter for a required field means `T::default()` is returned.
+    ///
+    /// Unknown parameters (eg. tracking parameters like `utm_source`) are ignored by both
+    /// methods, unless `T` uses `#[serde(deny_unknown_fields)]` in which case only this method
+    /// ignores them. Use [Request::query] when clients should be told about invalid
+    /// parameters.
+    pub fn query_lenient<T: DeserializeOwned + Default>(&self) -> T {
+        let q = self.inner.uri().query().unwrap_or("");
+        // parsing into pairs can't fail, invalid percent encodings are decoded lossily
+        let pairs: Vec<(String, String)> = serde_urlencoded::from_str(q).unwrap_or_default();
+
+        // a repeated parameter is always rejected by T, so only the first is kept
+        let mut seen = HashSet::new();
+        let pairs: Vec<(String, String)> = pairs
+            .into_iter()
+            .filter(|(key, _)| seen.insert(key.clone()))
+            .collect();
+
+        if let Some(value) = query_from_pairs(&pairs) {
+            return value;
+        }
+
+        // check each parameter on its own, so this takes linear time in the number of
+        // parameters. A parameter is fine if T only complains about other fields missing.
+        let kept: Vec<_> = pairs
+            .into_iter()
+            .filter(|pair| {
+                let q = serde_urlencoded::to_string([pair]).unwrap_or_default();
+                match serde_urlencoded::from_str::<T>(&q) {
+                    Ok(_) => true,
+                    Err(err) => err.to_string().starts_with("missing field"),
+                }
+            })
+            .collect();
+
+        query_from_pairs(&kept).unwrap_or_default()
+    }
+
+    /// Get the decoded parameters of the URI query string in order, including repeated
+    /// parameters which [Request::query] can't handle.
+    /// ```
+    /// # use highnoon::Request;
+    /// async fn search(req: Request<()>) -> String {
+    ///     // `?tag=rust&tag=web` gives both tags
+    ///     let tags: Vec<String> = req
+    ///         .query_pairs()
+    ///         .into_iter()
+    ///         .filter(|(key, _)| key == "tag")
+    ///         .map(|(_, value)| value)
+    ///         .collect();
+    ///     tags.join(", ")
+    /// }
+    /// ```
+    /// `+` is decoded as a space.
+    pub fn query_pairs(&self) -> Vec<(String, String)> {
+        let q = self.inner.uri().query().unwrap_or("");
+        // parsing into pairs can't fail, invalid percent encodings are decoded lossily
+        serde_urlencoded::from_str(q).unwrap_or_default()
+    }
+
     /// Get a typed header from the request
     /// (See also `headers`)
     pub fn header<T: Header>(&self) -> Option<T> {
         self.inner.headers().typed_get()
     }
 
+    /// Get the parsed `Content-Type` of the request, including any parameters (eg. `charset`).
+    /// Returns `None` if the header is missing or invalid.
+    pub fn content_type(&self) -> Option<mime::Mime> {
+        self.header::<headers::ContentType>().map(mime::Mime::from)
+    }
+
+    /// Check if the client asked to upgrade the connection to another protocol (eg. websockets),
+    /// ie. the request has an `Upgrade` header and a `Connection` header containing `upgrade`.
+    ///
+    /// Upgraded connections are handed off to a separate task when the response is sent, so
+    /// filters can use this to skip work that only makes sense for ordinary requests.
+    pub fn is_upgrade(&self) -> bool {
+        let connection_upgrade = self
+            .header::<headers::Connection>()
+            .map(|conn| conn.contains(hyper::header::UPGRADE))
+            .unwrap_or(false);
+        connection_upgrade && self.inner.headers().contains_key(hyper::header::UPGRADE)
+    }
+
     /// Get all headers as a `HeaderMap`
     pub fn headers(&self) -> &HeaderMap<HeaderValue> {
         self.inner.headers()
     }
 
-    /// Get the request's cookies
+    /// Get the request's extensions. These can be used to store arbitrary data in the
+    /// request keyed by type (but prefer using the Context for data known ahead of time)
+    pub fn extensions(&self) -> &Extensions {
+        self.inner.extensions()
+    }
+
+    /// Get a mut reference to the request's extensions
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        self.inner.extensions_mut()
+    }
+
+    /// Get a value of type `T` which is computed at most once per request. The first call runs
+    /// `init` and stores the result in the request's extensions, later calls (eg. from the
+    /// endpoint, after a filter has already asked for it) return the stored value.
+    /// ```
+    /// # use highnoon::{Request, Result};
+    /// struct Claims(String);
+    ///
+    /// async fn handler(mut req: Request<()>) -> Result<String> {
+    ///     let claims = req
+    ///         .get_or_init(|| async { Claims("expensive to parse".to_owned()) })
+    ///         .await;
+    ///     Ok(claims.0.clone())
+    /// }
+    /// ```
+    pub async fn get_or_init<T, F, Fut>(&mut self, init: F) -> &T
+    where
+        T: Send + Sync + 'static,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        self.lazy_cell::<T>().get_or_init(init).await
+    }
+
+    /// Like [Request::get_or_init], but `init` may fail. If it fails the error is returned and
+    /// nothing is stored, so the next call runs its `init` again.
+    pub async fn get_or_try_init<T, F, Fut>(&mut self, init: F) -> Result<&T>
+    where
+        T: Send + Sync + 'static,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.lazy_cell::<T>().get_or_try_init(init).await
+    }
+
+    fn lazy_cell<T: Send + Sync + 'static>(&mut self) -> &OnceCell<T> {
+        if self.extensions().get::<Lazy<T>>().is_none() {
+            self.extensions_mut().insert(Lazy(OnceCell::<T>::new()));
+        }
+        &self
+            .extensions()
+            .get::<Lazy<T>>()
+            .expect("lazy value was just inserted")
+            .0
+    }
+
+    /// Get the deadline by which this request must be completed, if one has been set by
+    /// the [Timeout](crate::filter::Timeout) filter
+    pub fn deadline(&self) -> Option<Instant> {
+        self.extensions().get::<Deadline>().map(|d| d.0)
+    }
+
+    /// Get the request's cookies.
+    ///
+    /// The cookies are added to the jar as originals, so the jar tracks any changes made to it.
+    /// Pass the modified jar to [Response::set_cookie_jar] to send just the changes back to the
+    /// client.
     pub fn cookies(&self) -> Result<CookieJar> {
         let mut cookies = CookieJar::new();
 
         for val in self.inner.headers().get_all(headers::Cookie::name()) {
-            let c = Cookie::parse(val.to_str()?)?;
-            cookies.add(c.into_owned());
+            // one header may hold several cookies, separated by semicolons
+            for pair in val.to_str()?.split(';').map(str::trim) {
+                if !pair.is_empty() {
+                    cookies.add_original(Cookie::parse(pair)?.into_owned());
+                }
+            }
         }
 
         Ok(cookies)
     }
 
+    /// Get a cookie which was signed using [SignedCookies], and verify the signature.
+    /// Returns `None` if the cookie is missing, or if the signature is invalid.
+    pub fn signed_cookie(
+        &self,
+        signer: &SignedCookies,
+        name: &str,
+    ) -> Result<Option<Cookie<'static>>> {
+        Ok(self.cookies()?.signed(signer.key()).get(name))
+    }
+
     /// Get a route parameter (eg. `:key` or `*key` segments in the URI path)
     ///
+    /// The value is returned exactly as it appears in the URI, it is *not* percent-decoded.
+    /// Routes are matched before decoding, so a percent-encoded slash (`%2F`) does not split a
+    /// segment, and `:key` may match a value which contains a slash once decoded.
+    /// Use [Request::param_decoded] to get the decoded value.
+    ///
     /// If the parameter is not present, logs an error and returns a `400 Bad Request` to the client
     pub fn param(&self, param: &str) -> Result<&str> {
         self.params.find(param).ok_or_else(|| {
@@ -118,59 +379,479 @@ impl<S: State> Request<S> {
         })
     }
 
-    /// Get all route parameters
+    /// Get a route parameter and percent-decode it (eg. `John%20Doe` becomes `John Doe`)
+    ///
+    /// Decoding happens after routing, so an encoded slash in the value does not change which
+    /// route matched. If the value needs to be used as a path, check it for slashes.
+    ///
+    /// If the parameter is not present, or does not decode to valid UTF-8, returns a
+    /// `400 Bad Request` to the client
+    pub fn param_decoded(&self, param: &str) -> Result<Cow<'_, str>> {
+        let raw = self.param(param)?;
+        percent_decode_str(raw).decode_utf8().map_err(|_| {
+            error!("parameter {} is not valid UTF-8 once decoded", param);
+            Error::http(StatusCode::BAD_REQUEST)
+        })
+    }
+
+    /// Get all route parameters (not percent-decoded, see [Request::param])
     pub fn params(&self) -> &Params {
         &self.params
     }
 
+    /// Called before the body is read. Records that the body was read, and gives the reader a
+    /// fresh copy of the buffered body if there is one.
+    fn start_body_read(&mut self) {
+        if let Some(body_read) = self.inner.extensions().get::<BodyRead>() {
+            body_read.0.store(true, Ordering::Relaxed);
+        }
+        if let Some(buffered) = &self.buffered {
+            *self.inner.body_mut() = Body::from(buffered.clone());
+        }
+    }
+
     /// Get the request body as a `hyper::Body`
     pub async fn body_mut(&mut self) -> Result<&mut Body> {
+        self.start_body_read();
         Ok(self.inner.body_mut())
     }
 
+    /// Take the request body as a stream of chunks, to process it as it arrives. The stream
+    /// is `'static`, so it can be moved into another task. Combined with
+    /// [Response::body_channel] this allows the response to be sent while the request body is
+    /// still being read.
+    ///
+    /// After calling this the request body is empty.
+    pub fn body_stream(&mut self) -> impl Stream<Item = Result<Bytes>> + Send + 'static {
+        self.start_body_read();
+        TryStreamExt::map_err(std::mem::take(self.inner.body_mut()), body_error)
+    }
+
     pub(crate) fn as_inner_mut(&mut self) -> &mut hyper::Request<Body> {
         &mut self.inner
     }
 
     /// Get a reader to read the request body
     ///
-    /// (This does buffer the whole body into memory, but not necessarily contiguous memory).
-    /// If you need to protect against malicious clients you should access the body via `body_mut`
+    /// (This does buffer the whole body into memory, but not necessarily contiguous memory,
+    /// see [Request::body_buf]).
+    /// If you need to protect against malicious clients use [Request::reader_limited], or access
+    /// the body via `body_mut`
     pub async fn reader(&mut self) -> Result<impl Read + '_> {
-        let buffer = hyper::body::aggregate(self.inner.body_mut()).await?;
-        Ok(buffer.reader())
+        Ok(self.body_buf().await?.reader())
+    }
+
+    /// Get a reader to read the request body, which is limited to `max` bytes. Larger bodies
+    /// return `413 Payload Too Large` (without reading the body if its `Content-Length` is too
+    /// large), so unlike [Request::reader] this is safe to use with untrusted clients.
+    pub async fn reader_limited(&mut self, max: usize) -> Result<impl Read + '_> {
+        Ok(self.body_limited(max).await?.reader())
+    }
+
+    /// Read the body into one buffer, returning an error if it is larger than `max` bytes
+    async fn body_limited(&mut self, max: usize) -> Result<Bytes> {
+        self.start_body_read();
+        let declared = self.header::<headers::ContentLength>();
+        if let Some(headers::ContentLength(declared)) = declared {
+            if declared > max as u64 {
+                return Err(body_too_large(max));
+            }
+        }
+
+        let mut chunks = Vec::new();
+        let mut len = 0;
+        while let Some(chunk) = self.inner.body_mut().data().await {
+            let chunk = chunk.map_err(body_error)?;
+            len += chunk.len();
+            if len > max {
+                return Err(body_too_large(max));
+            }
+            chunks.push(chunk);
+        }
+        check_body_length(declared, len)?;
+
+        // avoid copying when the body arrived in one chunk
+        if chunks.len() == 1 {
+            return Ok(chunks.remove(0));
+        }
+        let mut bytes = BytesMut::with_capacity(len);
+        for chunk in chunks {
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(bytes.freeze())
+    }
+
+    /// Read the whole request body into memory, and return it as a [Buf].
+    ///
+    /// The buffer is made of the chunks the body arrived in, so no data is copied, but the
+    /// body may not be in contiguous memory (ie. [Buf::chunk] may return less than the whole
+    /// body). Use [Request::body_contiguous] if you need a single slice.
+    pub async fn body_buf(&mut self) -> Result<impl Buf + '_> {
+        self.start_body_read();
+        let declared = self.header::<headers::ContentLength>();
+        let buffer = hyper::body::aggregate(self.inner.body_mut())
+            .await
+            .map_err(body_error)?;
+        check_body_length(declared, buffer.remaining())?;
+        Ok(buffer)
+    }
+
+    /// Read the whole request body into memory and keep it, so it can be read any number of
+    /// times (eg. by a filter checking a signature and then by the endpoint). Every later read
+    /// of the body, with any of the body methods, gets the buffered copy. Returns the body.
+    pub async fn buffer_body(&mut self) -> Result<Bytes> {
+        if let Some(buffered) = &self.buffered {
+            return Ok(buffered.clone());
+        }
+        let bytes = self.body_contiguous().await?;
+        Ok(self.keep_buffered(bytes))
+    }
+
+    /// Like [Request::buffer_body], but returns `413 Payload Too Large` if the body is larger
+    /// than `max` bytes.
+    pub async fn buffer_body_limited(&mut self, max: usize) -> Result<Bytes> {
+        if let Some(buffered) = &self.buffered {
+            if buffered.len() > max {
+                return Err(body_too_large(max));
+            }
+            return Ok(buffered.clone());
+        }
+        let bytes = self.body_limited(max).await?;
+        Ok(self.keep_buffered(bytes))
+    }
+
+    fn keep_buffered(&mut self, bytes: Bytes) -> Bytes {
+        // also put it back in the inner request for anything reading it directly
+        *self.inner.body_mut() = Body::from(bytes.clone());
+        self.buffered = Some(bytes.clone());
+        bytes
+    }
+
+    /// Make a copy of this request, to send it down the filter chain more than once (eg. in a
+    /// filter which retries failed requests). The body is buffered in memory (see
+    /// [Request::buffer_body]) and both requests get a copy.
+    ///
+    /// The method, URI, headers, route parameters and context are copied, along with the
+    /// request extensions highnoon itself uses (eg. the timeout deadline and TLS details).
+    /// Other extensions can't be cloned in general, so they are left behind.
+    /// ```
+    /// # use highnoon::filter::Next;
+    /// # use highnoon::{Error, Request, Response, Result};
+    /// async fn retry_once(mut req: Request<()>, next: Next<'_, ()>) -> Result<Response> {
+    ///     let retry = req.try_clone().await?;
+    ///     // server errors can be returned as a response or as an error
+    ///     match next.next(req).await {
+    ///         Ok(resp) | Err(Error::Http(resp)) if resp.as_ref().status().is_server_error() => {
+    ///             next.next(retry).await
+    ///         }
+    ///         Err(Error::Internal(_)) => next.next(retry).await,
+    ///         result => result,
+    ///     }
+    /// }
+    /// ```
+    pub async fn try_clone(&mut self) -> Result<Self>
+    where
+        S::Context: Clone,
+    {
+        let body = self.buffer_body().await?;
+
+        let mut inner = hyper::Request::new(Body::from(body.clone()));
+        *inner.method_mut() = self.inner.method().clone();
+        *inner.uri_mut() = self.inner.uri().clone();
+        *inner.version_mut() = self.inner.version();
+        *inner.headers_mut() = self.inner.headers().clone();
+        copy_extensions(self.inner.extensions(), inner.extensions_mut());
+
+        Ok(Self {
+            app: self.app.clone(),
+            context: self.context.clone(),
+            params: self.params.clone(),
+            inner,
+            remote_addr: self.remote_addr,
+            buffered: Some(body),
+        })
+    }
+
+    /// Read the whole request body into memory as a single contiguous [Bytes] buffer.
+    ///
+    /// If the body arrived in one chunk this does not copy it, otherwise the chunks are copied
+    /// into a new buffer.
+    pub async fn body_contiguous(&mut self) -> Result<Bytes> {
+        self.start_body_read();
+        let declared = self.header::<headers::ContentLength>();
+        let bytes = hyper::body::to_bytes(self.inner.body_mut())
+            .await
+            .map_err(body_error)?;
+        check_body_length(declared, bytes.len())?;
+        Ok(bytes)
     }
 
     /// Get the request body as raw bytes in a `Vec<u8>`
     pub async fn body_bytes(&mut self) -> Result<Vec<u8>> {
-        let bytes = hyper::body::to_bytes(self.inner.body_mut()).await?;
+        let bytes = self.body_contiguous().await?;
         Ok(bytes.to_vec())
     }
 
     /// Get the request body as UTF-8 data in String
     pub async fn body_string(&mut self) -> Result<String> {
-        let bytes = hyper::body::to_bytes(self.inner.body_mut()).await?;
+        let bytes = self.body_contiguous().await?;
         Ok(String::from_utf8(bytes.to_vec())?)
     }
 
     /// Get the request body as JSON and deserialize into `T`.
     ///
-    /// If deserialization fails, log an error and return `400 Bad Request`.
+    /// If deserialization fails, log an error and return `400 Bad Request`. The response body
+    /// describes the error unless it has been turned off with
+    /// [App::detailed_body_errors](crate::App::detailed_body_errors).
     /// (If this logic is not appropriate, consider using `reader` and using `serde_json` directly)
     pub async fn body_json<T: DeserializeOwned>(&mut self) -> Result<T> {
         let reader = self.reader().await?;
-        serde_json::from_reader(reader).map_err(|err| {
-            let msg = format!("error parsing request body as json: {}", err);
-            error!("{}", msg);
+        let result = serde_json::from_reader(reader);
+        result.map_err(|err| self.body_error("json", err))
+    }
+
+    /// Log an error parsing the body, and build the `400 Bad Request` response for it
+    fn body_error(&self, format: &str, err: impl std::fmt::Display) -> Error {
+        let msg = format!("error parsing request body as {}: {}", format, err);
+        error!("{}", msg);
+        if self.app.detailed_body_errors {
             Error::http((StatusCode::BAD_REQUEST, msg))
-        })
+        } else {
+            Error::bad_request("invalid request body")
+        }
+    }
+
+    /// Get the request body as either JSON or urlencoded form data, depending on the
+    /// `Content-Type`, and deserialize into `T`. This suits endpoints used by both API clients
+    /// and HTML forms.
+    ///
+    /// `application/json` (and types with a `+json` suffix) are parsed as JSON, and
+    /// `application/x-www-form-urlencoded` as form data. Any other content type (or none)
+    /// returns `415 Unsupported Media Type`, and if deserialization fails `400 Bad Request` is
+    /// returned (as for [Request::body_json]).
+    pub async fn body_input<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let mime = self
+            .content_type()
+            .ok_or_else(|| Error::http(StatusCode::UNSUPPORTED_MEDIA_TYPE))?;
+
+        if mime.essence_str() == mime::APPLICATION_JSON.essence_str()
+            || mime.suffix() == Some(mime::JSON)
+        {
+            self.body_json().await
+        } else if mime.essence_str() == mime::APPLICATION_WWW_FORM_URLENCODED.essence_str() {
+            let bytes = self.body_contiguous().await?;
+            serde_urlencoded::from_bytes(&bytes).map_err(|err| self.body_error("form data", err))
+        } else {
+            Err(Error::http(StatusCode::UNSUPPORTED_MEDIA_TYPE))
+        }
+    }
+
+    /// Respond with the contents of a file. The content type is guessed from the file name, and
+    /// `ETag` and `Last-Modified` headers are sent so clients can cache the file. If the
+    /// request's `If-None-Match` or `If-Modified-Since` headers show the client already has the
+    /// current version, a `304 Not Modified` response is returned instead.
+    ///
+    /// A single byte range may be requested with the `Range` header (and `If-Range`), which
+    /// returns `206 Partial Content`, or `416 Range Not Satisfiable` if the range is outside the
+    /// file. Requests for multiple ranges get the whole file.
+    ///
+    /// Returns `404 Not Found` if the file does not exist, and `403 Forbidden` if it can't be
+    /// opened due to permissions.
+    ///
+    /// No checks are made on `path`, so don't pass untrusted paths to this method. The file is
+    /// always read, never memory mapped, so it is safe to change it while it is being sent.
+    /// ```
+    /// # use highnoon::{Request, Response, Result};
+    /// async fn avatar(req: Request<()>) -> Result<Response> {
+    ///     let user = req.param("user")?;
+    ///     req.send_file(format!("avatars/{}.png", user.parse::<u64>()?)).await
+    /// }
+    /// ```
+    pub async fn send_file(&self, path: impl AsRef<Path>) -> Result<Response> {
+        files::send_file(self.headers(), path.as_ref(), files::FileBody::Read).await
+    }
+
+    /// Parse the request body as `multipart/form-data`. The body is parsed as it is read, one
+    /// field at a time (see [Multipart](crate::multipart::Multipart)).
+    ///
+    /// Returns `415 Unsupported Media Type` if the request is not `multipart/form-data`, and
+    /// `400 Bad Request` if the content type has no boundary.
+    ///
+    /// No limits are applied to the number or size of fields, use [Request::multipart_with]
+    /// for endpoints open to the public.
+    /// (Only available with the `multipart` feature)
+    #[cfg(feature = "multipart")]
+    pub fn multipart(&mut self) -> Result<Multipart> {
+        self.multipart_with(MultipartLimits::default())
+    }
+
+    /// Parse the request body as `multipart/form-data`, enforcing the given limits.
+    /// See [Request::multipart].
+    /// (Only available with the `multipart` feature)
+    #[cfg(feature = "multipart")]
+    pub fn multipart_with(&mut self, limits: MultipartLimits) -> Result<Multipart> {
+        let content_type = self
+            .headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        let mime: mime::Mime = content_type
+            .parse()
+            .map_err(|_| Error::http(StatusCode::UNSUPPORTED_MEDIA_TYPE))?;
+        if mime.essence_str() != mime::MULTIPART_FORM_DATA.essence_str() {
+            return Err(Error::http(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+        }
+
+        let boundary = multer::parse_boundary(content_type)
+            .map_err(|_| Error::bad_request("multipart boundary is missing"))?;
+
+        self.start_body_read();
+        let body = std::mem::take(self.inner.body_mut());
+        Ok(Multipart::new(body, boundary, limits))
     }
 
     /// Get the address of the remote peer.
     ///
     /// This method uses the network level address only and hence may be incorrect if you are
-    /// behind a proxy. (This does *not* check for any `Forwarded` headers etc...)
+    /// behind a proxy. (This does *not* check for any `Forwarded` headers etc, see
+    /// [Request::real_ip] for that)
     pub fn remote_addr(&self) -> &SocketAddr {
         &self.remote_addr
     }
+
+    /// Get the IP address of the client, taking proxies set with
+    /// [App::trust_proxies](crate::App::trust_proxies) into account.
+    ///
+    /// If the remote peer is a trusted proxy, the address it forwarded the request for is
+    /// taken from the header chosen with [App::forwarded_header](crate::App::forwarded_header)
+    /// (`X-Forwarded-For` by default). This is repeated for chains of trusted proxies, and the first address which
+    /// isn't a trusted proxy is returned. Otherwise this is the IP address of
+    /// [Request::remote_addr].
+    pub fn real_ip(&self) -> IpAddr {
+        match self.inner.extensions().get::<TrustedProxies>() {
+            Some(proxies) => proxies.real_ip(self.remote_addr.ip(), self.inner.headers()),
+            None => self.remote_addr.ip(),
+        }
+    }
+
+    /// Get the certificate the client presented during the TLS handshake.
+    ///
+    /// Returns `None` if the connection is not using TLS, or the client did not send a
+    /// certificate. The server's `rustls::ServerConfig` must be set up to request client
+    /// certificates for this to be available. (Only available with the `tls` feature)
+    #[cfg(feature = "tls")]
+    pub fn client_cert(&self) -> Option<&CertificateInfo> {
+        self.inner
+            .extensions()
+            .get::<TlsInfo>()
+            .and_then(|tls| tls.client_cert.as_deref())
+    }
+
+    /// Get the server name the client asked for using SNI during the TLS handshake. This is
+    /// useful for choosing a tenant or virtual host.
+    ///
+    /// Returns `None` if the connection is not using TLS, or the client did not send SNI (eg. if
+    /// it connected using an IP address). (Only available with the `tls` feature)
+    #[cfg(feature = "tls")]
+    pub fn sni_hostname(&self) -> Option<&str> {
+        self.inner
+            .extensions()
+            .get::<TlsInfo>()
+            .and_then(|tls| tls.server_name.as_deref())
+    }
+
+    /// Get the application protocol negotiated with ALPN during the TLS handshake (eg. `h2` or
+    /// `http/1.1`).
+    ///
+    /// Returns `None` if the connection is not using TLS, or no protocol was negotiated. The
+    /// server's `rustls::ServerConfig` must list its `alpn_protocols` for negotiation to happen.
+    /// (Only available with the `tls` feature)
+    #[cfg(feature = "tls")]
+    pub fn alpn_protocol(&self) -> Option<&str> {
+        self.inner
+            .extensions()
+            .get::<TlsInfo>()
+            .and_then(|tls| tls.alpn_protocol.as_deref())
+    }
+}
+
+/// Deserialize query parameters which have already been decoded
+fn query_from_pairs<T: DeserializeOwned>(pairs: &[(String, String)]) -> Option<T> {
+    serde_urlencoded::to_string(pairs)
+        .ok()
+        .and_then(|q| serde_urlencoded::from_str(&q).ok())
+}
+
+/// Copy the extensions highnoon adds to requests (the rest can't be cloned)
+fn copy_extensions(from: &Extensions, to: &mut Extensions) {
+    if let Some(deadline) = from.get::<Deadline>() {
+        to.insert(*deadline);
+    }
+    if let Some(timeout) = from.get::<TimeoutOverride>() {
+        to.insert(*timeout);
+    }
+    if let Some(body_read) = from.get::<BodyRead>() {
+        to.insert(body_read.clone());
+    }
+    #[cfg(feature = "tls")]
+    if let Some(tls) = from.get::<TlsInfo>() {
+        to.insert(tls.clone());
+    }
+}
+
+/// Convert an error reading the request body into an `Error`. Bodies which end before the
+/// declared `Content-Length` are the client's fault and return `400 Bad Request`, and compressed
+/// bodies which expand past the limit return `413 Payload Too Large`. Anything else is an
+/// internal error.
+fn body_error(err: hyper::Error) -> Error {
+    #[cfg(feature = "compression")]
+    if let Some(too_large) = err
+        .source()
+        .and_then(|cause| cause.downcast_ref::<io::Error>())
+        .and_then(|cause| cause.get_ref())
+        .and_then(|cause| cause.downcast_ref::<crate::decompress::DecompressedTooLarge>())
+    {
+        error!("{}", too_large);
+        return Error::Http(
+            Response::generated(StatusCode::PAYLOAD_TOO_LARGE).body(too_large.to_string()),
+        );
+    }
+
+    let truncated = err.is_incomplete_message()
+        || err
+            .source()
+            .and_then(|cause| cause.downcast_ref::<io::Error>())
+            .map(|cause| cause.kind() == io::ErrorKind::UnexpectedEof)
+            .unwrap_or(false);
+
+    if truncated {
+        let msg = "request body is shorter than Content-Length";
+        error!("{}", msg);
+        Error::bad_request(msg)
+    } else {
+        err.into()
+    }
+}
+
+fn body_too_large(max: usize) -> Error {
+    let msg = format!("request body is larger than the limit of {} bytes", max);
+    error!("{}", msg);
+    Error::Http(Response::generated(StatusCode::PAYLOAD_TOO_LARGE).body(msg))
+}
+
+/// Check the length of the body that was read matches the `Content-Length` header.
+/// hyper enforces this for real connections, but the test client doesn't check it at all.
+fn check_body_length(declared: Option<headers::ContentLength>, actual: usize) -> Result<()> {
+    match declared {
+        Some(headers::ContentLength(declared)) if declared != actual as u64 => {
+            let msg = format!(
+                "request body length {} does not match Content-Length {}",
+                actual, declared
+            );
+            error!("{}", msg);
+            Err(Error::bad_request(msg))
+        }
+        _ => Ok(()),
+    }
 }
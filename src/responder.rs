@@ -1,7 +1,13 @@
 use crate::response::Response;
-use crate::Result;
+use crate::{Error, Result};
+use bytes::Bytes;
+use futures_util::stream::{self, Stream, StreamExt};
+use headers::Header;
 use hyper::{Body, StatusCode};
-use serde::Serialize;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::io;
+use tracing::error;
 
 /// This trait is implemented for all the common types you can return from an endpoint
 ///
@@ -39,7 +45,6 @@ use serde::Serialize;
 ///     Ok((StatusCode::CONFLICT, "Already Exists"))
 /// }
 /// ```
-
 pub trait Responder {
     fn into_response(self) -> Result<Response>;
 }
@@ -52,13 +57,13 @@ impl Responder for StatusCode {
 
 impl Responder for String {
     fn into_response(self) -> Result<Response> {
-        Ok(Response::ok().body(self))
+        Ok(Response::ok().text(self))
     }
 }
 
 impl Responder for &str {
     fn into_response(self) -> Result<Response> {
-        Ok(Response::ok().body(self.to_owned()))
+        Ok(Response::ok().text(self))
     }
 }
 
@@ -82,6 +87,24 @@ impl<R: Responder> Responder for (StatusCode, R) {
     }
 }
 
+/// Set the status and a header (eg. a `ContentType` override) along with the body
+/// ```
+/// use highnoon::{Request, Responder, StatusCode};
+/// use highnoon::headers::ContentType;
+///
+/// fn example(_: Request<()>) -> impl Responder {
+///     (StatusCode::OK, ContentType::html(), "<h1>Hello</h1>")
+/// }
+/// ```
+impl<H: Header, R: Responder> Responder for (StatusCode, H, R) {
+    fn into_response(self) -> Result<Response> {
+        let mut resp = self.2.into_response()?;
+        resp.set_status(self.0);
+        resp.set_header(self.1);
+        Ok(resp)
+    }
+}
+
 /// Returns `StatusCode::NotFound` for `None`, and the inner value for `Some`
 impl<R: Responder> Responder for Option<R> {
     fn into_response(self) -> Result<Response> {
@@ -99,7 +122,8 @@ impl<R: Responder> Responder for Option<R> {
 ///     Json(vec!["an", "array"])
 /// }
 /// ```
-pub struct Json<T: Serialize>(pub T);
+/// It is also an [extractor](crate::extract) for JSON request bodies.
+pub struct Json<T>(pub T);
 
 impl<T: Serialize> Responder for Json<T> {
     fn into_response(self) -> Result<Response> {
@@ -107,6 +131,52 @@ impl<T: Serialize> Responder for Json<T> {
     }
 }
 
+/// A Wrapper to return canonical JSON: object keys are sorted and there is no whitespace, so
+/// equal values always serialize to the same bytes. This is useful when the body is signed
+/// (eg. webhooks), or hashed for caching.
+/// ```
+/// use highnoon::{CanonicalJson, Request, Responder};
+/// use serde_json::json;
+///
+/// fn returns_json(_: Request<()>) -> impl Responder {
+///     // sent as {"a":[1,2],"b":true}
+///     CanonicalJson(json!({ "b": true, "a": [1, 2] }))
+/// }
+/// ```
+pub struct CanonicalJson<T: Serialize>(pub T);
+
+impl<T: Serialize> Responder for CanonicalJson<T> {
+    fn into_response(self) -> Result<Response> {
+        let value = serde_json::to_value(self.0)?;
+        let data = serde_json::to_vec(&SortedKeys(&value))?;
+        Ok(Response::ok()
+            .header(headers::ContentType::json())
+            .body(data))
+    }
+}
+
+/// Serializes a JSON value with the keys of every object in sorted order. `serde_json::Map`
+/// only sorts its keys when the `preserve_order` feature is off, and another crate in the
+/// build could turn it on.
+struct SortedKeys<'a>(&'a Value);
+
+impl Serialize for SortedKeys<'_> {
+    fn serialize<Ser: Serializer>(
+        &self,
+        serializer: Ser,
+    ) -> std::result::Result<Ser::Ok, Ser::Error> {
+        match self.0 {
+            Value::Array(items) => serializer.collect_seq(items.iter().map(SortedKeys)),
+            Value::Object(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                serializer.collect_map(entries.into_iter().map(|(k, v)| (k, SortedKeys(v))))
+            }
+            other => other.serialize(serializer),
+        }
+    }
+}
+
 /// A Wrapper to return Form data. This can be wrapped over any `serde::Serialize` type.
 pub struct Form<T: Serialize>(pub T);
 
@@ -116,6 +186,79 @@ impl<T: Serialize> Responder for Form<T> {
     }
 }
 
+/// A Wrapper to stream a JSON array, one item at a time. The array is sent as the stream
+/// produces items, so large arrays never need to be held in memory, and clients can parse the
+/// response as normal JSON (unlike newline delimited JSON).
+/// ```
+/// use futures_util::stream;
+/// use highnoon::{JsonArrayStream, Request, Responder};
+///
+/// fn returns_array(_: Request<()>) -> impl Responder {
+///     let rows = stream::iter((0..1000).map(highnoon::Result::Ok));
+///     JsonArrayStream(rows)
+/// }
+/// ```
+/// The status code and headers are sent before the first item, so an error from the stream (or
+/// from serializing an item) can't be turned into an error response. Instead the error is
+/// logged and the connection is aborted, leaving the client with an incomplete (and hence
+/// invalid) JSON document rather than a truncated array that looks complete.
+pub struct JsonArrayStream<S>(pub S);
+
+/// Position in the JSON array, to know where brackets and commas go
+enum ArrayState {
+    Start,
+    First,
+    Rest,
+    Done,
+}
+
+impl<S, T, E> Responder for JsonArrayStream<S>
+where
+    S: Stream<Item = std::result::Result<T, E>> + Send + 'static,
+    T: Serialize + 'static,
+    E: Into<Error> + 'static,
+{
+    fn into_response(self) -> Result<Response> {
+        let items = self.0.boxed();
+
+        let body = stream::unfold(
+            (items, ArrayState::Start),
+            |(mut items, state)| async move {
+                let (chunk, next) = match state {
+                    ArrayState::Start => (Ok(Bytes::from_static(b"[")), ArrayState::First),
+                    ArrayState::First | ArrayState::Rest => match items.next().await {
+                        Some(Ok(item)) => {
+                            let mut buf = match state {
+                                ArrayState::Rest => vec![b','],
+                                _ => vec![],
+                            };
+                            match serde_json::to_writer(&mut buf, &item) {
+                                Ok(()) => (Ok(Bytes::from(buf)), ArrayState::Rest),
+                                Err(err) => (Err(abort_stream(err.into())), ArrayState::Done),
+                            }
+                        }
+                        Some(Err(err)) => (Err(abort_stream(err.into())), ArrayState::Done),
+                        None => (Ok(Bytes::from_static(b"]")), ArrayState::Done),
+                    },
+                    ArrayState::Done => return None,
+                };
+                Some((chunk, (items, next)))
+            },
+        );
+
+        Ok(Response::ok()
+            .header(headers::ContentType::json())
+            .body(Body::wrap_stream(body)))
+    }
+}
+
+/// Log an error which happened part way through streaming a body, and convert it to an error
+/// which makes hyper abort the response
+fn abort_stream(err: Error) -> io::Error {
+    error!("error while streaming JSON array: {}", err);
+    io::Error::other("JSON array stream failed")
+}
+
 /// Identity implementation
 impl Responder for Response {
     fn into_response(self) -> Result<Response> {
@@ -7,15 +7,131 @@
 /// }
 /// ```
 use crate::Result;
-use headers::{Header, HeaderMapExt};
+use bytes::Bytes;
+use cookie::{Cookie, CookieJar};
+use futures_util::future::BoxFuture;
+use futures_util::Stream;
+use headers::{ContentType, Header, HeaderMapExt};
+use hyper::body::HttpBody;
+use hyper::ext::ReasonPhrase;
 use hyper::header::{HeaderName, HeaderValue};
-use hyper::{Body, StatusCode};
+use hyper::{Body, HeaderMap, StatusCode};
+use mime::Mime;
 use serde::Serialize;
 use std::convert::TryInto;
+use std::error::Error as StdError;
 use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
 use tokio::io::AsyncRead;
 use tokio_util::io::ReaderStream;
-use tracing::debug;
+use tracing::{debug, warn};
+
+/// Size of the chunks read when streaming a body from a reader or file
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Files at least this big are memory mapped instead of read
+#[cfg(feature = "mmap")]
+const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// Marks a response whose body was set from a string, so the app's default text content type
+/// can be applied (see [App::default_text_content_type](crate::App::default_text_content_type))
+#[derive(Clone, Copy)]
+struct TextBody;
+
+/// Marks a response generated by highnoon itself (eg. a 404 from the router), which can be
+/// replaced by an error page (see [App::error_page](crate::App::error_page))
+#[derive(Clone, Copy)]
+struct Generated;
+
+/// The task copying the body into the channel of a response with trailers, which is spawned
+/// when the response is sent
+struct TrailersTask(Mutex<Option<BoxFuture<'static, ()>>>);
+
+/// Writes to the body of a response created with [Response::body_channel]. The body ends when
+/// the sender is dropped.
+pub struct BodySender {
+    inner: hyper::body::Sender,
+}
+
+impl BodySender {
+    /// Send a chunk of the body, waiting until the connection is ready for it. Returns an error
+    /// if the client has disconnected, so producers should stop sending.
+    pub async fn send(&mut self, data: impl Into<Bytes>) -> Result<()> {
+        self.inner.send_data(data.into()).await?;
+        Ok(())
+    }
+
+    /// Abort the response, so the client sees an incomplete body instead of a normal end
+    pub fn abort(self) {
+        self.inner.abort()
+    }
+}
+
+/// A caching policy for the `Cache-Control` header, set with [Response::cache_control].
+/// ```
+/// use highnoon::{CacheControl, Response};
+/// use std::time::Duration;
+///
+/// let resp = Response::ok().cache_control(
+///     CacheControl::new()
+///         .public()
+///         .max_age(Duration::from_secs(3600))
+///         .immutable(),
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct CacheControl(headers::CacheControl);
+
+impl Default for CacheControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CacheControl {
+    /// Create an empty policy
+    pub fn new() -> Self {
+        Self(headers::CacheControl::new())
+    }
+
+    /// Allow the response to be cached for at most `max_age`
+    pub fn max_age(self, max_age: Duration) -> Self {
+        Self(self.0.with_max_age(max_age))
+    }
+
+    /// Don't allow the response to be stored in any cache
+    pub fn no_store(self) -> Self {
+        Self(self.0.with_no_store())
+    }
+
+    /// Require caches to revalidate the response with the server before using it
+    pub fn no_cache(self) -> Self {
+        Self(self.0.with_no_cache())
+    }
+
+    /// Allow shared caches (eg. proxies) to store the response
+    pub fn public(self) -> Self {
+        Self(self.0.with_public())
+    }
+
+    /// Only allow the client's own cache to store the response
+    pub fn private(self) -> Self {
+        Self(self.0.with_private())
+    }
+
+    /// Indicate the response will never change while it is fresh, so clients needn't
+    /// revalidate it (eg. for files with a hash in the name)
+    pub fn immutable(self) -> Self {
+        Self(self.0.with_immutable())
+    }
+}
+
+impl From<CacheControl> for headers::CacheControl {
+    fn from(cache_control: CacheControl) -> Self {
+        cache_control.0
+    }
+}
 
 /// A response to be returned to the client.
 /// You do not always need to use this struct directly as endpoints can
@@ -47,11 +163,41 @@ impl Response {
         }
     }
 
+    /// Create an empty response generated by highnoon itself, which may be replaced with an
+    /// error page
+    pub(crate) fn generated(s: StatusCode) -> Self {
+        let mut resp = Self::status(s);
+        resp.inner.extensions_mut().insert(Generated);
+        resp
+    }
+
+    /// Check if the response was generated by highnoon itself, clearing the marker
+    pub(crate) fn take_generated(&mut self) -> bool {
+        self.inner.extensions_mut().remove::<Generated>().is_some()
+    }
+
+    /// Take the task which sends the body and trailers (see [Response::trailers])
+    pub(crate) fn take_trailers_task(&mut self) -> Option<BoxFuture<'static, ()>> {
+        let task = self.inner.extensions_mut().remove::<TrailersTask>()?;
+        task.0.into_inner().ok().flatten()
+    }
+
     /// Set the status code of a response
     pub fn set_status(&mut self, s: StatusCode) {
         *self.inner.status_mut() = s;
     }
 
+    /// Set a custom reason phrase for the status line, in place of the standard one for the
+    /// status code (eg. `HTTP/1.1 200 Everything Is Fine`). Some legacy clients check this.
+    ///
+    /// Returns an error if the phrase contains control characters (other than tab). HTTP/2
+    /// has no reason phrase, so this is ignored for HTTP/2 connections.
+    pub fn set_reason(&mut self, phrase: &str) -> Result<()> {
+        let reason = ReasonPhrase::try_from(phrase.as_bytes())?;
+        self.inner.extensions_mut().insert(reason);
+        Ok(())
+    }
+
     /// Get the status code of a response
     pub fn get_status(&self) -> StatusCode {
         self.inner.status()
@@ -63,24 +209,206 @@ impl Response {
         self
     }
 
+    /// Set the body to a stream of chunks. An error from the stream aborts the response, which
+    /// the client sees as an incomplete body.
+    pub fn stream<S, B, E>(mut self, stream: S) -> Self
+    where
+        S: Stream<Item = std::result::Result<B, E>> + Send + 'static,
+        B: Into<Bytes> + 'static,
+        E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    {
+        *self.inner.body_mut() = Body::wrap_stream(stream);
+        self
+    }
+
+    /// Set the body to be fed by a [BodySender], which can write to it from another task.
+    ///
+    /// Together with [Request::body_stream](crate::Request::body_stream) this allows
+    /// full-duplex handling - the response is returned straight away, and a task produces the
+    /// response body while it consumes the request body:
+    /// ```
+    /// use futures_util::TryStreamExt;
+    /// use highnoon::{Request, Response, Result};
+    ///
+    /// async fn upper(mut req: Request<()>) -> Result<Response> {
+    ///     let mut input = req.body_stream();
+    ///     let (mut tx, resp) = Response::ok().body_channel();
+    ///
+    ///     tokio::spawn(async move {
+    ///         while let Some(chunk) = input.try_next().await? {
+    ///             tx.send(chunk.to_ascii_uppercase()).await?;
+    ///         }
+    ///         highnoon::Result::Ok(())
+    ///     });
+    ///
+    ///     Ok(resp)
+    /// }
+    /// ```
+    /// This works as expected over HTTP/2. Over HTTP/1.1 the protocol allows the response to
+    /// start before the request body has been sent, but many clients (including browsers) and
+    /// proxies don't read the response until they have finished sending the request, so the
+    /// server must not wait for the client to read output before consuming more input, or both
+    /// sides can deadlock. Also if the response ends before the request body has been read
+    /// completely, hyper closes the HTTP/1.1 connection rather than reusing it.
+    pub fn body_channel(mut self) -> (BodySender, Self) {
+        let (tx, body) = Body::channel();
+        *self.inner.body_mut() = body;
+        (BodySender { inner: tx }, self)
+    }
+
+    /// Set the body (without consuming self)
+    pub(crate) fn set_body(&mut self, body: impl Into<Body>) {
+        *self.inner.body_mut() = body.into();
+    }
+
+    /// Set the body to a string, marking it as text for the app's default text content type
+    pub(crate) fn text(mut self, body: impl Into<String>) -> Self {
+        self.inner.extensions_mut().insert(TextBody);
+        self.body(body.into())
+    }
+
+    /// Set the content type if the body is text and there isn't one already
+    pub(crate) fn set_default_text_content_type(&mut self, mime: &Mime) {
+        let is_text = self.inner.extensions_mut().remove::<TextBody>().is_some();
+        if is_text
+            && !self
+                .inner
+                .headers()
+                .contains_key(hyper::header::CONTENT_TYPE)
+        {
+            self.set_header(ContentType::from(mime.clone()));
+        }
+    }
+
+    pub(crate) fn headers_mut(&mut self) -> &mut HeaderMap {
+        self.inner.headers_mut()
+    }
+
     /// Set the body to an AsyncRead object
     pub fn reader(mut self, r: impl AsyncRead + Send + 'static) -> Self {
-        let body = Body::wrap_stream(ReaderStream::new(r));
+        let body = Body::wrap_stream(ReaderStream::with_capacity(r, READ_BUFFER_SIZE));
         *self.inner.body_mut() = body;
         self
     }
 
+    /// Send HTTP trailers after the body. Trailers are headers sent after the body (typically
+    /// to report a checksum or status of a streamed body).
+    ///
+    /// Trailers are only sent on HTTP/2 connections - hyper does not support sending trailers
+    /// over HTTP/1.1 so they will be silently dropped. The body must be set before calling this
+    /// method, and any trailers already produced by the body are merged with these. The body is
+    /// only sent once the response is returned from the app.
+    pub fn trailers(mut self, trailers: HeaderMap) -> Self {
+        // announce the trailers in the headers
+        let names = trailers
+            .keys()
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>();
+        if let Ok(value) = HeaderValue::try_from(names.join(", ")) {
+            self.inner
+                .headers_mut()
+                .insert(hyper::header::TRAILER, value);
+        }
+
+        let mut body = std::mem::take(self.inner.body_mut());
+        let (mut tx, new_body) = Body::channel();
+        *self.inner.body_mut() = new_body;
+
+        // the task is spawned by the app when it sends the response, so it runs on the app's
+        // spawner (see App::with_spawner)
+        let task = async move {
+            while let Some(chunk) = body.data().await {
+                match chunk {
+                    Ok(chunk) => {
+                        if tx.send_data(chunk).await.is_err() {
+                            // client has gone away
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        warn!("error reading body before sending trailers: {}", err);
+                        tx.abort();
+                        return;
+                    }
+                }
+            }
+
+            let mut all_trailers = match body.trailers().await {
+                Ok(Some(existing)) => existing,
+                _ => HeaderMap::new(),
+            };
+            all_trailers.extend(trailers);
+
+            let _ = tx.send_trailers(all_trailers).await;
+        };
+        self.inner
+            .extensions_mut()
+            .insert(TrailersTask(Mutex::new(Some(Box::pin(task)))));
+
+        self
+    }
+
     /// Set the body to the content of a file given by a Path
     /// Also sets a content type by guessing the mime type from the path name
+    ///
+    /// To honour conditional request headers as well, use [Request::send_file](crate::Request::send_file).
     pub async fn path(self, path: impl AsRef<Path>) -> Result<Self> {
         let target = path.as_ref();
+        let file = tokio::fs::File::open(target).await?;
+        self.file(file, target).await
+    }
+
+    /// Set the body to the content of a file like [Response::path], but memory map files of
+    /// 1MiB or more instead of reading them, which avoids a syscall and a copy for each chunk.
+    ///
+    /// Only use this for files which are never changed while the server is running. If the file
+    /// is truncated while it is being sent the whole server crashes (with `SIGBUS` on Unix), and
+    /// if it is rewritten the client may get a mix of old and new content.
+    /// (Only available with the `mmap` feature)
+    #[cfg(feature = "mmap")]
+    pub async fn path_mmap(self, path: impl AsRef<Path>) -> Result<Self> {
+        let target = path.as_ref();
+        let file = tokio::fs::File::open(target).await?;
+        self.file_mmap(file, target).await
+    }
+
+    /// Set the body to an already opened file, guessing the content type from `path`
+    pub(crate) async fn file(self, file: tokio::fs::File, path: &Path) -> Result<Self> {
+        Ok(self.guess_content_type(path).reader(file))
+    }
 
-        let reader = tokio::fs::File::open(&target).await?;
+    /// Set the body to an already opened file, memory mapping it if it is large enough. Callers
+    /// must only use this when the user has opted in to memory mapping.
+    #[cfg(feature = "mmap")]
+    pub(crate) async fn file_mmap(self, file: tokio::fs::File, path: &Path) -> Result<Self> {
+        if file.metadata().await?.len() < MMAP_THRESHOLD {
+            return self.file(file, path).await;
+        }
 
-        let mime = mime_guess::from_path(&target).first_or_text_plain();
+        let file = file.into_std().await;
+        // SAFETY: the map is only valid while no other process truncates or writes to the file,
+        // which we can't enforce. The user opted in to mapping this file with
+        // `Response::path_mmap` or `StaticFilesOptions::mmap`, whose docs require that the
+        // file is not changed while the server is running.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(self.guess_content_type(path).mapped(map))
+    }
+
+    /// Set the content type by guessing the mime type from a file name
+    pub(crate) fn guess_content_type(self, path: &Path) -> Self {
+        let mime = mime_guess::from_path(path).first_or_text_plain();
         debug!("guessed mime: {}", mime);
+        self.header(headers::ContentType::from(mime))
+    }
 
-        Ok(self.header(headers::ContentType::from(mime)).reader(reader))
+    #[cfg(feature = "mmap")]
+    fn mapped(self, map: memmap2::Mmap) -> Self {
+        let data = bytes::Bytes::from_owner(map);
+        let chunks = (0..data.len()).step_by(READ_BUFFER_SIZE).map(move |start| {
+            let end = data.len().min(start + READ_BUFFER_SIZE);
+            Ok::<_, std::convert::Infallible>(data.slice(start..end))
+        });
+        self.body(Body::wrap_stream(futures_util::stream::iter(chunks)))
     }
 
     /// Set the body of the response to a JSON payload
@@ -91,6 +419,19 @@ impl Response {
         Ok(self)
     }
 
+    /// Create a response with the given status code and a JSON body. This is handy for REST
+    /// APIs, eg. to return `201 Created` along with the new resource:
+    /// ```
+    /// # use highnoon::{Request, Response, Result, StatusCode};
+    /// # use serde_json::json;
+    /// async fn create_user(_: Request<()>) -> Result<Response> {
+    ///     Response::json_with_status(StatusCode::CREATED, json!({ "id": 42 }))
+    /// }
+    /// ```
+    pub fn json_with_status(status: StatusCode, body: impl Serialize) -> Result<Self> {
+        Self::status(status).json(body)
+    }
+
     /// Set the body of the response to form data
     pub fn form(mut self, body: impl Serialize) -> Result<Self> {
         let form = serde_urlencoded::to_string(body)?;
@@ -110,6 +451,17 @@ impl Response {
         self.inner.headers_mut().typed_insert(h);
     }
 
+    /// Set the `Cache-Control` header
+    pub fn cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.set_cache_control(cache_control);
+        self
+    }
+
+    /// Set the `Cache-Control` header (without consuming self)
+    pub fn set_cache_control(&mut self, cache_control: CacheControl) {
+        self.set_header(headers::CacheControl::from(cache_control));
+    }
+
     /// Set a raw header (from the `http` crate)
     pub fn raw_header<N, K>(mut self, name: N, key: K) -> Result<Self>
     where
@@ -136,6 +488,90 @@ impl Response {
         Ok(())
     }
 
+    /// Add a header name to the `Vary` header, keeping any names already listed. Filters which
+    /// change the response based on a request header (eg. compression using `Accept-Encoding`)
+    /// must do this so caches don't serve the response to clients which sent a different value.
+    pub fn vary(mut self, name: HeaderName) -> Self {
+        self.add_vary(name);
+        self
+    }
+
+    /// Add a header name to the `Vary` header (without consuming self)
+    pub fn add_vary(&mut self, name: HeaderName) {
+        let headers = self.inner.headers_mut();
+
+        let mut names = Vec::new();
+        for value in headers.get_all(hyper::header::VARY) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            names.extend(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_owned),
+            );
+        }
+
+        // `*` already varies on everything
+        if names
+            .iter()
+            .any(|existing| existing == "*" || existing.eq_ignore_ascii_case(name.as_str()))
+        {
+            return;
+        }
+        names.push(name.as_str().to_owned());
+
+        if let Ok(value) = HeaderValue::try_from(names.join(", ")) {
+            headers.insert(hyper::header::VARY, value);
+        }
+    }
+
+    /// Add a `Set-Cookie` header. Unlike the other header methods this appends a new
+    /// header rather than replacing any existing one, so several cookies can be set.
+    pub fn cookie(mut self, cookie: Cookie) -> Result<Self> {
+        self.set_cookie(cookie)?;
+        Ok(self)
+    }
+
+    /// Add a `Set-Cookie` header (without consuming self)
+    pub fn set_cookie(&mut self, cookie: Cookie) -> Result<()> {
+        let value = HeaderValue::try_from(cookie.to_string())?;
+        self.inner
+            .headers_mut()
+            .append(hyper::header::SET_COOKIE, value);
+        Ok(())
+    }
+
+    /// Add `Set-Cookie` headers for the changes made to a cookie jar (usually the jar from
+    /// [Request::cookies](crate::Request::cookies)). Added cookies are set, and removed cookies
+    /// are expired, while unchanged cookies are not sent again.
+    /// ```
+    /// # use highnoon::{Request, Response, Result};
+    /// # use highnoon::cookie::Cookie;
+    /// async fn consent(req: Request<()>) -> Result<Response> {
+    ///     let mut jar = req.cookies()?;
+    ///     jar.add(Cookie::new("consent", "yes"));
+    ///     jar.remove(Cookie::named("tracking"));
+    ///
+    ///     Response::ok().cookie_jar(&jar)
+    /// }
+    /// ```
+    pub fn cookie_jar(mut self, jar: &CookieJar) -> Result<Self> {
+        self.set_cookie_jar(jar)?;
+        Ok(self)
+    }
+
+    /// Add `Set-Cookie` headers for the changes made to a cookie jar (without consuming self)
+    pub fn set_cookie_jar(&mut self, jar: &CookieJar) -> Result<()> {
+        for cookie in jar.delta() {
+            self.set_cookie(cookie.clone())?;
+        }
+        Ok(())
+    }
+
     /// Consume this response and return the inner `hyper::Response`
     pub fn into_inner(self) -> hyper::Response<hyper::Body> {
         self.inner
@@ -1,17 +1,228 @@
-use crate::endpoint::Endpoint;
+//! The route matching used by [App](crate::App), for building custom dispatch.
+//!
+//! [Router] maps a method and path to any type of handler, using the same path syntax as
+//! [App::at](crate::App::at):
+//! ```
+//! use highnoon::router::{RouteMatch, Router};
+//! use highnoon::Method;
+//!
+//! let mut router = Router::new();
+//! router.add(Method::GET, "/users/:id", "get user");
+//! router.add_all("/health", "health check");
+//!
+//! match router.lookup(&Method::GET, "/users/42") {
+//!     RouteMatch::Found { handler, params } => {
+//!         assert_eq!(*handler, "get user");
+//!         assert_eq!(params.find("id"), Some("42"));
+//!     }
+//!     _ => unreachable!(),
+//! }
+//!
+//! assert!(matches!(router.lookup(&Method::POST, "/users/42"), RouteMatch::MethodNotAllowed));
+//! assert!(matches!(router.lookup(&Method::GET, "/nowhere"), RouteMatch::NotFound));
+//! ```
+use crate::endpoint::{DynEndpoint, Endpoint};
+use crate::filter::timeout::TimeoutOverride;
 use crate::state::State;
-use crate::{Request, Responder};
-use hyper::{Method, StatusCode};
-use route_recognizer::Params;
+use crate::{Error, Request, Responder, Response};
+use async_trait::async_trait;
+use headers::Allow;
+use hyper::header::HOST;
+use hyper::{Body, Method, StatusCode};
+pub use route_recognizer::Params;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-type DynEndpoint<S> = dyn Endpoint<S> + Send + Sync + 'static;
+/// Matches request paths to handlers of type `T`, by method.
+///
+/// Paths may contain parameters (`/users/:id`) and a final wildcard segment (`/files/*`).
+/// When several routes match, the most specific one wins (static segments are preferred over
+/// parameters, and parameters over wildcards).
+pub struct Router<T> {
+    methods: HashMap<Method, route_recognizer::Router<T>>,
+    all: route_recognizer::Router<T>,
+}
+
+/// The result of [Router::lookup]
+#[derive(Debug)]
+pub enum RouteMatch<'a, T> {
+    /// A route matched, and these are the parameters captured from the path
+    Found { handler: &'a T, params: Params },
+    /// The path matched routes for other methods only
+    MethodNotAllowed,
+    /// No route matched the path
+    NotFound,
+}
+
+impl<T> Default for Router<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Router<T> {
+    /// Create an empty router
+    pub fn new() -> Self {
+        Self {
+            methods: HashMap::new(),
+            all: route_recognizer::Router::new(),
+        }
+    }
+
+    /// Add a handler for requests with `method` to `path`
+    pub fn add(&mut self, method: Method, path: &str, handler: T) {
+        self.methods.entry(method).or_default().add(path, handler)
+    }
+
+    /// Add a handler for requests to `path` with any method. Handlers added for a specific
+    /// method take precedence.
+    pub fn add_all(&mut self, path: &str, handler: T) {
+        self.all.add(path, handler)
+    }
+
+    /// Find the handler for a request
+    pub fn lookup(&self, method: &Method, path: &str) -> RouteMatch<'_, T> {
+        if let Some(match_) = self
+            .methods
+            .get(method)
+            .and_then(|recog| recog.recognize(path).ok())
+        {
+            RouteMatch::Found {
+                handler: match_.handler(),
+                params: match_.params().clone(),
+            }
+        } else if let Ok(match_) = self.all.recognize(path) {
+            RouteMatch::Found {
+                handler: match_.handler(),
+                params: match_.params().clone(),
+            }
+        } else if self
+            .methods
+            .iter()
+            .filter(|(k, _)| *k != method)
+            .any(|(_, recog)| recog.recognize(path).is_ok())
+        {
+            RouteMatch::MethodNotAllowed
+        } else {
+            RouteMatch::NotFound
+        }
+    }
+
+    /// The methods with a handler for `path`, sorted by name (not counting handlers for all
+    /// methods). This is what belongs in the `Allow` header of a `405 Method Not Allowed`
+    /// response.
+    pub fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        let mut methods: Vec<_> = self
+            .methods
+            .iter()
+            .filter(|(_, recog)| recog.recognize(path).is_ok())
+            .map(|(method, _)| method.clone())
+            .collect();
+        methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        methods
+    }
 
-type Recogniser<S> = route_recognizer::Router<Box<DynEndpoint<S>>>;
+    /// The methods which have at least one handler (not counting handlers for all methods)
+    pub fn methods(&self) -> impl Iterator<Item = &Method> {
+        self.methods.keys()
+    }
+}
 
-pub(crate) struct Router<S> {
-    methods: HashMap<Method, Recogniser<S>>,
-    all: Recogniser<S>,
+/// How an [App](crate::App) handles a request whose path only matches a route once a trailing
+/// slash is added or removed (eg. `/users/` when only `/users` has a route). Set with
+/// [App::trailing_slash](crate::App::trailing_slash).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// `/users` and `/users/` are different paths, so one of them is `404 Not Found` (the
+    /// default)
+    #[default]
+    Strict,
+    /// Redirect to the path with a route using `308 Permanent Redirect`, keeping the query
+    /// string
+    Redirect,
+    /// Handle the request with the route for the other path, as if both were registered
+    Merge,
+}
+
+/// Add or remove the trailing slash of a path, or `None` for the root path
+fn toggle_trailing_slash(path: &str) -> Option<String> {
+    match path.strip_suffix('/') {
+        Some("") => None,
+        Some(stripped) => Some(stripped.to_owned()),
+        None if path.is_empty() => None,
+        None => Some(format!("{}/", path)),
+    }
+}
+
+/// A problem with a route path found by [validate_path]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    /// The path doesn't start with `/`
+    MissingLeadingSlash,
+    /// A parameter segment has no name (ie. it is just `:`)
+    EmptyParamName,
+    /// A wildcard segment (`*`) is followed by more segments - wildcards always match the rest
+    /// of the path
+    WildcardNotLast,
+}
+
+impl std::fmt::Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathError::MissingLeadingSlash => write!(f, "path does not start with '/'"),
+            PathError::EmptyParamName => write!(f, "path has a parameter with no name"),
+            PathError::WildcardNotLast => write!(f, "path has a wildcard before the last segment"),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Check a route path for obvious mistakes. [App::at](crate::App::at) logs a warning for paths
+/// which fail this check, but still adds the route.
+/// ```
+/// use highnoon::router::{validate_path, PathError};
+///
+/// assert_eq!(validate_path("/users/:id/*"), Ok(()));
+/// assert_eq!(validate_path("users/:id"), Err(PathError::MissingLeadingSlash));
+/// assert_eq!(validate_path("/users/:"), Err(PathError::EmptyParamName));
+/// assert_eq!(validate_path("/files/*/meta"), Err(PathError::WildcardNotLast));
+/// ```
+pub fn validate_path(path: &str) -> Result<(), PathError> {
+    let rest = path
+        .strip_prefix('/')
+        .ok_or(PathError::MissingLeadingSlash)?;
+
+    let mut segments = rest.split('/').peekable();
+    while let Some(segment) = segments.next() {
+        if segment == ":" {
+            return Err(PathError::EmptyParamName);
+        }
+        if segment.starts_with('*') && segments.peek().is_some() {
+            return Err(PathError::WildcardNotLast);
+        }
+    }
+
+    Ok(())
+}
+
+/// An endpoint along with the path it was registered under, which is used to find the
+/// per-route settings
+struct Entry<S> {
+    path: String,
+    ep: Box<DynEndpoint<S>>,
+}
+
+/// The routes of an [App](crate::App), which adds per-route settings, virtual hosts and
+/// the built in responses to [Router]
+pub(crate) struct Routes<S> {
+    router: Router<Entry<S>>,
+    fallback: Option<Box<DynEndpoint<S>>>,
+    method_not_allowed: Option<Box<DynEndpoint<S>>>,
+    timeouts: HashMap<String, TimeoutOverride>,
+    hosts: HashMap<String, Box<DynEndpoint<S>>>,
+    pub(crate) auto_options: bool,
+    pub(crate) trailing_slash: TrailingSlash,
 }
 
 pub(crate) struct RouteTarget<'a, S>
@@ -20,13 +231,34 @@ where
 {
     pub(crate) ep: &'a DynEndpoint<S>,
     pub(crate) params: Params,
+    pub(crate) timeout: Option<TimeoutOverride>,
+    /// The methods allowed for the path, for the `405 Method Not Allowed` and automatic
+    /// `OPTIONS` responses
+    pub(crate) allowed: Option<AllowedMethods>,
 }
 
-impl<S: State> Router<S> {
+/// The `Allow` header for the `405 Method Not Allowed` and automatic `OPTIONS` responses,
+/// stored in the request extensions
+#[derive(Clone)]
+pub(crate) struct AllowedMethods(Allow);
+
+impl<S: State> Routes<S> {
     pub(crate) fn new() -> Self {
         Self {
-            methods: HashMap::new(),
-            all: Recogniser::new(),
+            router: Router::new(),
+            fallback: None,
+            method_not_allowed: None,
+            timeouts: HashMap::new(),
+            hosts: HashMap::new(),
+            auto_options: true,
+            trailing_slash: TrailingSlash::Strict,
+        }
+    }
+
+    fn entry(path: &str, ep: impl Endpoint<S> + Sync + Send + 'static) -> Entry<S> {
+        Entry {
+            path: path.to_owned(),
+            ep: Box::new(ep),
         }
     }
 
@@ -36,54 +268,254 @@ impl<S: State> Router<S> {
         path: &str,
         ep: impl Endpoint<S> + Sync + Send + 'static,
     ) {
-        self.methods
-            .entry(method)
-            .or_insert_with(route_recognizer::Router::new)
-            .add(path, Box::new(ep))
+        self.router.add(method, path, Self::entry(path, ep))
+    }
+
+    /// Register one endpoint for several methods, sharing it between them
+    pub(crate) fn add_methods(
+        &mut self,
+        methods: &[Method],
+        path: &str,
+        ep: impl Endpoint<S> + Sync + Send + 'static,
+    ) {
+        let ep = Arc::new(ep);
+        for method in methods {
+            self.add(method.clone(), path, ep.clone());
+        }
     }
 
     pub(crate) fn add_all(&mut self, path: &str, ep: impl Endpoint<S> + Sync + Send + 'static) {
-        self.all.add(path, Box::new(ep))
+        self.router.add_all(path, Self::entry(path, ep))
     }
 
-    pub(crate) fn lookup(&self, method: &Method, path: &str) -> RouteTarget<S> {
-        if let Some(match_) = self
-            .methods
-            .get(method)
-            .and_then(|recog| recog.recognize(path).ok())
-        {
-            RouteTarget {
-                ep: &***match_.handler(),
-                params: match_.params().clone(), // TODO - avoid this clone?
+    pub(crate) fn set_fallback(&mut self, ep: impl Endpoint<S> + Sync + Send + 'static) {
+        self.fallback = Some(Box::new(ep));
+    }
+
+    pub(crate) fn set_method_not_allowed(&mut self, ep: impl Endpoint<S> + Sync + Send + 'static) {
+        self.method_not_allowed = Some(Box::new(WithAllow(ep)));
+    }
+
+    /// Handle all requests for `host` with `ep`
+    pub(crate) fn add_host(&mut self, host: &str, ep: impl Endpoint<S> + Sync + Send + 'static) {
+        self.hosts
+            .insert(normalise_host(host).to_ascii_lowercase(), Box::new(ep));
+    }
+
+    /// Find the endpoint handling a request, taking the `Host` header into account if any
+    /// hosts were added
+    pub(crate) fn route(&self, req: &hyper::Request<Body>, path: &str) -> RouteTarget<'_, S> {
+        // RFC 7231 section 4.3.7 - `OPTIONS *` asks about the server as a whole
+        if req.method() == Method::OPTIONS && req.uri().path() == "*" {
+            return RouteTarget {
+                ep: &server_options,
+                params: Params::new(),
+                timeout: None,
+                allowed: None,
+            };
+        }
+
+        if !self.hosts.is_empty() {
+            let mut hosts = req.headers().get_all(HOST).iter();
+            let host = match (hosts.next(), hosts.next()) {
+                // RFC 7230 section 5.4 - the host in an absolute-form URI overrides the header
+                (_, None) if req.uri().host().is_some() => req.uri().host(),
+                (Some(host), None) => host.to_str().ok(),
+                (None, _) => None,
+                // RFC 7230 section 5.4 - more than one Host header is a bad request
+                (Some(_), Some(_)) => {
+                    return RouteTarget {
+                        ep: &bad_request,
+                        params: Params::new(),
+                        timeout: None,
+                        allowed: None,
+                    }
+                }
+            };
+
+            if let Some(ep) =
+                host.and_then(|host| self.hosts.get(&normalise_host(host).to_ascii_lowercase()))
+            {
+                return RouteTarget {
+                    ep: &**ep,
+                    params: Params::new(),
+                    timeout: None,
+                    allowed: None,
+                };
             }
-        } else if let Ok(match_) = self.all.recognize(path) {
-            RouteTarget {
-                ep: &***match_.handler(),
-                params: match_.params().clone(), // TODO - avoid this clone?
+        }
+
+        self.lookup(req.method(), path)
+    }
+
+    /// All methods which have at least one route, plus OPTIONS
+    pub(crate) fn allowed_methods(&self) -> Allow {
+        let mut methods: Vec<_> = self.router.methods().cloned().collect();
+        if !methods.contains(&Method::OPTIONS) {
+            methods.push(Method::OPTIONS);
+        }
+        methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        methods.into_iter().collect()
+    }
+
+    /// Override the timeout for all endpoints at `path`
+    pub(crate) fn set_timeout(&mut self, path: &str, timeout: TimeoutOverride) {
+        self.timeouts.insert(path.to_owned(), timeout);
+    }
+
+    pub(crate) fn lookup(&self, method: &Method, path: &str) -> RouteTarget<'_, S> {
+        let found = self.router.lookup(method, path);
+
+        if matches!(found, RouteMatch::NotFound) && self.trailing_slash != TrailingSlash::Strict {
+            if let Some(other) = toggle_trailing_slash(path) {
+                let other_found = self.router.lookup(method, &other);
+                if !matches!(other_found, RouteMatch::NotFound) {
+                    if self.trailing_slash == TrailingSlash::Redirect {
+                        return RouteTarget {
+                            ep: &trailing_slash_redirect,
+                            params: Params::new(),
+                            timeout: None,
+                            allowed: None,
+                        };
+                    }
+                    return self.target(method, &other, other_found);
+                }
             }
-        } else if self
-            .methods
-            .iter()
-            .filter(|(k, _)| k != method)
-            .any(|(_, recog)| recog.recognize(path).is_ok())
-        {
-            RouteTarget {
-                ep: &method_not_allowed,
-                params: Params::new(),
+        }
+
+        self.target(method, path, found)
+    }
+
+    /// The methods allowed on `path`, including `OPTIONS` when it is answered automatically
+    fn path_allowed_methods(&self, path: &str) -> AllowedMethods {
+        let mut methods = self.router.allowed_methods(path);
+        if self.auto_options && !methods.contains(&Method::OPTIONS) {
+            methods.push(Method::OPTIONS);
+            methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        }
+        AllowedMethods(methods.into_iter().collect())
+    }
+
+    fn target<'a>(
+        &'a self,
+        method: &Method,
+        path: &str,
+        found: RouteMatch<'a, Entry<S>>,
+    ) -> RouteTarget<'a, S> {
+        match found {
+            RouteMatch::Found { handler, params } => RouteTarget {
+                ep: &*handler.ep,
+                params,
+                timeout: self.timeouts.get(&handler.path).copied(),
+                allowed: None,
+            },
+            RouteMatch::MethodNotAllowed if method == Method::OPTIONS && self.auto_options => {
+                RouteTarget {
+                    ep: &path_options,
+                    params: Params::new(),
+                    timeout: None,
+                    allowed: Some(self.path_allowed_methods(path)),
+                }
             }
-        } else {
-            RouteTarget {
-                ep: &not_found,
+            RouteMatch::MethodNotAllowed => RouteTarget {
+                ep: match self.method_not_allowed {
+                    Some(ref ep) => &**ep,
+                    None => &method_not_allowed,
+                },
+                params: Params::new(),
+                timeout: None,
+                allowed: Some(self.path_allowed_methods(path)),
+            },
+            RouteMatch::NotFound => RouteTarget {
+                ep: match self.fallback {
+                    Some(ref fallback) => &**fallback,
+                    None => &not_found,
+                },
                 params: Params::new(),
+                timeout: None,
+                allowed: None,
+            },
+        }
+    }
+}
+
+/// Remove the port and any trailing dot from a host name
+pub(crate) fn normalise_host(host: &str) -> &str {
+    let host = match host.strip_prefix('[') {
+        // IPv6 literal - keep the brackets
+        Some(rest) => match rest.find(']') {
+            Some(end) => &host[..end + 2],
+            None => host,
+        },
+        None => host.split(':').next().unwrap_or(host),
+    };
+    host.trim_end_matches('.')
+}
+
+async fn server_options<S: State>(req: Request<S>) -> impl Responder {
+    let allow = req.app().router().allowed_methods();
+    Response::status(StatusCode::NO_CONTENT).header(allow)
+}
+
+async fn bad_request<S: State>(_: Request<S>) -> impl Responder {
+    Response::generated(StatusCode::BAD_REQUEST)
+}
+
+/// The automatic response to `OPTIONS` for a path with no `OPTIONS` endpoint
+async fn path_options<S: State>(req: Request<S>) -> impl Responder {
+    let mut resp = Response::status(StatusCode::NO_CONTENT);
+    if let Some(AllowedMethods(allow)) = req.extensions().get::<AllowedMethods>() {
+        resp.set_header(allow.clone());
+    }
+    resp
+}
+
+async fn method_not_allowed<S: State>(req: Request<S>) -> impl Responder {
+    let mut resp = Response::generated(StatusCode::METHOD_NOT_ALLOWED);
+    if let Some(AllowedMethods(allow)) = req.extensions().get::<AllowedMethods>() {
+        resp.set_header(allow.clone());
+    }
+    resp
+}
+
+/// Wraps a custom `405 Method Not Allowed` endpoint to add the `Allow` header if the endpoint
+/// didn't set one
+struct WithAllow<E>(E);
+
+#[async_trait]
+impl<S: State, E: Endpoint<S> + Send + Sync + 'static> Endpoint<S> for WithAllow<E> {
+    async fn call(&self, req: Request<S>) -> crate::Result<Response> {
+        let allowed = req.extensions().get::<AllowedMethods>().cloned();
+        let mut resp = match self.0.call(req).await {
+            Ok(resp) | Err(Error::Http(resp)) => resp,
+            Err(err) => return Err(err),
+        };
+        if let Some(AllowedMethods(allow)) = allowed {
+            if resp.as_ref().headers().get(hyper::header::ALLOW).is_none() {
+                resp.set_header(allow);
             }
         }
+        Ok(resp)
     }
 }
 
-async fn method_not_allowed<S: State>(_: Request<S>) -> impl Responder {
-    StatusCode::METHOD_NOT_ALLOWED
+/// Redirect to the request's path with the trailing slash added or removed. The router may be
+/// matching the end of the path only (in a mounted app), but adding or removing the slash
+/// from the full path has the same effect.
+async fn trailing_slash_redirect<S: State>(req: Request<S>) -> crate::Result<Response> {
+    let uri = req.uri();
+    let location = match toggle_trailing_slash(uri.path()) {
+        Some(path) => path,
+        None => return Ok(Response::generated(StatusCode::NOT_FOUND)),
+    };
+    let location = match uri.query() {
+        Some(query) => format!("{}?{}", location, query),
+        None => location,
+    };
+
+    Response::status(StatusCode::PERMANENT_REDIRECT).raw_header(hyper::header::LOCATION, location)
 }
 
 async fn not_found<S: State>(_: Request<S>) -> impl Responder {
-    StatusCode::NOT_FOUND
+    Response::generated(StatusCode::NOT_FOUND)
 }
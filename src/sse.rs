@@ -0,0 +1,228 @@
+//! Server-Sent Events (SSE) support.
+//!
+//! Return an [Sse] from an endpoint to stream events to the client:
+//! ```
+//! use highnoon::{Request, Result};
+//! use highnoon::sse::{Event, Sse};
+//! use std::time::Duration;
+//!
+//! async fn events(_req: Request<()>) -> Result<Sse> {
+//!     let (tx, sse) = Sse::channel(16);
+//!
+//!     tokio::spawn(async move {
+//!         let mut count = 0;
+//!         // send returns an error once the client has disconnected
+//!         while tx.send(Event::new(count.to_string())).await.is_ok() {
+//!             count += 1;
+//!             tokio::time::sleep(Duration::from_secs(1)).await;
+//!         }
+//!     });
+//!
+//!     Ok(sse.keepalive(Duration::from_secs(15)))
+//! }
+//! ```
+use crate::{Error, Responder, Response, Result};
+use bytes::Bytes;
+use futures_util::stream::{self, BoxStream, Stream, StreamExt};
+use headers::{CacheControl, ContentType};
+use hyper::Body;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Sleep};
+
+/// A single event sent to the client
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    data: String,
+    event: Option<String>,
+    id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// Create an event with the given data. Multi-line data is split into several `data` fields,
+    /// which the client joins back together.
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the event type (the `event` field)
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Set the event id (the `id` field), which the client sends back in the `Last-Event-ID`
+    /// header when it reconnects
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Set how long the client should wait before reconnecting (the `retry` field)
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    fn to_bytes(&self) -> Bytes {
+        let mut out = String::new();
+        // writing to a String can't fail
+        if let Some(event) = &self.event {
+            let _ = writeln!(out, "event: {}", single_line(event));
+        }
+        if let Some(id) = &self.id {
+            let _ = writeln!(out, "id: {}", single_line(id));
+        }
+        if let Some(retry) = &self.retry {
+            let _ = writeln!(out, "retry: {}", retry.as_millis());
+        }
+        // SSE also treats a bare CR as a line break, which `str::lines` does not
+        let data = self.data.replace("\r\n", "\n").replace('\r', "\n");
+        for line in data.lines() {
+            let _ = writeln!(out, "data: {}", line);
+        }
+        if self.data.is_empty() {
+            out.push_str("data:\n");
+        }
+        out.push('\n');
+        Bytes::from(out)
+    }
+}
+
+/// Newlines would end the field early, so strip them from single line fields
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], "")
+}
+
+/// A Server-Sent Events response. Create it from a stream of events with [Sse::new], or use
+/// [Sse::channel] to push events from another task.
+pub struct Sse {
+    events: BoxStream<'static, Event>,
+    keepalive: Option<Duration>,
+}
+
+impl Sse {
+    /// Create an SSE response which sends each event from the stream, and closes the
+    /// connection when the stream ends
+    pub fn new(events: impl Stream<Item = Event> + Send + 'static) -> Self {
+        Self {
+            events: events.boxed(),
+            keepalive: None,
+        }
+    }
+
+    /// Create an SSE response fed by a channel. The channel holds at most `buffer` events, after
+    /// that [SseSender::send] waits for the client to receive them.
+    ///
+    /// The connection closes when all senders are dropped.
+    pub fn channel(buffer: usize) -> (SseSender, Self) {
+        let (tx, rx) = mpsc::channel(buffer);
+        let events = stream::unfold(rx, |mut rx| async move {
+            let event = rx.recv().await?;
+            Some((event, rx))
+        });
+        (SseSender { inner: tx }, Self::new(events))
+    }
+
+    /// Send a keepalive comment when no event has been sent for `interval`. This stops
+    /// proxies from closing idle connections.
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.keepalive = Some(interval);
+        self
+    }
+}
+
+impl Responder for Sse {
+    fn into_response(self) -> Result<Response> {
+        let stream = Keepalive {
+            events: self.events,
+            interval: self.keepalive,
+            sleep: self
+                .keepalive
+                .map(|interval| Box::pin(tokio::time::sleep(interval))),
+        };
+
+        Ok(Response::ok()
+            .header(ContentType::from(mime::TEXT_EVENT_STREAM))
+            .header(CacheControl::new().with_no_cache())
+            .body(Body::wrap_stream(stream.map(Ok::<_, Infallible>))))
+    }
+}
+
+/// Sends events into an [Sse] response created with [Sse::channel]
+#[derive(Clone)]
+pub struct SseSender {
+    inner: mpsc::Sender<Event>,
+}
+
+impl SseSender {
+    /// Send an event, waiting if the channel is full. Returns an error if the client has
+    /// disconnected, so producers should stop sending.
+    pub async fn send(&self, event: Event) -> Result<()> {
+        self.inner
+            .send(event)
+            .await
+            .map_err(|_| Error::Internal(anyhow::Error::msg("SSE client disconnected")))
+    }
+
+    /// Check if the client has disconnected
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    /// Wait until the client disconnects
+    pub async fn closed(&self) {
+        self.inner.closed().await
+    }
+}
+
+/// Encodes the events, and sends a comment whenever the event stream is idle for too long
+struct Keepalive {
+    events: BoxStream<'static, Event>,
+    interval: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Keepalive {
+    fn reset(&mut self) {
+        if let (Some(sleep), Some(interval)) = (&mut self.sleep, self.interval) {
+            sleep.as_mut().reset(Instant::now() + interval);
+        }
+    }
+}
+
+impl Stream for Keepalive {
+    type Item = Bytes;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Bytes>> {
+        match self.events.poll_next_unpin(cx) {
+            Poll::Ready(Some(event)) => {
+                self.reset();
+                return Poll::Ready(Some(event.to_bytes()));
+            }
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => {}
+        }
+
+        let idle = match &mut self.sleep {
+            Some(sleep) => sleep.as_mut().poll(cx).is_ready(),
+            None => false,
+        };
+
+        if idle {
+            self.reset();
+            Poll::Ready(Some(Bytes::from_static(b":\n\n")))
+        } else {
+            Poll::Pending
+        }
+    }
+}
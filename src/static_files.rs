@@ -1,18 +1,79 @@
 use crate::endpoint::Endpoint;
+use crate::files::{self, FileBody};
 use crate::state::State;
-use crate::{Request, Response, Result};
+use crate::{CacheControl, Request, Response, Result};
 use async_trait::async_trait;
+use headers::ContentType;
 use hyper::StatusCode;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::io;
 use std::marker::PhantomData;
-use std::path::{Component, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use tracing::{debug, warn};
 
+/// Characters to percent-encode in a file name used as a path segment
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Options for serving static files with [Route::static_files_with](crate::Route::static_files_with)
+#[derive(Clone, Debug, Default)]
+pub struct StaticFilesOptions {
+    pub(crate) autoindex: bool,
+    show_hidden: bool,
+    cache_control: Option<CacheControl>,
+    file_body: FileBody,
+}
+
+impl StaticFilesOptions {
+    /// Create the default options, which are the same as [Route::static_files](crate::Route::static_files)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Respond to requests for a directory with an HTML listing of its entries, instead of
+    /// `404 Not Found`
+    pub fn autoindex(mut self, autoindex: bool) -> Self {
+        self.autoindex = autoindex;
+        self
+    }
+
+    /// Include dotfiles (names starting with `.`) in directory listings. These are hidden by
+    /// default.
+    pub fn show_hidden(mut self, show_hidden: bool) -> Self {
+        self.show_hidden = show_hidden;
+        self
+    }
+
+    /// Set the `Cache-Control` header of file responses (but not directory listings or errors)
+    pub fn cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
+
+    /// Memory map files of 1MiB or more instead of reading them (default `false`), see
+    /// [Response::path_mmap].
+    ///
+    /// Only enable this when the served files are never changed while the server is running -
+    /// if a file is truncated while it is being sent (eg. by a deploy or log rotation) the whole
+    /// server crashes with `SIGBUS`.
+    /// (Only available with the `mmap` feature)
+    #[cfg(feature = "mmap")]
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.file_body = if mmap { FileBody::Mmap } else { FileBody::Read };
+        self
+    }
+}
+
 pub(crate) struct StaticFiles<S>
 where
     S: Send + Sync + 'static,
 {
     root: PathBuf,
     prefix: PathBuf,
+    options: StaticFilesOptions,
     _phantom: PhantomData<S>,
 }
 
@@ -28,9 +89,86 @@ where
         Self {
             root: root.into(),
             prefix,
+            options: StaticFilesOptions::default(),
             _phantom: PhantomData,
         }
     }
+
+    pub(crate) fn options(mut self, options: StaticFilesOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Generate an HTML listing of the directory `dir`, requested at `path`
+    async fn index(&self, dir: &Path, path: &str) -> Result<Response> {
+        let mut read_dir = match tokio::fs::read_dir(dir).await {
+            Ok(read_dir) => read_dir,
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                return Ok(Response::generated(StatusCode::FORBIDDEN))
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut entries = Vec::new();
+        while let Some(entry) = read_dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') && !self.options.show_hidden {
+                continue;
+            }
+            let is_dir = entry.file_type().await?.is_dir();
+            entries.push((name, is_dir));
+        }
+        entries.sort();
+
+        // links are absolute so they work whether or not the request had a trailing slash
+        let base = path.trim_end_matches('/');
+        let title = escape_html(&percent_decode_str(path).decode_utf8_lossy());
+
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Index of {0}</title>\n</head>\n<body>\n<h1>Index of {0}</h1>\n<ul>\n",
+            title
+        );
+        if dir != self.root {
+            let parent = &base[..=base.rfind('/').unwrap_or(0)];
+            html.push_str(&format!(
+                "<li><a href=\"{}\">../</a></li>\n",
+                escape_html(parent)
+            ));
+        }
+        for (name, is_dir) in entries {
+            let slash = if is_dir { "/" } else { "" };
+            html.push_str(&format!(
+                "<li><a href=\"{}/{}{}\">{}{}</a></li>\n",
+                escape_html(base),
+                utf8_percent_encode(&name, SEGMENT),
+                slash,
+                escape_html(&name),
+                slash
+            ));
+        }
+        html.push_str("</ul>\n</body>\n</html>\n");
+
+        Ok(Response::ok()
+            .header(ContentType::from(mime::TEXT_HTML_UTF_8))
+            .body(html))
+    }
+}
+
+/// Escape text for use in HTML content or a quoted attribute value
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
 }
 
 #[async_trait]
@@ -43,11 +181,22 @@ impl<S: State> Endpoint<S> for StaticFiles<S> {
         for part in path.strip_prefix(&self.prefix)?.components() {
             match part {
                 Component::Normal(component) => {
-                    target.push(component);
+                    // segments are matched before being percent-decoded, so check the decoded
+                    // segment doesn't introduce new path separators or dot segments
+                    let raw = component.to_str().unwrap_or_default();
+                    let decoded = match percent_decode_str(raw).decode_utf8() {
+                        Ok(decoded) => decoded,
+                        Err(_) => return Ok(Response::generated(StatusCode::BAD_REQUEST)),
+                    };
+                    if decoded.contains(['/', '\\', '\0']) || decoded == "." || decoded == ".." {
+                        warn!("path segment contains encoded separators or dot segments");
+                        return Ok(Response::generated(StatusCode::FORBIDDEN));
+                    }
+                    target.push(decoded.as_ref());
                 }
                 Component::Prefix(_) => {
                     // Windows path prefixes - all are forbidden
-                    return Ok(Response::status(StatusCode::FORBIDDEN));
+                    return Ok(Response::generated(StatusCode::FORBIDDEN));
                 }
                 Component::RootDir => {
                     // ignored for URLs
@@ -65,17 +214,24 @@ impl<S: State> Endpoint<S> for StaticFiles<S> {
 
         if !target.starts_with(&self.root) {
             warn!("path tried to navigate out of the static files root dir");
-            return Ok(Response::status(StatusCode::FORBIDDEN));
+            return Ok(Response::generated(StatusCode::FORBIDDEN));
         }
 
-        if !target.is_file() {
-            // small race condition - if the file is deleted between
-            // here and where we open it then we're going to return a 500
-            // instead of 404
-            warn!("path isn't a file");
-            return Ok(Response::status(StatusCode::NOT_FOUND));
+        if self.options.autoindex {
+            if let Ok(metadata) = tokio::fs::metadata(&target).await {
+                if metadata.is_dir() {
+                    return self.index(&target, req.uri().path()).await;
+                }
+            }
         }
 
-        Response::ok().path(target).await
+        let mut resp = files::send_file(req.headers(), &target, self.options.file_body).await?;
+        if let Some(cache_control) = &self.options.cache_control {
+            let status = resp.get_status();
+            if status.is_success() || status == StatusCode::NOT_MODIFIED {
+                resp.set_cache_control(cache_control.clone());
+            }
+        }
+        Ok(resp)
     }
 }
@@ -1,20 +1,59 @@
+use crate::test_client::cookie_jar::TestCookies;
 use crate::test_client::test_request::TestRequest;
+#[cfg(feature = "websocket")]
+use crate::test_client::test_websocket::TestWebSocket;
 use crate::{App, Method, State};
 use hyper::{http, Uri};
 use std::sync::Arc;
 
+mod cookie_jar;
 mod test_request;
 mod test_response;
+#[cfg(feature = "websocket")]
+mod test_websocket;
 
 /// A client that can send fake requests to an App and receive the responses back for unit
 /// and integration testing. Obtain one by calling [App::test]
 pub struct TestClient<S: State> {
     app: Arc<App<S>>,
+    cookies: Option<Arc<TestCookies>>,
 }
 
 impl<S: State> TestClient<S> {
     pub(crate) fn new(app: App<S>) -> Self {
-        Self { app: Arc::new(app) }
+        Self {
+            app: Arc::new(app),
+            cookies: None,
+        }
+    }
+
+    /// Remember cookies set by responses and send them with later requests from this client,
+    /// like a browser would. This makes it possible to test flows like logging in and then
+    /// using the session.
+    /// ```
+    /// # use highnoon::{App, Response};
+    /// # use highnoon::cookie::Cookie;
+    /// # #[tokio::main]
+    /// # async fn main() -> highnoon::Result<()> {
+    /// let mut app = App::new(());
+    /// app.at("/login")
+    ///     .post(|_req| async { Response::ok().cookie(Cookie::new("user", "steve")) });
+    /// app.at("/me").get(|req: highnoon::Request<()>| async move {
+    ///     Ok(req.cookies()?.get("user").map(|c| c.value().to_owned()).unwrap_or_default())
+    /// });
+    ///
+    /// let tc = app.test().with_cookies();
+    /// tc.post("/login").send().await?;
+    /// tc.get("/me").send().await?.assert_body("steve").await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Cookies are matched loosely: the `Domain` and `Path` attributes are respected, but
+    /// `Secure` is ignored since test requests don't use TLS.
+    pub fn with_cookies(mut self) -> Self {
+        self.cookies = Some(Arc::new(TestCookies::default()));
+        self
     }
 
     /// Prepare a GET request. Returns a TestRequest which is used to add headers and the body
@@ -57,6 +96,36 @@ impl<S: State> TestClient<S> {
         self.method(Method::DELETE, uri)
     }
 
+    /// Prepare a PATCH request. Returns a TestRequest which is used to add headers and the body
+    /// before being sent.
+    pub fn patch<U>(&self, uri: U) -> TestRequest<S>
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<hyper::http::Error>,
+    {
+        self.method(Method::PATCH, uri)
+    }
+
+    /// Prepare a HEAD request. Returns a TestRequest which is used to add headers and the body
+    /// before being sent.
+    pub fn head<U>(&self, uri: U) -> TestRequest<S>
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<hyper::http::Error>,
+    {
+        self.method(Method::HEAD, uri)
+    }
+
+    /// Prepare an OPTIONS request. Returns a TestRequest which is used to add headers and the body
+    /// before being sent.
+    pub fn options<U>(&self, uri: U) -> TestRequest<S>
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<hyper::http::Error>,
+    {
+        self.method(Method::OPTIONS, uri)
+    }
+
     /// Prepare request with the given HTTP method. Returns a TestRequest which is used to add headers
     /// and the body before being sent.
     pub fn method<U>(&self, method: Method, uri: U) -> TestRequest<S>
@@ -67,6 +136,48 @@ impl<S: State> TestClient<S> {
         TestRequest::new(
             self.app.clone(),
             http::request::Builder::new().method(method).uri(uri),
+            self.cookies.clone(),
         )
     }
+
+    /// Open a websocket connection to the App, for testing websocket handlers (eg. those added
+    /// with [Route::ws](crate::Route::ws)). The handshake is made over an in-memory connection,
+    /// and an error is returned if the App doesn't accept the upgrade.
+    /// ```
+    /// # use highnoon::{App, Message};
+    /// # #[tokio::main]
+    /// # async fn main() -> highnoon::Result<()> {
+    /// let mut app = App::new(());
+    /// app.at("/echo").ws_echo();
+    ///
+    /// let mut ws = app.test().ws("/echo").await?;
+    /// ws.send(Message::text("hello")).await?;
+    /// assert_eq!(ws.recv().await?, Some(Message::text("hello")));
+    /// # Ok(())
+    /// # }
+    /// ```
+    /// (Only available with the `websocket` feature)
+    #[cfg(feature = "websocket")]
+    pub async fn ws(&self, uri: &str) -> crate::Result<TestWebSocket> {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+
+        tokio::spawn(test_websocket::serve(self.app.clone(), server_io));
+
+        // tungstenite needs an absolute URI
+        let url = if uri.starts_with('/') {
+            format!("ws://localhost{}", uri)
+        } else {
+            uri.to_owned()
+        };
+        let mut req = url.into_client_request()?;
+        if let Some(cookies) = &self.cookies {
+            let uri = req.uri().clone();
+            cookies.add_to_request(&uri, req.headers_mut());
+        }
+
+        let (ws, _) = tokio_tungstenite::client_async(req, client_io).await?;
+        Ok(TestWebSocket::new(ws))
+    }
 }
@@ -0,0 +1,111 @@
+use cookie::time::OffsetDateTime;
+use cookie::{Cookie, CookieJar};
+use hyper::header::{COOKIE, HOST, SET_COOKIE};
+use hyper::{HeaderMap, Uri};
+use std::sync::Mutex;
+use tracing::debug;
+
+/// Cookies remembered by a [TestClient](super::TestClient) created with
+/// [TestClient::with_cookies](super::TestClient::with_cookies).
+///
+/// Matching is loose: the `Domain` and `Path` attributes are checked, but `Secure` is ignored
+/// (test requests are never sent over TLS) and cookies are keyed by name only.
+#[derive(Default)]
+pub(crate) struct TestCookies {
+    jar: Mutex<CookieJar>,
+}
+
+impl TestCookies {
+    /// Add the matching cookies to a request's `Cookie` header
+    pub(crate) fn add_to_request(&self, uri: &Uri, headers: &mut HeaderMap) {
+        let host = uri
+            .host()
+            .or_else(|| headers.get(HOST).and_then(|host| host.to_str().ok()))
+            .map(|host| host.split(':').next().unwrap_or_default().to_owned());
+
+        let jar = self.jar.lock().unwrap();
+        let mut matching: Vec<_> = jar
+            .iter()
+            .filter(|cookie| domain_matches(cookie, host.as_deref()))
+            .filter(|cookie| path_matches(cookie, uri.path()))
+            .collect();
+        // like browsers, send cookies with longer paths first
+        matching.sort_by_key(|cookie| {
+            (
+                std::cmp::Reverse(cookie.path().map_or(0, str::len)),
+                cookie.name(),
+            )
+        });
+
+        let mut pairs: Vec<String> = matching
+            .into_iter()
+            .map(|cookie| cookie.stripped().to_string())
+            .collect();
+        if pairs.is_empty() {
+            return;
+        }
+
+        // keep any cookies set explicitly on the request
+        if let Some(existing) = headers.get(COOKIE).and_then(|value| value.to_str().ok()) {
+            pairs.insert(0, existing.to_owned());
+        }
+        let value = pairs.join("; ");
+        debug!(%value, "test client sending cookies");
+        if let Ok(value) = value.parse() {
+            headers.insert(COOKIE, value);
+        }
+    }
+
+    /// Remember the cookies set by a response, and forget the ones it removes
+    pub(crate) fn store_from_response(&self, headers: &HeaderMap) {
+        let mut jar = self.jar.lock().unwrap();
+        for value in headers.get_all(SET_COOKIE) {
+            let cookie = match value.to_str().map(Cookie::parse) {
+                Ok(Ok(cookie)) => cookie.into_owned(),
+                _ => continue,
+            };
+
+            if is_expired(&cookie) {
+                jar.force_remove(&cookie);
+            } else {
+                jar.add(cookie);
+            }
+        }
+    }
+}
+
+fn is_expired(cookie: &Cookie) -> bool {
+    let max_age_expired = cookie
+        .max_age()
+        .is_some_and(|max_age| max_age.is_zero() || max_age.is_negative());
+    let expires_passed = cookie
+        .expires_datetime()
+        .is_some_and(|expires| expires <= OffsetDateTime::now_utc());
+    max_age_expired || expires_passed
+}
+
+fn domain_matches(cookie: &Cookie, host: Option<&str>) -> bool {
+    match (cookie.domain(), host) {
+        (Some(domain), Some(host)) => {
+            let domain = domain.trim_start_matches('.');
+            host.eq_ignore_ascii_case(domain)
+                || host
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", domain.to_ascii_lowercase()))
+        }
+        // requests without a host (eg. `/path`) only get host-only cookies
+        (Some(_), None) => false,
+        (None, _) => true,
+    }
+}
+
+fn path_matches(cookie: &Cookie, path: &str) -> bool {
+    let cookie_path = match cookie.path() {
+        Some(cookie_path) => cookie_path,
+        None => return true,
+    };
+    match path.strip_prefix(cookie_path) {
+        Some(rest) => cookie_path.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
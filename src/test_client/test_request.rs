@@ -2,10 +2,13 @@ use crate::Result;
 use crate::{App, State};
 use headers::{Header, HeaderMapExt};
 use hyper::header::{HeaderName, HeaderValue};
-use hyper::{http, Body, HeaderMap};
+use hyper::http::uri::PathAndQuery;
+use hyper::{http, Body, HeaderMap, Uri};
 use serde::Serialize;
+use std::net::SocketAddr;
 use std::sync::Arc;
 //use crate::test_client::into_body::IntoBody;
+use crate::test_client::cookie_jar::TestCookies;
 use crate::test_client::test_response::TestResponse;
 
 enum PartialReq {
@@ -20,13 +23,21 @@ enum PartialReq {
 pub struct TestRequest<S: State> {
     app: Arc<App<S>>,
     req: PartialReq,
+    remote_addr: SocketAddr,
+    cookies: Option<Arc<TestCookies>>,
 }
 
 impl<S: State> TestRequest<S> {
-    pub(crate) fn new(app: Arc<App<S>>, builder: http::request::Builder) -> Self {
+    pub(crate) fn new(
+        app: Arc<App<S>>,
+        builder: http::request::Builder,
+        cookies: Option<Arc<TestCookies>>,
+    ) -> Self {
         Self {
             app,
             req: PartialReq::Builder(builder),
+            remote_addr: "127.0.0.1:8080".parse().expect("socket addr is invalid?"),
+            cookies,
         }
     }
 
@@ -55,6 +66,28 @@ impl<S: State> TestRequest<S> {
         Ok(self)
     }
 
+    /// Serialize `data` into a query string and append it to the request URI. If the URI
+    /// already has a query string the new parameters are added to the end of it.
+    pub fn query(mut self, data: impl Serialize) -> Result<Self> {
+        let query = serde_urlencoded::to_string(data)?;
+
+        self.req = match self.req {
+            PartialReq::Builder(b) => match b.uri_ref() {
+                Some(uri) => {
+                    let uri = append_query(uri.clone(), &query)?;
+                    PartialReq::Builder(b.uri(uri))
+                }
+                // the builder already has an error which will be reported on send
+                None => PartialReq::Builder(b),
+            },
+            PartialReq::Request(mut req) => {
+                *req.uri_mut() = append_query(req.uri().clone(), &query)?;
+                PartialReq::Request(req)
+            }
+        };
+        Ok(self)
+    }
+
     /// Add a body to this request.
     pub fn body(mut self, body: impl Into<Body>) -> Result<Self> {
         self.req = match self.req {
@@ -70,18 +103,60 @@ impl<S: State> TestRequest<S> {
     /// to `application/json`
     pub fn json(self, data: impl Serialize) -> Result<Self> {
         let body = serde_json::to_string(&data)?;
-        self.body(body)
+        self.header(headers::ContentType::json()).body(body)
+    }
+
+    /// Add a form encoded body to this request, and set the `Content-Type` header
+    /// to `application/x-www-form-urlencoded`
+    pub fn form(self, data: impl Serialize) -> Result<Self> {
+        let body = serde_urlencoded::to_string(data)?;
+        self.header(headers::ContentType::form_url_encoded())
+            .body(body)
+    }
+
+    /// Set the address of the remote peer seen by the App (the default is `127.0.0.1:8080`)
+    pub fn remote_addr(mut self, addr: SocketAddr) -> Self {
+        self.remote_addr = addr;
+        self
     }
 
     /// Send the request to the App and receive the response.
     pub async fn send(self) -> Result<TestResponse> {
-        let req = match self.req {
+        let mut req = match self.req {
             PartialReq::Builder(b) => b.body(Body::empty())?,
             PartialReq::Request(r) => r,
         };
 
-        let addr = "127.0.0.1:8080".parse().expect("socket addr is invalid?");
-        let resp = App::serve_one_req(self.app, req, addr).await?;
+        if let Some(cookies) = &self.cookies {
+            let uri = req.uri().clone();
+            cookies.add_to_request(&uri, req.headers_mut());
+        }
+
+        let resp = App::serve_one_req(self.app, req, self.remote_addr).await?;
+
+        if let Some(cookies) = &self.cookies {
+            cookies.store_from_response(resp.headers());
+        }
         Ok(TestResponse::from(resp))
     }
 }
+
+fn append_query(uri: Uri, query: &str) -> Result<Uri> {
+    if query.is_empty() {
+        return Ok(uri);
+    }
+
+    let mut parts = uri.into_parts();
+    let path_and_query = match &parts.path_and_query {
+        Some(pq) => match pq.query() {
+            Some(existing) if !existing.is_empty() => {
+                format!("{}?{}&{}", pq.path(), existing, query)
+            }
+            _ => format!("{}?{}", pq.path(), query),
+        },
+        None => format!("/?{}", query),
+    };
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query)?);
+
+    Ok(Uri::from_parts(parts)?)
+}
@@ -1,6 +1,9 @@
 use crate::{Result, StatusCode};
-use hyper::{body::Buf, Body, Response};
+use bytes::Bytes;
+use hyper::body::HttpBody;
+use hyper::{body::Buf, Body, HeaderMap, Response};
 use serde::de::DeserializeOwned;
+use serde_json::Value;
 
 /// The response returned from the test client
 /// This currently has an AsRef implementation to get the inner hyper response
@@ -34,6 +37,22 @@ impl TestResponse {
         Ok(bytes.to_vec())
     }
 
+    /// Get the next chunk of the response body as it arrives. Returns `None` when the body
+    /// is complete. This is useful for testing streaming responses which may never end.
+    pub async fn next_chunk(&mut self) -> Option<Result<Bytes>> {
+        self.inner
+            .body_mut()
+            .data()
+            .await
+            .map(|chunk| chunk.map_err(Into::into))
+    }
+
+    /// Get the trailers sent after the body. This reads (and discards) any remaining body data.
+    pub async fn trailers(&mut self) -> Result<Option<HeaderMap>> {
+        while self.next_chunk().await.transpose()?.is_some() {}
+        Ok(self.inner.body_mut().trailers().await?)
+    }
+
     /// Get the request body by decoding JSON. Any type that implements Deserialize can be used.
     pub async fn body_json<T: DeserializeOwned>(&mut self) -> Result<T> {
         let buffer = hyper::body::aggregate(self.inner.body_mut()).await?;
@@ -42,6 +61,81 @@ impl TestResponse {
     }
 }
 
+/// Assertion helpers. These panic with a descriptive message (including the response body)
+/// on failure, and return `&mut Self` so they can be chained.
+impl TestResponse {
+    /// Read the whole body into memory, and then put it back so it can be read again
+    async fn buffer_body(&mut self) -> Bytes {
+        let bytes = hyper::body::to_bytes(self.inner.body_mut())
+            .await
+            .expect("error reading response body");
+        *self.inner.body_mut() = Body::from(bytes.clone());
+        bytes
+    }
+
+    /// Assert that the response has the given status code
+    pub async fn assert_status(&mut self, status: StatusCode) -> &mut Self {
+        if self.status() != status {
+            let body = self.buffer_body().await;
+            panic!(
+                "expected status {} but got {}\nbody: {}",
+                status,
+                self.status(),
+                String::from_utf8_lossy(&body)
+            );
+        }
+        self
+    }
+
+    /// Assert that the response has a header with the given value
+    pub fn assert_header(&mut self, name: &str, value: &str) -> &mut Self {
+        let actual = self.inner.headers().get(name);
+        if actual.map(|v| v.as_bytes()) != Some(value.as_bytes()) {
+            panic!(
+                "expected header {}: {} but got {:?}\nheaders: {:?}",
+                name,
+                value,
+                actual,
+                self.inner.headers()
+            );
+        }
+        self
+    }
+
+    /// Assert that the response body is exactly `text`
+    pub async fn assert_body(&mut self, text: &str) -> &mut Self {
+        let body = self.buffer_body().await;
+        if body != text.as_bytes() {
+            panic!(
+                "expected body {:?} but got {:?}",
+                text,
+                String::from_utf8_lossy(&body)
+            );
+        }
+        self
+    }
+
+    /// Assert that the response body is JSON equal to `expected`
+    pub async fn assert_json(&mut self, expected: Value) -> &mut Self {
+        let body = self.buffer_body().await;
+        match serde_json::from_slice::<Value>(&body) {
+            Ok(actual) if actual == expected => {}
+            Ok(actual) => panic!(
+                "expected json body {} but got {}",
+                serde_json::to_string_pretty(&expected).unwrap_or_default(),
+                serde_json::to_string_pretty(&actual).unwrap_or_default()
+            ),
+            Err(err) => panic!(
+                "expected json body {} but body is not valid json ({})\nbody: {}",
+                expected,
+                err,
+                String::from_utf8_lossy(&body)
+            ),
+        }
+        self
+    }
+}
+
 impl AsRef<hyper::Response<Body>> for TestResponse {
     fn as_ref(&self) -> &hyper::Response<Body> {
         &self.inner
@@ -0,0 +1,60 @@
+use crate::{App, Result, State};
+use futures_util::{FutureExt, SinkExt, StreamExt};
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::Body;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::DuplexStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// A websocket connection to an App, for testing websocket handlers. Obtain one by calling
+/// [TestClient::ws](super::TestClient::ws).
+pub struct TestWebSocket {
+    inner: WebSocketStream<DuplexStream>,
+}
+
+impl TestWebSocket {
+    pub(crate) fn new(inner: WebSocketStream<DuplexStream>) -> Self {
+        Self { inner }
+    }
+
+    /// Send a message to the handler
+    pub async fn send(&mut self, msg: Message) -> Result<()> {
+        self.inner.send(msg).await?;
+        Ok(())
+    }
+
+    /// Receive a message from the handler. Returns `None` when the connection is closed.
+    /// Pings from the server are answered automatically, but still returned.
+    pub async fn recv(&mut self) -> Result<Option<Message>> {
+        Ok(self.inner.next().await.transpose()?)
+    }
+
+    /// Start the closing handshake. Keep calling [TestWebSocket::recv] until it returns `None`
+    /// to wait for the handler to finish closing.
+    pub async fn close(&mut self) -> Result<()> {
+        self.inner.close(None).await?;
+        Ok(())
+    }
+}
+
+/// Serve the App over one end of an in-memory connection, with upgrades enabled
+pub(crate) fn serve<S: State>(app: Arc<App<S>>, io: DuplexStream) -> impl Future<Output = ()> {
+    let addr: SocketAddr = "127.0.0.1:8080".parse().expect("socket addr is invalid?");
+    let service = service_fn(move |req: hyper::Request<Body>| {
+        App::serve_one_req(app.clone(), req, addr).map(|res| res.map_err(|err| err.into_std()))
+    });
+
+    Http::new()
+        .http1_only(true)
+        .serve_connection(io, service)
+        .with_upgrades()
+        .map(|res| {
+            if let Err(err) = res {
+                tracing::debug!("test websocket connection failed: {}", err);
+            }
+        })
+}
@@ -0,0 +1,245 @@
+//! TLS listener support, using rustls.
+//!
+//! Only compiled with the `tls` feature.
+use futures_util::future::BoxFuture;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use hyper::server::accept::Accept;
+use hyper::server::conn::{AddrIncoming, AddrStream};
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tracing::warn;
+
+/// How long a client has to complete the TLS handshake before the connection is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Load a certificate chain and private key from PEM files (eg. as issued by Let's Encrypt)
+/// and build a `rustls::ServerConfig` for [App::listen_tls](crate::App::listen_tls).
+///
+/// The certificate file holds the server certificate followed by any intermediates, and the key
+/// file holds a PKCS#8, PKCS#1 (RSA) or SEC1 (EC) private key. The config offers HTTP/2 and
+/// HTTP/1.1 with ALPN, and does not ask clients for certificates - build the config yourself
+/// for anything more involved.
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// let config = highnoon::tls_config_from_pem("cert.pem", "key.pem")?;
+/// highnoon::App::new(()).listen_tls("0.0.0.0:443", config).await
+/// # }
+/// ```
+/// (Only available with the `tls` feature)
+pub fn tls_config_from_pem(
+    cert_path: impl AsRef<Path>,
+    key_path: impl AsRef<Path>,
+) -> anyhow::Result<ServerConfig> {
+    let cert_path = cert_path.as_ref();
+    let key_path = key_path.as_ref();
+
+    let certs: Vec<_> = read_pem(cert_path)?
+        .into_iter()
+        .filter(|pem| pem.tag == "CERTIFICATE")
+        .map(|pem| Certificate(pem.contents))
+        .collect();
+    if certs.is_empty() {
+        anyhow::bail!("no certificates found in {}", cert_path.display());
+    }
+
+    let key = read_pem(key_path)?
+        .into_iter()
+        .find(|pem| {
+            matches!(
+                pem.tag.as_str(),
+                "PRIVATE KEY" | "RSA PRIVATE KEY" | "EC PRIVATE KEY"
+            )
+        })
+        .map(|pem| PrivateKey(pem.contents))
+        .ok_or_else(|| anyhow::anyhow!("no private key found in {}", key_path.display()))?;
+
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Read all the PEM blocks in a file
+fn read_pem(path: &Path) -> anyhow::Result<Vec<pem::Pem>> {
+    let data = std::fs::read(path)
+        .map_err(|err| anyhow::anyhow!("failed to read {}: {}", path.display(), err))?;
+    pem::parse_many(data)
+        .map_err(|err| anyhow::anyhow!("invalid PEM in {}: {}", path.display(), err))
+}
+
+/// Details of a client certificate presented during the TLS handshake.
+///
+/// Only available when the `rustls::ServerConfig` asks clients for a certificate (eg. using
+/// `AllowAnyAuthenticatedClient`), in which case rustls has already verified the certificate
+/// against the configured roots. See [Request::client_cert](crate::Request::client_cert).
+#[derive(Debug)]
+pub struct CertificateInfo {
+    chain: Vec<Vec<u8>>,
+    subject: String,
+    issuer: String,
+    fingerprint: String,
+}
+
+impl CertificateInfo {
+    fn from_chain(chain: Vec<Vec<u8>>) -> Option<Self> {
+        let leaf = chain.first()?;
+
+        let (_, cert) = match x509_parser::parse_x509_certificate(leaf) {
+            Ok(cert) => cert,
+            Err(err) => {
+                warn!("failed to parse client certificate: {}", err);
+                return None;
+            }
+        };
+
+        let digest = ring::digest::digest(&ring::digest::SHA256, leaf);
+        let fingerprint = digest
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            fingerprint,
+            chain,
+        })
+    }
+
+    /// The subject distinguished name of the client certificate (eg. `CN=client, O=Example`)
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// The issuer distinguished name of the client certificate
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// The SHA-256 fingerprint of the client certificate, as lowercase hex
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// The DER encoded client certificate
+    pub fn der(&self) -> &[u8] {
+        &self.chain[0]
+    }
+
+    /// The full DER encoded certificate chain sent by the client, starting with the client
+    /// certificate itself
+    pub fn chain(&self) -> &[Vec<u8>] {
+        &self.chain
+    }
+}
+
+/// Information about the TLS session, captured once per connection and added to each
+/// request's extensions.
+#[derive(Clone, Default)]
+pub(crate) struct TlsInfo {
+    pub(crate) client_cert: Option<Arc<CertificateInfo>>,
+    pub(crate) server_name: Option<Arc<str>>,
+    pub(crate) alpn_protocol: Option<Arc<str>>,
+}
+
+impl TlsInfo {
+    pub(crate) fn from_stream(stream: &TlsStream<AddrStream>) -> Self {
+        let (_, conn) = stream.get_ref();
+
+        let client_cert = conn
+            .peer_certificates()
+            .map(|certs| certs.iter().map(|cert| cert.0.clone()).collect())
+            .and_then(CertificateInfo::from_chain)
+            .map(Arc::new);
+
+        let server_name = conn.sni_hostname().map(Arc::from);
+
+        // ALPN protocol ids are bytes, but all the registered ones are ASCII
+        let alpn_protocol = conn
+            .alpn_protocol()
+            .and_then(|alpn| std::str::from_utf8(alpn).ok())
+            .map(Arc::from);
+
+        Self {
+            client_cert,
+            server_name,
+            alpn_protocol,
+        }
+    }
+}
+
+type Handshake = BoxFuture<'static, io::Result<TlsStream<AddrStream>>>;
+
+/// Accepts TCP connections and performs the TLS handshake before handing them to hyper.
+///
+/// Handshakes are run concurrently so a slow client can't stall the accept loop.
+pub(crate) struct TlsIncoming {
+    incoming: AddrIncoming,
+    acceptor: TlsAcceptor,
+    handshakes: FuturesUnordered<Handshake>,
+}
+
+impl TlsIncoming {
+    pub(crate) fn new(incoming: AddrIncoming, config: ServerConfig) -> Self {
+        Self {
+            incoming,
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            handshakes: FuturesUnordered::new(),
+        }
+    }
+
+    pub(crate) fn local_addr(&self) -> SocketAddr {
+        self.incoming.local_addr()
+    }
+}
+
+impl Accept for TlsIncoming {
+    type Conn = TlsStream<AddrStream>;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let this = self.get_mut();
+
+        // start handshakes for all pending connections
+        loop {
+            match Pin::new(&mut this.incoming).poll_accept(cx) {
+                Poll::Ready(Some(Ok(stream))) => {
+                    let accept = this.acceptor.accept(stream);
+                    this.handshakes.push(Box::pin(async move {
+                        tokio::time::timeout(HANDSHAKE_TIMEOUT, accept)
+                            .await
+                            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed out"))?
+                    }));
+                }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) if this.handshakes.is_empty() => return Poll::Ready(None),
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+
+        // return the first connection to finish its handshake
+        loop {
+            match this.handshakes.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(stream))) => return Poll::Ready(Some(Ok(stream))),
+                Poll::Ready(Some(Err(err))) => {
+                    warn!("TLS handshake failed: {}", err);
+                }
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
@@ -1,21 +1,78 @@
+use crate::app::Spawner;
 use crate::endpoint::Endpoint;
 use crate::state::State;
-use crate::{Request, Response, Result};
+use crate::{Error, Request, Response, Result};
 use async_trait::async_trait;
 use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt, TryStreamExt};
 use hyper::upgrade::Upgraded;
 use hyper::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
 use std::future::Future;
+use std::io;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio_tungstenite::tungstenite::Message;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, Mutex as AsyncMutex};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message, Result as WsResult};
 use tokio_tungstenite::WebSocketStream;
-use tracing::trace;
+use tracing::{debug, trace, warn};
+
+/// A callback which can add headers to the `101 Switching Protocols` response
+type AcceptHeaders = dyn Fn(&mut Response) + Send + Sync + 'static;
+
+/// A callback told about connections which failed to upgrade
+type UpgradeError = dyn Fn(&hyper::Error) + Send + Sync + 'static;
+
+type Sink = Arc<AsyncMutex<SplitSink<WebSocketStream<Upgraded>, Message>>>;
+
+/// How many received messages are buffered for the handler when keepalive pings are enabled
+const KEEPALIVE_BUFFER: usize = 16;
+
+/// Settings for websocket connections, set with [WsEndpoint::config] or
+/// [Route::ws_with](crate::Route::ws_with).
+/// ```
+/// # use highnoon::App;
+/// # use highnoon::ws::WsConfig;
+/// # use std::time::Duration;
+/// let mut app = App::new(());
+/// app.at("/chat").ws_with(
+///     WsConfig {
+///         ping_interval: Some(Duration::from_secs(30)),
+///         ..WsConfig::default()
+///     },
+///     |_req, _tx, _rx| async { Ok(()) },
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct WsConfig {
+    /// Send a ping to the client this often, so idle connections aren't closed by proxies and
+    /// load balancers, and dead clients are noticed. Disabled by default.
+    ///
+    /// With pings enabled, messages are read from the client on a background task (so pongs
+    /// are seen even while the handler is busy) and pongs are not passed on to the handler.
+    /// Up to 16 other messages are buffered until the handler receives them - reading stalls
+    /// while the buffer is full, so the handler must keep receiving or pongs will be missed.
+    pub ping_interval: Option<Duration>,
+    /// Close the connection if the client doesn't answer a ping within this time (default 10
+    /// seconds). The handler's [WebSocketReceiver::recv] then returns an error.
+    pub pong_timeout: Duration,
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: None,
+            pong_timeout: Duration::from_secs(10),
+        }
+    }
+}
 
 /// An endpoint for accepting a websocket connection.
 /// Typically constructed by the `Route::ws` method.
-#[derive(Debug)]
 pub struct WsEndpoint<H, F, S>
 where
     S: State + Send + Sync + 'static,
@@ -23,9 +80,88 @@ where
     F: Future<Output = Result<()>> + Send + 'static,
 {
     handler: Arc<H>,
+    metrics: Option<Arc<dyn WsMetrics>>,
+    accept_headers: Option<Box<AcceptHeaders>>,
+    config: WsConfig,
+    on_upgrade_error: Option<Arc<UpgradeError>>,
     _phantoms: PhantomData<S>,
 }
 
+impl<H, F, S> WsEndpoint<H, F, S>
+where
+    S: State + Send + Sync + 'static,
+    H: Send + Sync + 'static + Fn(Request<S>, WebSocketSender, WebSocketReceiver) -> F,
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    /// Report connections and messages on this endpoint to `metrics`.
+    /// ```
+    /// # use highnoon::{App, Result};
+    /// # use highnoon::ws::{self, WsCounters};
+    /// # use std::sync::Arc;
+    /// # fn main() -> Result<()> {
+    /// let counters = Arc::new(WsCounters::default());
+    ///
+    /// let mut app = App::new(());
+    /// app.at("/chat").get(
+    ///     ws::endpoint(|_req, _tx, _rx| async { Ok(()) }).metrics(counters.clone()),
+    /// );
+    ///
+    /// assert_eq!(counters.active(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn metrics(mut self, metrics: Arc<dyn WsMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Call `f` with the `101 Switching Protocols` response before it is sent, so it can add
+    /// headers to the handshake (eg. a cookie or a token for the client).
+    /// ```
+    /// # use highnoon::App;
+    /// # use highnoon::ws;
+    /// let mut app = App::new(());
+    /// app.at("/chat").get(
+    ///     ws::endpoint(|_req, _tx, _rx| async { Ok(()) })
+    ///         .accept_headers(|resp| resp.set_raw_header("x-chat-server", "eu-1").unwrap()),
+    /// );
+    /// ```
+    pub fn accept_headers(mut self, f: impl Fn(&mut Response) + Send + Sync + 'static) -> Self {
+        self.accept_headers = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` when a connection fails to upgrade after the `101 Switching Protocols` response
+    /// was sent (eg. because the client disconnected mid-handshake). The handler is not called
+    /// for these connections. Failures are always logged.
+    pub fn on_upgrade_error(mut self, f: impl Fn(&hyper::Error) + Send + Sync + 'static) -> Self {
+        self.on_upgrade_error = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the [WsConfig] for connections to this endpoint (eg. to send keepalive pings)
+    pub fn config(mut self, config: WsConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+impl<H, F, S> fmt::Debug for WsEndpoint<H, F, S>
+where
+    S: State + Send + Sync + 'static,
+    H: Send + Sync + 'static + Fn(Request<S>, WebSocketSender, WebSocketReceiver) -> F,
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsEndpoint")
+            .field("metrics", &self.metrics.is_some())
+            .field("accept_headers", &self.accept_headers.is_some())
+            .field("config", &self.config)
+            .field("on_upgrade_error", &self.on_upgrade_error.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
 /// Create a websocket endpoint.
 /// Typically called by the `Route::ws` method.
 pub fn endpoint<H, F, S>(handler: H) -> WsEndpoint<H, F, S>
@@ -36,10 +172,29 @@ where
 {
     WsEndpoint {
         handler: Arc::new(handler),
+        metrics: None,
+        accept_headers: None,
+        config: WsConfig::default(),
+        on_upgrade_error: None,
         _phantoms: PhantomData,
     }
 }
 
+/// Create a websocket endpoint which sends every text and binary message it receives back to
+/// the client. This is useful for health checks and testing.
+/// Typically called by the `Route::ws_echo` method.
+pub fn echo<S: State>() -> impl Endpoint<S> {
+    endpoint(|_req: Request<S>, mut tx, mut rx| async move {
+        while let Some(msg) = rx.recv().await? {
+            // pings and closes are answered by tungstenite itself
+            if msg.is_text() || msg.is_binary() {
+                tx.send(msg).await?;
+            }
+        }
+        Ok(())
+    })
+}
+
 #[async_trait]
 impl<H, F, S> Endpoint<S> for WsEndpoint<H, F, S>
 where
@@ -50,13 +205,28 @@ where
     async fn call(&self, req: Request<S>) -> Result<Response> {
         let handler = self.handler.clone();
 
-        let res = upgrade_connection(req, handler).await;
+        let res = upgrade_connection(
+            req,
+            handler,
+            self.metrics.clone(),
+            self.accept_headers.as_deref(),
+            self.config.clone(),
+            self.on_upgrade_error.clone(),
+        )
+        .await;
 
         Ok(res)
     }
 }
 
-async fn upgrade_connection<S, H, F>(mut req: Request<S>, handler: Arc<H>) -> Response
+async fn upgrade_connection<S, H, F>(
+    mut req: Request<S>,
+    handler: Arc<H>,
+    metrics: Option<Arc<dyn WsMetrics>>,
+    accept_headers: Option<&AcceptHeaders>,
+    config: WsConfig,
+    on_upgrade_error: Option<Arc<UpgradeError>>,
+) -> Response
 where
     S: State,
     H: Send + Sync + 'static + Fn(Request<S>, WebSocketSender, WebSocketReceiver) -> F,
@@ -64,19 +234,8 @@ where
 {
     // TODO - check various headers
 
-    if let Some(conn) = req.header::<headers::Connection>() {
-        if !conn.contains(hyper::header::UPGRADE) {
-            return Response::status(StatusCode::BAD_REQUEST);
-        }
-    } else {
-        return Response::status(StatusCode::BAD_REQUEST);
-    }
-
-    if let Some(upgrade) = req.header::<headers::Upgrade>() {
-        if upgrade != headers::Upgrade::websocket() {
-            return Response::status(StatusCode::BAD_REQUEST);
-        }
-    } else {
+    if !req.is_upgrade() || req.header::<headers::Upgrade>() != Some(headers::Upgrade::websocket())
+    {
         return Response::status(StatusCode::BAD_REQUEST);
     }
 
@@ -85,17 +244,28 @@ where
         None => return Response::status(StatusCode::BAD_REQUEST),
     };
 
-    let res = Response::status(StatusCode::SWITCHING_PROTOCOLS)
+    let mut res = Response::status(StatusCode::SWITCHING_PROTOCOLS)
         .header(headers::Upgrade::websocket())
         .header(headers::Connection::upgrade())
         .header(headers::SecWebsocketAccept::from(key));
 
+    if let Some(accept_headers) = accept_headers {
+        accept_headers(&mut res);
+    }
+
     trace!("upgrading connection to websocket");
 
-    tokio::spawn(async move {
-        let upgraded = hyper::upgrade::on(req.as_inner_mut())
-            .await
-            .expect("websocket upgrade failed - TODO report this error");
+    req.app().spawner().spawn(async move {
+        let upgraded = match hyper::upgrade::on(req.as_inner_mut()).await {
+            Ok(upgraded) => upgraded,
+            Err(err) => {
+                warn!("websocket upgrade failed: {}", err);
+                if let Some(on_upgrade_error) = on_upgrade_error {
+                    on_upgrade_error(&err);
+                }
+                return;
+            }
+        };
 
         let ws = WebSocketStream::from_raw_socket(
             upgraded,
@@ -104,11 +274,42 @@ where
         )
         .await;
 
+        // the connection is counted as open until both halves are dropped
+        let conn = metrics.map(|metrics| {
+            metrics.connection_opened();
+            Arc::new(Connection {
+                metrics,
+                opened: Instant::now(),
+            })
+        });
+
         let (tx, rx) = ws.split();
+        let tx = Arc::new(AsyncMutex::new(tx));
+
+        let rx = match config.ping_interval {
+            Some(interval) => {
+                let (buffer_tx, buffer_rx) = mpsc::channel(KEEPALIVE_BUFFER);
+                req.app().spawner().spawn(keepalive(
+                    rx,
+                    tx.clone(),
+                    buffer_tx,
+                    interval,
+                    config.pong_timeout,
+                ));
+                Receiving::Keepalive(buffer_rx)
+            }
+            None => Receiving::Direct(rx),
+        };
+
+        let spawner = req.app().spawner();
         let res = (handler)(
             req,
-            WebSocketSender { inner: tx },
-            WebSocketReceiver { inner: rx },
+            WebSocketSender {
+                inner: tx,
+                conn: conn.clone(),
+                spawner,
+            },
+            WebSocketReceiver { inner: rx, conn },
         )
         .await;
 
@@ -121,28 +322,344 @@ where
     res
 }
 
-/// The sending half of the websocket connection
+/// Reads messages for the handler while sending pings, until the handler drops the receiver.
+/// If a ping isn't answered in time the connection is closed and the handler is sent an error.
+async fn keepalive(
+    mut stream: SplitStream<WebSocketStream<Upgraded>>,
+    sink: Sink,
+    buffer: mpsc::Sender<WsResult<Message>>,
+    interval: Duration,
+    pong_timeout: Duration,
+) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    let mut pong_deadline = None;
+
+    loop {
+        let timeout = async {
+            match pong_deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            msg = stream.next() => match msg {
+                Some(Ok(Message::Pong(_))) => pong_deadline = None,
+                Some(msg) => {
+                    let failed = msg.is_err();
+                    // if the handler dropped the receiver the loop ends on the next iteration
+                    let _ = buffer.send(msg).await;
+                    if failed {
+                        break;
+                    }
+                }
+                None => break,
+            },
+            _ = ticker.tick() => {
+                trace!("sending websocket ping");
+                if pong_deadline.is_none() {
+                    pong_deadline = Some(tokio::time::Instant::now() + pong_timeout);
+                }
+                if sink.lock().await.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+            _ = timeout => {
+                warn!("websocket client didn't answer a ping, closing the connection");
+                let _ = sink.lock().await.send(Message::Close(None)).await;
+                let err = io::Error::new(io::ErrorKind::TimedOut, "websocket ping timed out");
+                let _ = buffer.send(Err(WsError::Io(err))).await;
+                break;
+            }
+            _ = buffer.closed() => break,
+        }
+    }
+}
+
+/// The sending half of the websocket connection.
+///
+/// Clones send on the same connection, eg. to send from a background task (see
+/// [Broadcast::forward]) while the handler carries on.
+#[derive(Clone)]
 pub struct WebSocketSender {
-    inner: SplitSink<WebSocketStream<Upgraded>, Message>,
+    inner: Sink,
+    conn: Option<Arc<Connection>>,
+    spawner: Spawner,
 }
 
 impl WebSocketSender {
     /// Send a message over the websocket
     pub async fn send(&mut self, msg: Message) -> Result<()> {
-        self.inner.send(msg).await?;
+        if let Some(conn) = &self.conn {
+            conn.metrics.message_sent(&msg);
+        }
+        self.inner.lock().await.send(msg).await?;
         Ok(())
     }
+
+    /// Send a value serialized as JSON, in a text message
+    pub async fn send_json<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.send(Message::Text(serde_json::to_string(value)?))
+            .await
+    }
+}
+
+/// Where the receiver gets messages from
+enum Receiving {
+    Direct(SplitStream<WebSocketStream<Upgraded>>),
+    /// From the [keepalive] task
+    Keepalive(mpsc::Receiver<WsResult<Message>>),
 }
 
 /// The receiving half of the websocket connection
 pub struct WebSocketReceiver {
-    inner: SplitStream<WebSocketStream<Upgraded>>,
+    inner: Receiving,
+    conn: Option<Arc<Connection>>,
 }
 
 impl WebSocketReceiver {
-    /// Receive a message from the websocket
+    /// Receive a message from the websocket. Returns `None` when the connection is closed,
+    /// or an error if it fails (including when a keepalive ping times out, see [WsConfig]).
     pub async fn recv(&mut self) -> Result<Option<Message>> {
-        let msg = self.inner.try_next().await?;
+        let msg = match &mut self.inner {
+            Receiving::Direct(stream) => stream.try_next().await?,
+            Receiving::Keepalive(buffer) => buffer.recv().await.transpose()?,
+        };
+        if let (Some(conn), Some(msg)) = (&self.conn, &msg) {
+            conn.metrics.message_received(msg);
+        }
         Ok(msg)
     }
+
+    /// Receive a message containing JSON (in either a text or binary message) and deserialize
+    /// it. Pings and pongs are skipped, and `None` is returned when the connection is closed.
+    /// Returns a `400 Bad Request` error if the message is not valid JSON for `T`.
+    pub async fn recv_json<T: DeserializeOwned>(&mut self) -> Result<Option<T>> {
+        loop {
+            let result = match self.recv().await? {
+                Some(Message::Text(text)) => serde_json::from_str(&text),
+                Some(Message::Binary(data)) => serde_json::from_slice(&data),
+                Some(Message::Ping(_)) | Some(Message::Pong(_)) | Some(Message::Frame(_)) => {
+                    continue
+                }
+                Some(Message::Close(_)) | None => return Ok(None),
+            };
+
+            return result.map(Some).map_err(|err| {
+                debug!("error parsing websocket message as json: {}", err);
+                Error::bad_request(format!("invalid json message: {}", err))
+            });
+        }
+    }
+}
+
+/// Fans out messages to many websocket connections (eg. the members of a chat room), using a
+/// `tokio::sync::broadcast` channel.
+///
+/// Keep the `Broadcast` in the app's state, and have each handler [forward](Broadcast::forward)
+/// messages to its client while it reads from the client.
+/// ```
+/// use highnoon::ws::Broadcast;
+/// use highnoon::{App, Message, Request};
+///
+/// struct State {
+///     chat: Broadcast<String>,
+/// }
+///
+/// impl highnoon::State for State {
+///     type Context = ();
+///     fn new_context(&self) {}
+/// }
+///
+/// let mut app = App::new(State {
+///     chat: Broadcast::new(100),
+/// });
+/// app.at("/chat").ws(|req: Request<State>, tx, mut rx| async move {
+///     let _forwarding = req.state().chat.forward(tx);
+///     while let Some(msg) = rx.recv().await? {
+///         if let Message::Text(text) = msg {
+///             req.state().chat.send(text);
+///         }
+///     }
+///     Ok(())
+/// });
+/// ```
+pub struct Broadcast<T> {
+    sender: broadcast::Sender<T>,
+}
+
+impl<T> Clone for Broadcast<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Broadcast<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Broadcast")
+            .field("subscribers", &self.sender.receiver_count())
+            .finish()
+    }
+}
+
+impl<T: Clone + Send + 'static> Broadcast<T> {
+    /// Create a broadcast channel. Each subscriber buffers up to `capacity` messages; slow
+    /// subscribers which fall further behind miss the oldest messages.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Send a message to all the current subscribers, returning how many there are
+    pub fn send(&self, msg: T) -> usize {
+        self.sender.send(msg).unwrap_or(0)
+    }
+
+    /// Subscribe to messages sent from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<T> {
+        self.sender.subscribe()
+    }
+
+    /// The underlying `tokio::sync::broadcast` sender
+    pub fn sender(&self) -> &broadcast::Sender<T> {
+        &self.sender
+    }
+
+    /// Subscribe, and send each message to `tx` from a background task (on the app's
+    /// [spawner](crate::App::with_spawner) runtime if one is set). Forwarding stops when
+    /// the returned [Forwarding] is dropped (eg. when the handler returns), or when sending to
+    /// the client fails. A client which falls behind misses some messages rather than
+    /// holding up the others.
+    pub fn forward(&self, mut tx: WebSocketSender) -> Forwarding
+    where
+        T: Into<Message>,
+    {
+        let mut subscription = self.subscribe();
+        let spawner = tx.spawner.clone();
+        let task = spawner.spawn(async move {
+            loop {
+                match subscription.recv().await {
+                    Ok(msg) => {
+                        if let Err(err) = tx.send(msg.into()).await {
+                            trace!("stopped forwarding broadcast: {}", err);
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!(missed, "websocket client fell behind a broadcast");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        Forwarding { task }
+    }
+}
+
+/// Forwards messages from a [Broadcast] to a websocket until it is dropped.
+/// Created by [Broadcast::forward].
+#[derive(Debug)]
+pub struct Forwarding {
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Receives events from websocket connections, for exporting to a metrics system.
+///
+/// Websocket connections outlive the request that upgraded them, so they aren't seen by
+/// filters. Attach an implementation to an endpoint with [WsEndpoint::metrics]. All the
+/// methods do nothing by default, and they are called on the connection's task so should not
+/// block. [WsCounters] is a simple implementation which keeps running totals.
+pub trait WsMetrics: Send + Sync + 'static {
+    /// A connection was upgraded to a websocket
+    fn connection_opened(&self) {}
+
+    /// A connection was closed after being open for `duration`. This is called once the handler
+    /// has dropped both the [WebSocketSender] and the [WebSocketReceiver].
+    fn connection_closed(&self, _duration: Duration) {}
+
+    /// A message is being sent to the client
+    fn message_sent(&self, _msg: &Message) {}
+
+    /// A message was received from the client
+    fn message_received(&self, _msg: &Message) {}
+}
+
+/// A [WsMetrics] implementation which counts connections and messages
+#[derive(Debug, Default)]
+pub struct WsCounters {
+    active: AtomicU64,
+    opened: AtomicU64,
+    sent: AtomicU64,
+    received: AtomicU64,
+    connected_micros: AtomicU64,
+}
+
+impl WsCounters {
+    /// The number of connections currently open
+    pub fn active(&self) -> u64 {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// The total number of connections opened
+    pub fn opened(&self) -> u64 {
+        self.opened.load(Ordering::Relaxed)
+    }
+
+    /// The total number of messages sent to clients
+    pub fn messages_sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    /// The total number of messages received from clients
+    pub fn messages_received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// The total time spent connected, summed over all closed connections
+    pub fn connected_time(&self) -> Duration {
+        Duration::from_micros(self.connected_micros.load(Ordering::Relaxed))
+    }
+}
+
+impl WsMetrics for WsCounters {
+    fn connection_opened(&self) {
+        self.opened.fetch_add(1, Ordering::Relaxed);
+        self.active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn connection_closed(&self, duration: Duration) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.connected_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    fn message_sent(&self, _msg: &Message) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn message_received(&self, _msg: &Message) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Shared by both halves of an instrumented connection, reports the close when dropped
+struct Connection {
+    metrics: Arc<dyn WsMetrics>,
+    opened: Instant,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.metrics.connection_closed(self.opened.elapsed());
+    }
 }
@@ -0,0 +1,91 @@
+#![cfg(all(feature = "client", feature = "test-client"))]
+
+use highnoon::{App, HttpClient, Json, Method, Request, Response, StatusCode};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+
+fn start_upstream() -> anyhow::Result<SocketAddr> {
+    let mut app = App::new(());
+    app.at("/data")
+        .get(|_req| async { Json(json!({ "answer": 42 })) });
+    app.at("/echo").post(|mut req: Request<()>| async move {
+        let body: Value = req.body_json().await?;
+        Response::status(StatusCode::CREATED).json(body)
+    });
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on(tcp));
+    Ok(addr)
+}
+
+struct Proxy {
+    client: HttpClient,
+    upstream: SocketAddr,
+}
+
+impl highnoon::State for Proxy {
+    type Context = ();
+    fn new_context(&self) {}
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_http_client() -> highnoon::Result<()> {
+    let upstream = start_upstream()?;
+
+    let mut app = App::new(Proxy {
+        client: HttpClient::new(),
+        upstream,
+    });
+    app.at("/proxy").get(|req: Request<Proxy>| async move {
+        let state = req.state();
+        state
+            .client
+            .get(&format!("http://{}/data", state.upstream))
+            .await
+    });
+    app.at("/answer").get(|req: Request<Proxy>| async move {
+        let state = req.state();
+        let data: Value = state
+            .client
+            .get_json(&format!("http://{}/data", state.upstream))
+            .await?;
+        Ok(data["answer"].to_string())
+    });
+
+    let tc = app.test();
+    tc.get("/proxy")
+        .send()
+        .await?
+        .assert_header("content-type", "application/json")
+        .assert_json(json!({ "answer": 42 }))
+        .await;
+    tc.get("/answer").send().await?.assert_body("42").await;
+
+    let client = HttpClient::new();
+    let resp = client
+        .send_json(
+            Method::POST,
+            &format!("http://{}/echo", upstream),
+            &json!({ "hello": "world" }),
+        )
+        .await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(
+        hyper::body::to_bytes(resp.into_body()).await?,
+        r#"{"hello":"world"}"#
+    );
+
+    // missing pages are an error for get_json
+    let missing = client
+        .get_json::<Value>(&format!("http://{}/missing", upstream))
+        .await;
+    assert!(missing.is_err());
+
+    // https needs a TLS config
+    let https = client.get(&format!("https://{}/data", upstream)).await;
+    assert!(https.is_err());
+
+    Ok(())
+}
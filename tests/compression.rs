@@ -0,0 +1,101 @@
+#![cfg(all(feature = "compression", feature = "test-client"))]
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use highnoon::{App, Request, StatusCode};
+use std::io::Write;
+
+fn make_app() -> App<()> {
+    let mut app = App::new(());
+
+    app.at("/echo").post(|mut req: Request<()>| async move {
+        let data = req.body_bytes().await?;
+        Ok(data)
+    });
+
+    app
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_gzip_body() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc
+        .post("/echo")
+        .raw_header("content-encoding", "gzip")?
+        .body(gzip(b"Hello World!"))?
+        .send()
+        .await?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "Hello World!");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_unsupported_encoding() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let resp = tc
+        .post("/echo")
+        .raw_header("content-encoding", "zstd")?
+        .body("Hello World!")?
+        .send()
+        .await?;
+
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_decompression_bomb() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let resp = tc
+        .post("/echo")
+        .raw_header("content-encoding", "gzip")?
+        .body(gzip(&vec![0; 32 * 1024 * 1024]))?
+        .send()
+        .await?;
+
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_max_decompressed_size() -> highnoon::Result<()> {
+    let mut app = make_app();
+    app.max_decompressed_size(1024);
+    let tc = app.test();
+
+    let resp = tc
+        .post("/echo")
+        .raw_header("content-encoding", "gzip")?
+        .body(gzip(&[b'a'; 1024]))?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = tc
+        .post("/echo")
+        .raw_header("content-encoding", "gzip")?
+        .body(gzip(&[b'a'; 1025]))?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    Ok(())
+}
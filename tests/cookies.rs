@@ -0,0 +1,131 @@
+#![cfg(feature = "test-client")]
+
+use highnoon::cookie::Cookie;
+use highnoon::{App, Request, Response, SignedCookies, StatusCode};
+
+struct CookieState {
+    signer: SignedCookies,
+}
+
+impl highnoon::State for CookieState {
+    type Context = ();
+
+    fn new_context(&self) {}
+}
+
+fn make_app() -> App<CookieState> {
+    let mut app = App::new(CookieState {
+        signer: SignedCookies::generate(),
+    });
+
+    app.at("/login")
+        .get(|req: Request<CookieState>| async move {
+            let mut resp = Response::ok();
+            resp.set_cookie(req.state().signer.sign(Cookie::new("user", "steve")))?;
+            resp.set_cookie(Cookie::new("plain", "value"))?;
+            Ok(resp)
+        });
+
+    app.at("/whoami")
+        .get(|req: Request<CookieState>| async move {
+            let user = req.signed_cookie(&req.state().signer, "user")?;
+            Ok(user.map(|c| c.value().to_owned()))
+        });
+
+    app.at("/prefs")
+        .get(|req: Request<CookieState>| async move {
+            let mut jar = req.cookies()?;
+            jar.add(Cookie::new("theme", "dark"));
+            jar.remove(Cookie::named("old"));
+            Response::ok().cookie_jar(&jar)
+        });
+
+    app
+}
+
+#[test]
+fn test_sign_and_verify() {
+    let signer = SignedCookies::new(&[7; 64]).unwrap();
+
+    let signed = signer.sign(Cookie::new("name", "value"));
+    assert_ne!(signed.value(), "value");
+
+    let verified = signer.verify(signed.clone()).unwrap();
+    assert_eq!(verified.value(), "value");
+
+    let other = SignedCookies::generate();
+    assert!(other.verify(signed).is_none());
+
+    assert!(signer.verify(Cookie::new("name", "value")).is_none());
+}
+
+#[test]
+fn test_short_key() {
+    assert!(SignedCookies::new(b"too short").is_err());
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_signed_cookie_roundtrip() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let resp = tc.get("/login").send().await?;
+    let cookies: Vec<_> = resp
+        .as_ref()
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|c| c.to_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(cookies.len(), 2);
+
+    let mut resp = tc
+        .get("/whoami")
+        .raw_header("cookie", &cookies[0])?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "steve");
+
+    let resp = tc
+        .get("/whoami")
+        .raw_header("cookie", "user=steve")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_cookie_jar_delta() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let resp = tc
+        .get("/prefs")
+        .raw_header("cookie", "old=1; keep=2")?
+        .send()
+        .await?;
+    let mut cookies: Vec<_> = resp
+        .as_ref()
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|c| Cookie::parse(c.to_str().unwrap().to_owned()).unwrap())
+        .collect();
+    cookies.sort_by(|a, b| a.name().cmp(b.name()));
+
+    // only the changes are sent back
+    assert_eq!(cookies.len(), 2);
+    assert_eq!(cookies[0].name(), "old");
+    assert_eq!(cookies[0].value(), "");
+    assert_eq!(
+        cookies[0].max_age(),
+        Some(highnoon::cookie::time::Duration::ZERO)
+    );
+    assert_eq!(cookies[1].name(), "theme");
+    assert_eq!(cookies[1].value(), "dark");
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+#![cfg(all(feature = "static-files", feature = "test-client"))]
+
+use highnoon::{App, StatusCode};
+use std::collections::HashMap;
+
+fn make_app() -> App<()> {
+    let mut assets: HashMap<&'static str, &'static [u8]> = HashMap::new();
+    assets.insert(
+        "hello world.txt",
+        include_bytes!("resources/static/hello world.txt"),
+    );
+    assets.insert("js/app.js", b"console.log('hi');");
+
+    let mut app = App::new(());
+    app.at("/assets/*").embedded_files(assets);
+    app
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_embedded_files() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/assets/hello%20world.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.assert_header("content-type", "text/plain")
+        .assert_header("content-length", "13");
+    assert_eq!(resp.body_string().await?, "Hello World!\n");
+
+    let mut resp = tc.get("/assets/js/app.js").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.assert_header("content-type", "text/javascript");
+
+    let resp = tc.get("/assets/missing.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_embedded_conditional() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let resp = tc.get("/assets/js/app.js").send().await?;
+    let etag = resp.as_ref().headers()["etag"].to_str().unwrap().to_owned();
+
+    let resp = tc
+        .get("/assets/js/app.js")
+        .raw_header("if-none-match", &etag)?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+    let mut resp = tc
+        .get("/assets/hello%20world.txt")
+        .raw_header("range", "bytes=6-10")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+    resp.assert_header("content-range", "bytes 6-10/13");
+    assert_eq!(resp.body_string().await?, "World");
+
+    Ok(())
+}
+
+#[cfg(feature = "rust-embed")]
+#[tokio::main]
+#[test]
+pub async fn test_rust_embed() -> highnoon::Result<()> {
+    use highnoon::RustEmbedAssets;
+
+    #[derive(rust_embed::RustEmbed)]
+    #[folder = "tests/resources/static/"]
+    struct Static;
+
+    let mut app = App::new(());
+    app.at("/static/*")
+        .embedded_files(RustEmbedAssets::<Static>::new());
+    let tc = app.test();
+
+    let mut resp = tc.get("/static/sub/nested.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let etag = resp.as_ref().headers()["etag"].to_str().unwrap().to_owned();
+    assert_eq!(etag.len(), 66); // quoted sha256
+    assert_eq!(resp.body_string().await?, "nested\n");
+
+    let resp = tc.get("/static/missing.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+#![cfg(feature = "test-client")]
+
+use highnoon::extract::{extract, Bytes, Param, RawQuery};
+use highnoon::{App, Json, StatusCode};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Deserialize, Serialize)]
+struct Item {
+    name: String,
+    count: u32,
+}
+
+fn make_app() -> App<()> {
+    let mut app = App::new(());
+
+    app.at("/items/:id").put(extract(
+        |Param(id): Param<u32>, Json(item): Json<Item>| async move {
+            Json(json!({ "id": id, "name": item.name, "count": item.count + 1 }))
+        },
+    ));
+    app.at("/bytes")
+        .post(extract(|Bytes(body)| async move { body.len().to_string() }));
+    app.at("/text")
+        .post(extract(|body: String| async move { body.to_uppercase() }));
+    app.at("/query").get(extract(|RawQuery(query)| async move {
+        query.unwrap_or_else(|| "none".to_owned())
+    }));
+
+    app
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_param_and_json() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    tc.put("/items/7")
+        .json(json!({ "name": "widget", "count": 1 }))?
+        .send()
+        .await?
+        .assert_json(json!({ "id": 7, "name": "widget", "count": 2 }))
+        .await;
+
+    // a bad parameter or body stops before the handler is called
+    tc.put("/items/seven")
+        .json(json!({ "name": "widget", "count": 1 }))?
+        .send()
+        .await?
+        .assert_status(StatusCode::BAD_REQUEST)
+        .await;
+    tc.put("/items/7")
+        .json(json!({ "name": "widget" }))?
+        .send()
+        .await?
+        .assert_status(StatusCode::BAD_REQUEST)
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_param_in_mounted_app() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/api").mount(make_app());
+    let tc = app.test();
+
+    tc.put("/api/items/7")
+        .json(json!({ "name": "widget", "count": 1 }))?
+        .send()
+        .await?
+        .assert_json(json!({ "id": 7, "name": "widget", "count": 2 }))
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_body_and_query_extractors() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    tc.post("/bytes")
+        .body(vec![0u8, 159, 146, 150])?
+        .send()
+        .await?
+        .assert_body("4")
+        .await;
+
+    tc.post("/text")
+        .body("hello")?
+        .send()
+        .await?
+        .assert_body("HELLO")
+        .await;
+    tc.post("/text")
+        .body(vec![0u8, 159, 146, 150])?
+        .send()
+        .await?
+        .assert_status(StatusCode::BAD_REQUEST)
+        .await;
+
+    tc.get("/query?a=1&b=2")
+        .send()
+        .await?
+        .assert_body("a=1&b=2")
+        .await;
+    tc.get("/query").send().await?.assert_body("none").await;
+
+    Ok(())
+}
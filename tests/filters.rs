@@ -0,0 +1,588 @@
+#![cfg(feature = "test-client")]
+
+mod common;
+
+use common::LogCapture;
+use highnoon::filter::{AuditEntry, AuditLog, Cors, Filter, Log, Next, RateLimit, Timeout};
+#[cfg(feature = "signature")]
+use highnoon::filter::{SignatureAlgorithm, VerifySignature};
+use highnoon::headers::ContentType;
+use highnoon::{App, Error, Method, Request, Response, Result, StatusCode};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+struct OrderState;
+
+impl highnoon::State for OrderState {
+    type Context = Vec<&'static str>;
+
+    fn new_context(&self) -> Self::Context {
+        vec![]
+    }
+}
+
+/// A filter that records its name in the context
+struct Named(&'static str);
+
+#[async_trait::async_trait]
+impl Filter<OrderState> for Named {
+    async fn apply(
+        &self,
+        mut req: Request<OrderState>,
+        next: Next<'_, OrderState>,
+    ) -> Result<Response> {
+        req.context_mut().push(self.0);
+        next.next(req).await
+    }
+}
+
+async fn visited(req: Request<OrderState>) -> String {
+    req.context().join(",")
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_filter_order() -> highnoon::Result<()> {
+    let mut app = App::new(OrderState);
+
+    app.at("/before").get(visited);
+    app.with(Named("one"));
+    app.with_all([Named("two"), Named("three")]);
+    app.at("/after").get(visited);
+    app.with(Named("four"));
+
+    let tc = app.test();
+
+    for path in ["/before", "/after"] {
+        tc.get(path)
+            .send()
+            .await?
+            .assert_body("one,two,three,four")
+            .await;
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_timeout() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.with(Timeout::new(Duration::from_millis(50)));
+
+    app.at("/fast").get(|req: Request<()>| async move {
+        match req.deadline() {
+            Some(_) => StatusCode::OK,
+            None => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    });
+    app.at("/slow").get(|_req| async {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        StatusCode::OK
+    });
+
+    let tc = app.test();
+
+    let resp = tc.get("/fast").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = tc.get("/slow").send().await?;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_audit_log() -> highnoon::Result<()> {
+    let entries: Arc<Mutex<Vec<AuditEntry>>> = Default::default();
+
+    let mut app = App::new(OrderState);
+    app.trust_proxies(["127.0.0.1".parse()?]);
+    app.with(Named("alice"));
+    let sink = entries.clone();
+    app.with(
+        AuditLog::new(move |entry| sink.lock().unwrap().push(entry))
+            .with_user(|req: &Request<OrderState>| req.context().first().map(|u| u.to_string())),
+    );
+    app.at("/hello").get(|_req| async { "Hello" });
+
+    let tc = app.test();
+    tc.get("/hello?x=1")
+        .raw_header("user-agent", "test-agent")?
+        .raw_header("referer", "http://example.com/")?
+        .raw_header("x-forwarded-for", "192.0.2.7")?
+        .send()
+        .await?;
+    tc.get("/missing").send().await?;
+
+    let entries = entries.lock().unwrap();
+    assert_eq!(entries.len(), 2);
+
+    assert_eq!(entries[0].user.as_deref(), Some("alice"));
+    assert_eq!(entries[0].path, "/hello");
+    assert_eq!(entries[0].status, StatusCode::OK);
+    assert_eq!(entries[0].bytes, Some(5));
+    assert_eq!(entries[0].user_agent.as_deref(), Some("test-agent"));
+    assert_eq!(entries[0].referer.as_deref(), Some("http://example.com/"));
+    assert_eq!(entries[0].remote_addr.to_string(), "127.0.0.1:8080");
+    assert_eq!(entries[0].real_ip.to_string(), "192.0.2.7");
+
+    assert_eq!(entries[1].status, StatusCode::NOT_FOUND);
+    assert_eq!(entries[1].user_agent, None);
+    assert_eq!(entries[1].real_ip.to_string(), "127.0.0.1");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_log_sizes() -> highnoon::Result<()> {
+    let logs = LogCapture::default();
+    let _guard = logs.set_default();
+
+    let mut app = App::new(());
+    app.with(Log);
+    app.at("/echo")
+        .post(|mut req: Request<()>| async move { req.body_string().await });
+    app.at("/stream").get(|_req| async {
+        let (mut tx, resp) = Response::ok().body_channel();
+        tx.send("unknown size").await?;
+        Ok(resp)
+    });
+    let tc = app.test();
+
+    tc.post("/echo")
+        .raw_header("content-length", "12")?
+        .body("Hello World!")?
+        .send()
+        .await?
+        .assert_body("Hello World!")
+        .await;
+    let output = logs.take();
+    assert!(output.contains("request_size=12"), "{}", output);
+    assert!(output.contains("response_size=12"), "{}", output);
+
+    // sizes which aren't known up front are left out
+    tc.get("/stream").send().await?;
+    let output = logs.take();
+    assert!(output.contains("status=200"), "{}", output);
+    assert!(!output.contains("request_size"), "{}", output);
+    assert!(!output.contains("response_size"), "{}", output);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_log_bodies() -> highnoon::Result<()> {
+    let _ = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_test_writer()
+        .try_init();
+
+    let mut app = App::new(());
+    app.with(Log::with_bodies(8));
+    app.at("/echo")
+        .post(|mut req: Request<()>| async move { req.body_string().await });
+    app.at("/json").get(|_req| async {
+        Response::ok().json(serde_json::json!({"message": "a json response body"}))
+    });
+
+    let tc = app.test();
+
+    // bodies shorter and longer than the capture limit arrive intact
+    for body in ["short", "a much longer body than the limit"] {
+        tc.post("/echo")
+            .header(ContentType::text())
+            .body(body)?
+            .send()
+            .await?
+            .assert_body(body)
+            .await;
+    }
+
+    // binary bodies are passed through untouched
+    tc.post("/echo")
+        .header(ContentType::octet_stream())
+        .body("not logged")?
+        .send()
+        .await?
+        .assert_body("not logged")
+        .await;
+
+    tc.get("/json")
+        .send()
+        .await?
+        .assert_json(serde_json::json!({"message": "a json response body"}))
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_timeout_override() -> highnoon::Result<()> {
+    async fn slow(_req: Request<()>) -> StatusCode {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        StatusCode::OK
+    }
+
+    let mut app = App::new(());
+    app.with(Timeout::new(Duration::from_millis(50)));
+
+    app.at("/default").get(slow);
+    app.at("/longer").timeout(Duration::from_secs(5)).get(slow);
+    app.at("/disabled").get(|req: Request<()>| async move {
+        match req.deadline() {
+            Some(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            None => slow(req).await,
+        }
+    });
+    // the override applies regardless of the order endpoints are attached
+    app.at("/disabled").no_timeout();
+
+    let tc = app.test();
+
+    let resp = tc.get("/default").send().await?;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let resp = tc.get("/longer").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = tc.get("/disabled").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+/// A filter that reads a request scoped value which the endpoint also uses
+struct ParseUser(Arc<Mutex<u32>>);
+
+struct User(String);
+
+impl ParseUser {
+    async fn user(&self, req: &mut Request<()>) -> String {
+        let calls = self.0.clone();
+        req.get_or_init(|| async move {
+            *calls.lock().unwrap() += 1;
+            User("steve".to_owned())
+        })
+        .await
+        .0
+        .clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl Filter<()> for ParseUser {
+    async fn apply(&self, mut req: Request<()>, next: Next<'_, ()>) -> Result<Response> {
+        let user = self.user(&mut req).await;
+        let mut resp = next.next(req).await?;
+        resp.set_raw_header("x-user", user)?;
+        Ok(resp)
+    }
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_request_lazy_values() -> highnoon::Result<()> {
+    let calls = Arc::new(Mutex::new(0));
+
+    let mut app = App::new(());
+    app.with(ParseUser(calls.clone()));
+    app.at("/user").get(|mut req: Request<()>| async move {
+        let user = req
+            .get_or_init(|| async { User("someone else".to_owned()) })
+            .await;
+        let name = user.0.clone();
+
+        // a failed init is not stored
+        let failed = req
+            .get_or_try_init::<u32, _, _>(|| async { Err(highnoon::Error::bad_request("no")) })
+            .await;
+        assert!(failed.is_err());
+        let number = *req.get_or_try_init(|| async { Ok(42u32) }).await?;
+
+        Ok(format!("{} {}", name, number))
+    });
+
+    let tc = app.test();
+    for _ in 0..2 {
+        tc.get("/user")
+            .send()
+            .await?
+            .assert_header("x-user", "steve")
+            .assert_body("steve 42")
+            .await;
+    }
+
+    // computed once per request
+    assert_eq!(*calls.lock().unwrap(), 2);
+
+    Ok(())
+}
+
+/// A filter which retries requests that fail with a server error
+struct Retry(usize);
+
+#[async_trait::async_trait]
+impl Filter<()> for Retry {
+    async fn apply(&self, mut req: Request<()>, next: Next<'_, ()>) -> Result<Response> {
+        for _ in 0..self.0 {
+            let attempt = req.try_clone().await?;
+            // retry server errors, whether they are returned as a response or an error
+            match next.next(attempt).await {
+                Ok(resp) | Err(Error::Http(resp)) if resp.as_ref().status().is_server_error() => {}
+                Err(Error::Internal(_)) => {}
+                result => return result,
+            }
+        }
+        next.next(req).await
+    }
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_retry_filter() -> highnoon::Result<()> {
+    let attempts = Arc::new(Mutex::new(0));
+
+    let mut app = App::new(());
+    app.with(Retry(3));
+
+    let counter = attempts.clone();
+    app.at("/flaky").post(move |mut req: Request<()>| {
+        let counter = counter.clone();
+        async move {
+            let body = req.body_string().await?;
+            let mut attempts = counter.lock().unwrap();
+            *attempts += 1;
+            match *attempts {
+                1 => return Ok(Response::status(StatusCode::SERVICE_UNAVAILABLE)),
+                2 => return Err(Error::http(StatusCode::BAD_GATEWAY)),
+                3 => return Err(Error::Internal(anyhow::anyhow!("connection reset"))),
+                _ => {}
+            }
+            Ok(Response::ok().body(format!(
+                "{} {}",
+                body,
+                req.header::<ContentType>().is_some()
+            )))
+        }
+    });
+
+    app.test()
+        .post("/flaky")
+        .header(ContentType::text())
+        .body("payload")?
+        .send()
+        .await?
+        .assert_body("payload true")
+        .await;
+    assert_eq!(*attempts.lock().unwrap(), 4);
+
+    Ok(())
+}
+
+#[cfg(feature = "signature")]
+#[tokio::main]
+#[test]
+pub async fn test_verify_signature() -> highnoon::Result<()> {
+    use hmac::{Hmac, Mac};
+
+    let mut app = App::new(());
+    app.with(VerifySignature::new(
+        "secret",
+        "x-signature",
+        SignatureAlgorithm::Sha256,
+    ));
+    app.at("/hook")
+        .post(|mut req: Request<()>| async move { req.body_string().await });
+    let tc = app.test();
+
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+    mac.update(b"payload");
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    for signature in [format!("sha256={}", hex), hex.clone()] {
+        tc.post("/hook")
+            .raw_header("x-signature", signature)?
+            .body("payload")?
+            .send()
+            .await?
+            .assert_body("payload")
+            .await;
+    }
+
+    for signature in [
+        format!("sha1={}", hex),
+        format!("sha256={}00", hex),
+        "zz".into(),
+    ] {
+        tc.post("/hook")
+            .raw_header("x-signature", signature)?
+            .body("payload")?
+            .send()
+            .await?
+            .assert_status(StatusCode::UNAUTHORIZED)
+            .await;
+    }
+
+    tc.post("/hook")
+        .raw_header("x-signature", format!("sha256={}", hex))?
+        .body("tampered")?
+        .send()
+        .await?
+        .assert_status(StatusCode::UNAUTHORIZED)
+        .await;
+    tc.post("/hook")
+        .body("payload")?
+        .send()
+        .await?
+        .assert_status(StatusCode::UNAUTHORIZED)
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_cors() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.with(
+        Cors::new()
+            .allow_origin("https://example.com")
+            .allow_methods([Method::GET, Method::POST])
+            .allow_headers([highnoon::headers::HeaderName::from_static("x-token")])
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(600)),
+    );
+    app.at("/api").get(|_req| async { "api" });
+    let tc = app.test();
+
+    // preflight requests are answered by the filter
+    let mut resp = tc
+        .options("/api")
+        .raw_header("origin", "https://example.com")?
+        .raw_header("access-control-request-method", "POST")?
+        .send()
+        .await?;
+    resp.assert_status(StatusCode::NO_CONTENT)
+        .await
+        .assert_header("access-control-allow-origin", "https://example.com")
+        .assert_header("access-control-allow-methods", "GET, POST")
+        .assert_header("access-control-allow-headers", "x-token")
+        .assert_header("access-control-allow-credentials", "true")
+        .assert_header("access-control-max-age", "600")
+        .assert_header("vary", "origin");
+
+    // simple requests get the headers added
+    let mut resp = tc
+        .get("/api")
+        .raw_header("origin", "https://example.com")?
+        .send()
+        .await?;
+    resp.assert_status(StatusCode::OK)
+        .await
+        .assert_header("access-control-allow-origin", "https://example.com")
+        .assert_header("vary", "origin");
+
+    // other origins get no CORS headers, but the response still varies by origin
+    let mut resp = tc
+        .get("/api")
+        .raw_header("origin", "https://evil.example")?
+        .send()
+        .await?;
+    assert!(!resp
+        .as_ref()
+        .headers()
+        .contains_key("access-control-allow-origin"));
+    resp.assert_header("vary", "origin");
+
+    // as do requests without an origin
+    let mut resp = tc.get("/api").send().await?;
+    assert!(!resp
+        .as_ref()
+        .headers()
+        .contains_key("access-control-allow-origin"));
+    resp.assert_header("vary", "origin");
+
+    // a wildcard origin doesn't vary
+    let mut app = App::new(());
+    app.with(Cors::new().allow_any_origin());
+    app.at("/api").get(|_req| async { "api" });
+    let mut resp = app
+        .test()
+        .get("/api")
+        .raw_header("origin", "https://example.com")?
+        .send()
+        .await?;
+    resp.assert_status(StatusCode::OK)
+        .await
+        .assert_header("access-control-allow-origin", "*");
+    assert!(!resp.as_ref().headers().contains_key("vary"));
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_rate_limit() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.with(RateLimit::new(2, 0.5));
+    app.at("/").get(|_req| async { "hello" });
+    let tc = app.test();
+
+    for _ in 0..2 {
+        tc.get("/")
+            .send()
+            .await?
+            .assert_status(StatusCode::OK)
+            .await;
+    }
+    tc.get("/")
+        .send()
+        .await?
+        .assert_status(StatusCode::TOO_MANY_REQUESTS)
+        .await
+        .assert_header("retry-after", "2");
+
+    // other clients have their own bucket
+    tc.get("/")
+        .remote_addr("127.0.0.2:8080".parse()?)
+        .send()
+        .await?
+        .assert_status(StatusCode::OK)
+        .await;
+
+    let mut app = App::new(());
+    app.with(RateLimit::new(1, 1.0).with_key_fn(|req| {
+        req.headers()
+            .get("x-api-token")
+            .and_then(|token| token.to_str().ok())
+            .unwrap_or_default()
+            .to_owned()
+    }));
+    app.at("/").get(|_req| async { "hello" });
+    let tc = app.test();
+
+    for (token, status) in [
+        ("a", StatusCode::OK),
+        ("b", StatusCode::OK),
+        ("a", StatusCode::TOO_MANY_REQUESTS),
+    ] {
+        tc.get("/")
+            .raw_header("x-api-token", token)?
+            .send()
+            .await?
+            .assert_status(status)
+            .await;
+    }
+
+    Ok(())
+}
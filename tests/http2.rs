@@ -0,0 +1,116 @@
+use futures_util::TryStreamExt;
+use highnoon::{App, Request, Response};
+use hyper::body::HttpBody;
+use hyper::{Body, Client, Method, StatusCode, Version};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+fn start_server(h2c: bool) -> anyhow::Result<SocketAddr> {
+    let mut app = App::new(());
+    if h2c {
+        app.enable_h2c();
+    }
+    app.at("/hello").get(|_req| async { "Hello" });
+    app.at("/upper").post(|mut req: Request<()>| async move {
+        let mut input = req.body_stream();
+        let (mut tx, resp) = Response::ok().body_channel();
+        tokio::spawn(async move {
+            while let Some(chunk) = input.try_next().await? {
+                tx.send(chunk.to_ascii_uppercase()).await?;
+            }
+            highnoon::Result::Ok(())
+        });
+        Ok(resp)
+    });
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on(tcp));
+    Ok(addr)
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_h2c_prior_knowledge() -> anyhow::Result<()> {
+    let addr = start_server(true)?;
+
+    let client = Client::builder().http2_only(true).build_http::<Body>();
+    let resp = client
+        .get(format!("http://{}/hello", addr).parse()?)
+        .await?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.version(), Version::HTTP_2);
+    assert_eq!(hyper::body::to_bytes(resp.into_body()).await?, "Hello");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_h2c_disabled() -> anyhow::Result<()> {
+    let addr = start_server(false)?;
+
+    let client = Client::builder().http2_only(true).build_http::<Body>();
+    let result = client.get(format!("http://{}/hello", addr).parse()?).await;
+    assert!(result.is_err());
+
+    // HTTP/1.1 still works
+    let resp = Client::new()
+        .get(format!("http://{}/hello", addr).parse()?)
+        .await?;
+    assert_eq!(resp.version(), Version::HTTP_11);
+    assert_eq!(hyper::body::to_bytes(resp.into_body()).await?, "Hello");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_h2c_upgrade_is_ignored() -> anyhow::Result<()> {
+    let addr = start_server(true)?;
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    stream
+        .write_all(
+            b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade, HTTP2-Settings, close\r\n\
+              Upgrade: h2c\r\nHTTP2-Settings: AAMAAABkAARAAAAAAAIAAAAA\r\n\r\n",
+        )
+        .await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nHello"), "{}", response);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_full_duplex() -> anyhow::Result<()> {
+    let addr = start_server(true)?;
+
+    let client = Client::builder().http2_only(true).build_http::<Body>();
+    let (mut input, body) = Body::channel();
+    let req = hyper::Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://{}/upper", addr))
+        .body(body)?;
+
+    // the response starts before the request body is finished
+    let resp = client.request(req).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let mut output = resp.into_body();
+
+    for chunk in ["first", "second"] {
+        input.send_data(chunk.into()).await?;
+        let echoed = output.data().await.expect("response ended early")?;
+        assert_eq!(echoed, chunk.to_ascii_uppercase());
+    }
+
+    drop(input);
+    assert_eq!(hyper::body::to_bytes(output).await?, "");
+
+    Ok(())
+}
@@ -0,0 +1,204 @@
+#![cfg(all(feature = "multipart", feature = "test-client"))]
+
+use highnoon::multipart::MultipartLimits;
+use highnoon::{App, Request, StatusCode};
+
+const BOUNDARY: &str = "X-HIGHNOON-BOUNDARY";
+
+fn multipart_body(fields: &[(&str, Option<&str>, &str)]) -> String {
+    let mut body = String::new();
+    for (name, file_name, data) in fields {
+        body.push_str(&format!("--{}\r\n", BOUNDARY));
+        match file_name {
+            Some(file_name) => body.push_str(&format!(
+                "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+                 Content-Type: text/plain\r\n\r\n",
+                name, file_name
+            )),
+            None => body.push_str(&format!(
+                "Content-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                name
+            )),
+        }
+        body.push_str(data);
+        body.push_str("\r\n");
+    }
+    body.push_str(&format!("--{}--\r\n", BOUNDARY));
+    body
+}
+
+fn make_app(dir: std::path::PathBuf) -> App<()> {
+    let mut app = App::new(());
+    app.at("/upload").post(move |mut req: Request<()>| {
+        let dir = dir.clone();
+        async move {
+            let mut multipart = req.multipart()?;
+            let mut out = vec![];
+
+            while let Some(field) = multipart.next_field().await? {
+                let name = field.name().unwrap_or_default().to_owned();
+                if field.file_name().is_some() {
+                    let path = dir.join(&name);
+                    let info = field.save_to(&path).await?;
+                    let saved = tokio::fs::read_to_string(&path).await?;
+                    out.push(format!(
+                        "{}: {} {} {} {:?}",
+                        name,
+                        info.file_name.unwrap_or_default(),
+                        info.content_type
+                            .map(|ct| ct.to_string())
+                            .unwrap_or_default(),
+                        info.size,
+                        saved
+                    ));
+                } else {
+                    out.push(format!("{}={}", name, field.text().await?));
+                }
+            }
+
+            highnoon::Result::Ok(out.join("\n"))
+        }
+    });
+    app
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_multipart_upload() -> highnoon::Result<()> {
+    let dir = std::env::temp_dir().join(format!("highnoon-upload-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let tc = make_app(dir.clone()).test();
+
+    let body = multipart_body(&[
+        ("title", None, "holiday"),
+        ("photo", Some("beach.txt"), "sand and sea"),
+    ]);
+    tc.post("/upload")
+        .raw_header(
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )?
+        .body(body)?
+        .send()
+        .await?
+        .assert_status(StatusCode::OK)
+        .await
+        .assert_body("title=holiday\nphoto: beach.txt text/plain 12 \"sand and sea\"")
+        .await;
+
+    tokio::fs::remove_dir_all(&dir).await?;
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_multipart_errors() -> highnoon::Result<()> {
+    let tc = make_app(std::env::temp_dir()).test();
+
+    let resp = tc
+        .post("/upload")
+        .raw_header("content-type", "text/plain")?
+        .body("hello")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let resp = tc
+        .post("/upload")
+        .raw_header("content-type", "multipart/form-data")?
+        .body("hello")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = tc
+        .post("/upload")
+        .raw_header(
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )?
+        .body("not really multipart")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_multipart_limits() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/limited").post(|mut req: Request<()>| async move {
+        let mut multipart = req.multipart_with(MultipartLimits {
+            max_fields: Some(2),
+            max_field_size: Some(8),
+            max_total_size: Some(200),
+        })?;
+        let mut count = 0;
+        while let Some(field) = multipart.next_field().await? {
+            field.bytes().await?;
+            count += 1;
+        }
+        highnoon::Result::Ok(count.to_string())
+    });
+    app.at("/total").post(|mut req: Request<()>| async move {
+        let mut multipart = req.multipart_with(MultipartLimits {
+            max_fields: None,
+            max_field_size: Some(8),
+            max_total_size: Some(400),
+        })?;
+        let mut count = 0;
+        while let Some(field) = multipart.next_field().await? {
+            field.bytes().await?;
+            count += 1;
+        }
+        highnoon::Result::Ok(count.to_string())
+    });
+    let tc = app.test();
+
+    let send_to = |path: &str, body: String| {
+        let req = tc.post(path).raw_header(
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        );
+        async move { req?.body(body)?.send().await }
+    };
+    let send = |body: String| send_to("/limited", body);
+
+    let mut resp = send(multipart_body(&[("a", None, "1"), ("b", None, "2")])).await?;
+    resp.assert_status(StatusCode::OK)
+        .await
+        .assert_body("2")
+        .await;
+
+    // too many fields
+    let resp = send(multipart_body(&[
+        ("a", None, "1"),
+        ("b", None, "2"),
+        ("c", None, "3"),
+    ]))
+    .await?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // field too large
+    let resp = send(multipart_body(&[("a", None, "more than eight bytes")])).await?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // body too large, with every field under the field limit
+    let fields: Vec<_> = (0..10).map(|i| format!("f{}", i)).collect();
+    let parts: Vec<_> = fields
+        .iter()
+        .map(|name| (name.as_str(), None, "12345678"))
+        .collect();
+    let mut resp = send_to("/total", multipart_body(&parts[..2])).await?;
+    resp.assert_status(StatusCode::OK)
+        .await
+        .assert_body("2")
+        .await;
+    let resp = send_to("/total", multipart_body(&parts)).await?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+#![cfg(feature = "test-client")]
+
+use async_trait::async_trait;
+use highnoon::router::{RouteMatch, Router};
+use highnoon::{App, Endpoint, Method, Request, Response, Result, StatusCode, TrailingSlash};
+
+/// Dispatches on a version header before the path, using a router per version
+struct Versioned {
+    v1: Router<&'static str>,
+    v2: Router<&'static str>,
+}
+
+#[async_trait]
+impl Endpoint<()> for Versioned {
+    async fn call(&self, req: Request<()>) -> Result<Response> {
+        let router = match req.headers().get("x-api-version").map(|v| v.as_bytes()) {
+            Some(b"2") => &self.v2,
+            _ => &self.v1,
+        };
+
+        match router.lookup(req.method(), req.uri().path()) {
+            RouteMatch::Found { handler, params } => Ok(Response::ok().body(format!(
+                "{} {}",
+                handler,
+                params.find("id").unwrap_or("-")
+            ))),
+            RouteMatch::MethodNotAllowed => Ok(Response::status(StatusCode::METHOD_NOT_ALLOWED)),
+            RouteMatch::NotFound => Ok(Response::status(StatusCode::NOT_FOUND)),
+        }
+    }
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_custom_dispatch() -> highnoon::Result<()> {
+    let mut v1 = Router::new();
+    v1.add(Method::GET, "/users/:id", "v1 user");
+    v1.add(Method::GET, "/users/me", "v1 me");
+
+    let mut v2 = Router::new();
+    v2.add(Method::GET, "/users/:id", "v2 user");
+    v2.add_all("/status", "v2 status");
+
+    let mut app = App::new(());
+    app.at("/*").all(Versioned { v1, v2 });
+    let tc = app.test();
+
+    tc.get("/users/7")
+        .send()
+        .await?
+        .assert_body("v1 user 7")
+        .await;
+    // static segments beat parameters
+    tc.get("/users/me")
+        .send()
+        .await?
+        .assert_body("v1 me -")
+        .await;
+    tc.get("/users/7")
+        .raw_header("x-api-version", "2")?
+        .send()
+        .await?
+        .assert_body("v2 user 7")
+        .await;
+    tc.delete("/status")
+        .raw_header("x-api-version", "2")?
+        .send()
+        .await?
+        .assert_body("v2 status -")
+        .await;
+
+    let resp = tc.delete("/users/7").send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    let resp = tc.get("/status").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+fn slash_app(mode: TrailingSlash) -> App<()> {
+    let mut app = App::new(());
+    app.trailing_slash(mode);
+    app.at("/users").get(|_req| async { "users" });
+    app.at("/users/:id/")
+        .get(|req: Request<()>| async move { req.param("id").map(|id| format!("user {}", id)) });
+    app.at("/both").get(|_req| async { "no slash" });
+    app.at("/both/").get(|_req| async { "slash" });
+    app
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_trailing_slash() -> highnoon::Result<()> {
+    let tc = slash_app(TrailingSlash::Strict).test();
+    let resp = tc.get("/users/").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let tc = slash_app(TrailingSlash::Redirect).test();
+    let mut resp = tc.get("/users/?page=2&sort=name").send().await?;
+    assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+    resp.assert_header("location", "/users?page=2&sort=name");
+
+    let mut resp = tc.post("/users/42").send().await?;
+    assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+    resp.assert_header("location", "/users/42/");
+
+    let mut resp = tc.get("/both/").send().await?;
+    assert_eq!(resp.body_string().await?, "slash");
+
+    let resp = tc.get("/nowhere/").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let tc = slash_app(TrailingSlash::Merge).test();
+    let mut resp = tc.get("/users/").send().await?;
+    assert_eq!(resp.body_string().await?, "users");
+
+    let mut resp = tc.get("/users/42").send().await?;
+    assert_eq!(resp.body_string().await?, "user 42");
+
+    let resp = tc.post("/users").send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let mut resp = tc.get("/both").send().await?;
+    assert_eq!(resp.body_string().await?, "no slash");
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use highnoon::App;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[test]
+pub fn test_spawner() -> anyhow::Result<()> {
+    let dedicated = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("dedicated")
+        .enable_all()
+        .build()?;
+
+    let main = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let mut app = App::new(());
+    app.with_spawner(dedicated.handle().clone());
+    app.at("/thread")
+        .get(|_req| async { std::thread::current().name().unwrap_or_default().to_owned() });
+
+    let response = main.block_on(async {
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = tcp.local_addr()?;
+        tokio::spawn(app.listen_on(tcp));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await?;
+        stream
+            .write_all(b"GET /thread HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok::<_, anyhow::Error>(response)
+    })?;
+
+    assert!(response.ends_with("\r\n\r\ndedicated"), "{}", response);
+
+    Ok(())
+}
@@ -0,0 +1,513 @@
+#![cfg(all(feature = "sessions", feature = "test-client"))]
+
+use async_trait::async_trait;
+use highnoon::cookie::Cookie;
+use highnoon::filter::session::{
+    HasSession, MemorySessionStore, SameSite, Session, SessionFilter, SessionStore, Sessions,
+    UrlEncodedFormat,
+};
+use highnoon::{App, Request, Response, StatusCode};
+
+struct SessionState;
+
+#[derive(Default)]
+struct Context {
+    session: Session,
+}
+
+impl highnoon::State for SessionState {
+    type Context = Context;
+
+    fn new_context(&self) -> Context {
+        Context::default()
+    }
+}
+
+impl HasSession for Context {
+    fn session(&mut self) -> &mut Session {
+        &mut self.session
+    }
+}
+
+fn make_app(filter: SessionFilter) -> App<SessionState> {
+    let mut app = App::new(SessionState);
+    app.with(filter);
+
+    app.at("/set")
+        .get(|mut req: Request<SessionState>| async move {
+            req.session().set("key".to_owned(), "value".to_owned());
+            StatusCode::OK
+        });
+
+    app.at("/set_special")
+        .get(|mut req: Request<SessionState>| async move {
+            req.session().set("key".to_owned(), SPECIAL.to_owned());
+            StatusCode::OK
+        });
+
+    app.at("/get")
+        .get(|mut req: Request<SessionState>| async move {
+            req.session().get("key").unwrap_or_default()
+        });
+
+    app
+}
+
+const SPECIAL: &str = "a&b=c\nd%20e+f \"g\" \u{e9}";
+
+fn set_cookie(resp: &hyper::Response<hyper::Body>) -> String {
+    resp.headers()
+        .get("set-cookie")
+        .expect("no set-cookie header")
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_default_cookie_attributes() -> highnoon::Result<()> {
+    let tc = make_app(SessionFilter::new(MemorySessionStore::new())).test();
+
+    let resp = tc.get("/set").send().await?;
+    let cookie = set_cookie(resp.as_ref());
+
+    assert!(cookie.starts_with("sid="));
+    assert!(cookie.contains("HttpOnly"));
+    assert!(cookie.contains("Secure"));
+    assert!(cookie.contains("SameSite=Strict"));
+    assert!(!cookie.contains("Path="));
+    assert!(!cookie.contains("Domain="));
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_cookie_attributes() -> highnoon::Result<()> {
+    let filter = SessionFilter::new(MemorySessionStore::new())
+        .with_secure(false)
+        .with_http_only(false)
+        .with_same_site(SameSite::Lax)
+        .with_path("/")
+        .with_domain("example.com");
+    let tc = make_app(filter).test();
+
+    let resp = tc.get("/set").send().await?;
+    let cookie = set_cookie(resp.as_ref());
+
+    assert!(!cookie.contains("HttpOnly"));
+    assert!(!cookie.contains("Secure"));
+    assert!(cookie.contains("SameSite=Lax"));
+    assert!(cookie.contains("Path=/"));
+    assert!(cookie.contains("Domain=example.com"));
+
+    Ok(())
+}
+
+struct MultiSessionState;
+
+#[derive(Default)]
+struct MultiContext {
+    sessions: Sessions,
+}
+
+impl highnoon::State for MultiSessionState {
+    type Context = MultiContext;
+
+    fn new_context(&self) -> MultiContext {
+        MultiContext::default()
+    }
+}
+
+impl HasSession for MultiContext {
+    fn session(&mut self) -> &mut Session {
+        self.sessions.session()
+    }
+
+    fn session_named(&mut self, name: &str) -> Option<&mut Session> {
+        Some(self.sessions.named(name))
+    }
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_named_sessions() -> highnoon::Result<()> {
+    let mut app = App::new(MultiSessionState);
+    app.with(SessionFilter::new(MemorySessionStore::new()));
+    app.with(
+        SessionFilter::new(MemorySessionStore::new())
+            .with_name("admin")
+            .with_cookie_name("admin_sid"),
+    );
+
+    app.at("/admin")
+        .get(|mut req: Request<MultiSessionState>| async move {
+            req.session_named("admin")
+                .expect("admin session")
+                .set("role".to_owned(), "admin".to_owned());
+            StatusCode::OK
+        });
+
+    let tc = app.test();
+
+    let resp = tc.get("/admin").send().await?;
+    let cookies: Vec<_> = resp
+        .as_ref()
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|c| c.to_str().unwrap().to_owned())
+        .collect();
+
+    // only the admin session was modified
+    assert_eq!(cookies.len(), 1);
+    assert!(cookies[0].starts_with("admin_sid="));
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_named_session_not_implemented() -> highnoon::Result<()> {
+    // the Context only has a default session
+    let mut app = make_app(SessionFilter::new(MemorySessionStore::new()).with_name("admin"));
+    app.at("/ok").get(|_req| async { StatusCode::OK });
+
+    let resp = app.test().get("/ok").send().await?;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_sessions_keep_other_cookies() -> highnoon::Result<()> {
+    let mut app = App::new(MultiSessionState);
+    app.with(SessionFilter::new(MemorySessionStore::new()));
+    app.with(
+        SessionFilter::new(MemorySessionStore::new())
+            .with_name("admin")
+            .with_cookie_name("admin_sid"),
+    );
+
+    app.at("/both")
+        .get(|mut req: Request<MultiSessionState>| async move {
+            req.session().set("key".to_owned(), "value".to_owned());
+            req.session_named("admin")
+                .expect("admin session")
+                .set("role".to_owned(), "admin".to_owned());
+            Response::ok().cookie(Cookie::new("theme", "dark"))
+        });
+
+    let tc = app.test();
+
+    let resp = tc.get("/both").send().await?;
+    let mut cookies: Vec<_> = resp
+        .as_ref()
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|c| c.to_str().unwrap().split('=').next().unwrap().to_owned())
+        .collect();
+    cookies.sort();
+
+    assert_eq!(cookies, ["admin_sid", "sid", "theme"]);
+
+    Ok(())
+}
+
+async fn special_round_trip(filter: SessionFilter) -> highnoon::Result<()> {
+    let tc = make_app(filter).test();
+
+    let resp = tc.get("/set_special").send().await?;
+    let cookie = set_cookie(resp.as_ref());
+    let sid = cookie.split(';').next().unwrap().to_owned();
+
+    let mut resp = tc.get("/get").raw_header("cookie", sid)?.send().await?;
+    assert_eq!(resp.body_string().await?, SPECIAL);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_special_characters() -> highnoon::Result<()> {
+    special_round_trip(SessionFilter::new(MemorySessionStore::new())).await?;
+    special_round_trip(SessionFilter::new(MemorySessionStore::new()).with_format(UrlEncodedFormat))
+        .await?;
+    Ok(())
+}
+
+/// A store which always returns data that can't be decoded
+struct CorruptStore;
+
+#[async_trait]
+impl SessionStore for CorruptStore {
+    async fn get(&self, _id: &str) -> highnoon::Result<Option<String>> {
+        Ok(Some("key=not json".to_owned()))
+    }
+
+    async fn set(&self, _id: String, _value: String) -> highnoon::Result<()> {
+        Ok(())
+    }
+
+    async fn clear(&self, _id: &str) -> highnoon::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_undecodable_session() -> highnoon::Result<()> {
+    let tc = make_app(SessionFilter::new(CorruptStore)).test();
+
+    let mut resp = tc
+        .get("/get")
+        .raw_header("cookie", "sid=1234")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "");
+
+    // the same data is valid in the old format
+    let tc = make_app(SessionFilter::new(CorruptStore).with_format(UrlEncodedFormat)).test();
+
+    let mut resp = tc
+        .get("/get")
+        .raw_header("cookie", "sid=1234")?
+        .send()
+        .await?;
+    assert_eq!(resp.body_string().await?, "not json");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_typed_values() -> highnoon::Result<()> {
+    #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, PartialEq)]
+    struct Cart {
+        items: Vec<String>,
+        total: u32,
+    }
+
+    let mut app = make_app(SessionFilter::new(MemorySessionStore::new()));
+    app.at("/typed")
+        .get(|mut req: Request<SessionState>| async move {
+            let count: u32 = req.session().get_as("count")?.unwrap_or(0);
+            let cart: Option<Cart> = req.session().get_as("cart")?;
+
+            let cart = match cart {
+                Some(cart) => cart,
+                None => Cart {
+                    items: vec!["apple".to_owned()],
+                    total: 3,
+                },
+            };
+            req.session().set_as("count".to_owned(), &(count + 1))?;
+            req.session().set_as("cart".to_owned(), &cart)?;
+
+            Ok(format!("{} {:?}", count, cart.items))
+        });
+    let tc = app.test();
+
+    let mut resp = tc.get("/typed").send().await?;
+    assert_eq!(resp.body_string().await?, "0 [\"apple\"]");
+    let cookie = set_cookie(resp.as_ref());
+    let sid = cookie.split(';').next().unwrap().to_owned();
+
+    let mut resp = tc.get("/typed").raw_header("cookie", &sid)?.send().await?;
+    assert_eq!(resp.body_string().await?, "1 [\"apple\"]");
+
+    Ok(())
+}
+
+/// Runs against a local Redis when `HIGHNOON_TEST_REDIS_URL` is set (eg. `redis://127.0.0.1/`)
+#[cfg(feature = "redis")]
+#[tokio::main]
+#[test]
+pub async fn test_redis_store() -> highnoon::Result<()> {
+    use highnoon::filter::session::RedisSessionStore;
+    use std::time::Duration;
+
+    let url = match std::env::var("HIGHNOON_TEST_REDIS_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("HIGHNOON_TEST_REDIS_URL not set, skipping redis test");
+            return Ok(());
+        }
+    };
+    let prefix = format!("highnoon-test-{}:", std::process::id());
+
+    let store = RedisSessionStore::connect(&url)
+        .await?
+        .with_prefix(prefix.clone());
+    special_round_trip(SessionFilter::new(store)).await?;
+
+    let store = RedisSessionStore::connect(&url)
+        .await?
+        .with_prefix(prefix)
+        .with_ttl(Duration::from_secs(60));
+    store.set("abc".to_owned(), "data".to_owned()).await?;
+    assert_eq!(store.get("abc").await?.as_deref(), Some("data"));
+    store.clear("abc").await?;
+    assert_eq!(store.get("abc").await?, None);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_encrypted_cookie_session() -> highnoon::Result<()> {
+    let key = [7u8; 64];
+    special_round_trip(SessionFilter::new_encrypted_cookie(&key)?).await?;
+
+    let mut app = make_app(SessionFilter::new_encrypted_cookie(&key)?);
+    app.at("/set_large")
+        .get(|mut req: Request<SessionState>| async move {
+            req.session().set("key".to_owned(), "x".repeat(4096));
+            StatusCode::OK
+        });
+    let tc = app.test();
+
+    let resp = tc.get("/set").send().await?;
+    let cookie = set_cookie(resp.as_ref());
+    let sid = cookie.split(';').next().unwrap().to_owned();
+    assert!(!sid.contains("value"));
+
+    let mut resp = tc
+        .get("/get")
+        .raw_header("cookie", sid.clone())?
+        .send()
+        .await?;
+    assert_eq!(resp.body_string().await?, "value");
+
+    // a tampered cookie is ignored
+    let mut tampered = sid.into_bytes();
+    let last = tampered.len() - 2;
+    tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+    let mut resp = tc
+        .get("/get")
+        .raw_header("cookie", String::from_utf8(tampered).unwrap())?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "");
+
+    // so is one encrypted with another key
+    let other = make_app(SessionFilter::new_encrypted_cookie(&[8u8; 64])?).test();
+    let resp = other.get("/set").send().await?;
+    let cookie = set_cookie(resp.as_ref());
+    let sid = cookie.split(';').next().unwrap().to_owned();
+    let mut resp = tc.get("/get").raw_header("cookie", sid)?.send().await?;
+    assert_eq!(resp.body_string().await?, "");
+
+    let resp = tc.get("/set_large").send().await?;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    assert!(SessionFilter::new_encrypted_cookie(b"too short").is_err());
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_encrypted_cookie_expiry() -> highnoon::Result<()> {
+    let key = [7u8; 64];
+    let filter = SessionFilter::new_encrypted_cookie(&key)?.with_expiry(time::Duration::seconds(1));
+    let tc = make_app(filter).test();
+
+    let resp = tc.get("/set").send().await?;
+    let cookie = set_cookie(resp.as_ref());
+    let sid = cookie.split(';').next().unwrap().to_owned();
+
+    let mut resp = tc
+        .get("/get")
+        .raw_header("cookie", sid.clone())?
+        .send()
+        .await?;
+    assert_eq!(resp.body_string().await?, "value");
+
+    // replaying the cookie after it expires gets a fresh session, even though the client
+    // ignored the cookie's expiry
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    let mut resp = tc.get("/get").raw_header("cookie", sid)?.send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_flash() -> highnoon::Result<()> {
+    let mut app = make_app(SessionFilter::new(MemorySessionStore::new()));
+    app.at("/flash")
+        .get(|mut req: Request<SessionState>| async move {
+            req.session().flash("notice", "saved".to_owned());
+            StatusCode::SEE_OTHER
+        });
+    app.at("/show")
+        .get(|mut req: Request<SessionState>| async move {
+            req.session().take_flash("notice").unwrap_or_default()
+        });
+    let tc = app.test();
+
+    let resp = tc.get("/flash").send().await?;
+    let cookie = set_cookie(resp.as_ref());
+    let sid = cookie.split(';').next().unwrap().to_owned();
+
+    // read exactly once
+    let mut resp = tc
+        .get("/show")
+        .raw_header("cookie", sid.clone())?
+        .send()
+        .await?;
+    assert_eq!(resp.body_string().await?, "saved");
+    let mut resp = tc
+        .get("/show")
+        .raw_header("cookie", sid.clone())?
+        .send()
+        .await?;
+    assert_eq!(resp.body_string().await?, "");
+
+    // only the next request can take it - after that it is gone
+    tc.get("/flash")
+        .raw_header("cookie", sid.clone())?
+        .send()
+        .await?;
+    tc.get("/get")
+        .raw_header("cookie", sid.clone())?
+        .send()
+        .await?;
+    let mut resp = tc
+        .get("/show")
+        .raw_header("cookie", sid.clone())?
+        .send()
+        .await?;
+    assert_eq!(resp.body_string().await?, "");
+
+    tc.get("/flash")
+        .raw_header("cookie", sid.clone())?
+        .send()
+        .await?;
+    let mut resp = tc.get("/show").raw_header("cookie", sid)?.send().await?;
+    assert_eq!(resp.body_string().await?, "saved");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_session_with_cookie_jar() -> highnoon::Result<()> {
+    let tc = make_app(SessionFilter::new(MemorySessionStore::new()))
+        .test()
+        .with_cookies();
+
+    tc.get("/get").send().await?.assert_body("").await;
+    tc.get("/set").send().await?;
+    tc.get("/get").send().await?.assert_body("value").await;
+
+    Ok(())
+}
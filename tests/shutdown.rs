@@ -0,0 +1,115 @@
+#![cfg(feature = "client")]
+
+use highnoon::{App, HttpClient};
+use std::future::IntoFuture;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+fn make_app() -> App<()> {
+    let mut app = App::new(());
+    app.at("/sleep/:ms")
+        .get(|req: highnoon::Request<()>| async move {
+            let ms = req.param("ms")?.parse()?;
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok("done")
+        });
+    app
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_graceful_shutdown() -> anyhow::Result<()> {
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    let (tx, rx) = oneshot::channel();
+    let server = tokio::spawn(
+        make_app()
+            .listen_on_with_shutdown(tcp, async {
+                rx.await.ok();
+            })
+            .into_future(),
+    );
+
+    let client = HttpClient::new();
+    let request =
+        tokio::spawn(async move { client.get(&format!("http://{}/sleep/300", addr)).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    tx.send(()).unwrap();
+
+    // the in-flight request finishes before the server stops
+    let resp = request.await?.map_err(|err| anyhow::anyhow!("{}", err))?;
+    assert_eq!(resp.status(), 200);
+    server.await??;
+
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_drain_timeout() -> anyhow::Result<()> {
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    let (tx, rx) = oneshot::channel();
+    let server = tokio::spawn(
+        make_app()
+            .listen_on_with_shutdown(tcp, async {
+                rx.await.ok();
+            })
+            .drain_timeout(Duration::from_millis(200))
+            .into_future(),
+    );
+
+    let client = HttpClient::new();
+    let request =
+        tokio::spawn(async move { client.get(&format!("http://{}/sleep/10000", addr)).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let start = Instant::now();
+    tx.send(()).unwrap();
+    server.await??;
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    // the stuck request was aborted
+    assert!(request.await?.is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "websocket")]
+#[tokio::main]
+#[test]
+pub async fn test_shutdown_leaves_websockets() -> anyhow::Result<()> {
+    use futures_util::{SinkExt, StreamExt};
+    use highnoon::Message;
+
+    let mut app = App::new(());
+    app.at("/echo").ws_echo();
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    let (tx, rx) = oneshot::channel();
+    let server = tokio::spawn(
+        app.listen_on_with_shutdown(tcp, async {
+            rx.await.ok();
+        })
+        .into_future(),
+    );
+
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let (mut client, _) =
+        tokio_tungstenite::client_async(format!("ws://{}/echo", addr), stream).await?;
+
+    // the server doesn't wait for the websocket to close
+    tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(2), server).await???;
+
+    client.send(Message::text("still here")).await?;
+    assert_eq!(
+        client.next().await.transpose()?,
+        Some(Message::text("still here"))
+    );
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+#![cfg(feature = "test-client")]
+
+use futures_util::stream;
+use highnoon::sse::{Event, Sse};
+use highnoon::{App, Request, StatusCode};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+#[tokio::main]
+#[test]
+pub async fn test_events() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/events").get(|_req| async {
+        Sse::new(stream::iter(vec![
+            Event::new("hello"),
+            Event::new("line 1\nline 2")
+                .event("update")
+                .id("2")
+                .retry(Duration::from_secs(3)),
+        ]))
+    });
+
+    let mut resp = app.test().get("/events").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.assert_header("content-type", "text/event-stream");
+    assert_eq!(
+        resp.body_string().await?,
+        "data: hello\n\nevent: update\nid: 2\nretry: 3000\ndata: line 1\ndata: line 2\n\n"
+    );
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_data_line_breaks() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/events").get(|_req| async {
+        Sse::new(stream::iter(vec![
+            Event::new("x\rid: 1\revent: admin"),
+            Event::new("a\r\nb\nc"),
+        ]))
+    });
+
+    let mut resp = app.test().get("/events").send().await?;
+    assert_eq!(
+        resp.body_string().await?,
+        "data: x\ndata: id: 1\ndata: event: admin\n\ndata: a\ndata: b\ndata: c\n\n"
+    );
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_keepalive() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/events").get(|_req| async {
+        let (tx, sse) = Sse::channel(1);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let _ = tx.send(Event::new("done")).await;
+        });
+        sse.keepalive(Duration::from_millis(10))
+    });
+
+    let mut resp = app.test().get("/events").send().await?;
+
+    let chunk = resp.next_chunk().await.expect("missing keepalive")?;
+    assert_eq!(&chunk[..], b":\n\n");
+
+    // keepalives are sent until the event arrives
+    loop {
+        let chunk = resp.next_chunk().await.expect("missing event")?;
+        if &chunk[..] != b":\n\n" {
+            assert_eq!(&chunk[..], b"data: done\n\n");
+            break;
+        }
+    }
+    assert!(resp.next_chunk().await.is_none());
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_disconnect() -> highnoon::Result<()> {
+    let (done_tx, done_rx) = oneshot::channel();
+    let done_tx = std::sync::Mutex::new(Some(done_tx));
+
+    let mut app = App::new(());
+    app.at("/events").get(move |_req: Request<()>| {
+        let done_tx = done_tx.lock().unwrap().take();
+        async move {
+            let (tx, sse) = Sse::channel(1);
+            tokio::spawn(async move {
+                tx.closed().await;
+                if let Some(done_tx) = done_tx {
+                    let _ = done_tx.send(());
+                }
+            });
+            sse
+        }
+    });
+
+    let resp = app.test().get("/events").send().await?;
+    drop(resp);
+
+    tokio::time::timeout(Duration::from_secs(1), done_rx)
+        .await
+        .expect("producer did not see the disconnect")
+        .expect("producer dropped");
+
+    Ok(())
+}
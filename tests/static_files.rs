@@ -0,0 +1,331 @@
+#![cfg(all(feature = "static-files", feature = "test-client"))]
+
+use highnoon::{App, CacheControl, Request, Response, StaticFilesOptions, StatusCode};
+use std::time::Duration;
+
+fn make_app() -> App<()> {
+    let mut app = App::new(());
+    app.at("/static/*").static_files("tests/resources/static/");
+    app.at("/download").get(|req: Request<()>| async move {
+        req.send_file("tests/resources/static/hello world.txt")
+            .await
+    });
+    app
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_static_file() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/static/sub/nested.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "nested\n");
+
+    let resp = tc.get("/static/missing.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_encoded_path() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/static/hello%20world.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "Hello World!\n");
+
+    let mut resp = tc.get("/static/sub/..%2Fsub/nested.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert_eq!(resp.body_string().await?, "");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_traversal() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    for path in [
+        "/static/../secret.txt",
+        "/static/..%2Fsecret.txt",
+        "/static/%2E%2E/secret.txt",
+        "/static/sub%2F..%2F..%2Fsecret.txt",
+        "/static/%5C..%5Csecret.txt",
+    ] {
+        let resp = tc.get(path).send().await?;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{}", path);
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_large_file() -> highnoon::Result<()> {
+    // large enough to be memory mapped with `StaticFilesOptions::mmap`
+    let dir = std::env::temp_dir().join(format!("highnoon-static-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let data: Vec<u8> = (0..3 * 1024 * 1024 + 17).map(|i| (i % 251) as u8).collect();
+    std::fs::write(dir.join("large.bin"), &data)?;
+
+    let mut app = App::new(());
+    app.at("/static/*").static_files(dir.clone());
+    #[cfg(feature = "mmap")]
+    app.at("/mapped/*")
+        .static_files_with(dir.clone(), StaticFilesOptions::new().mmap(true));
+    let tc = app.test();
+
+    let mut paths = vec!["/static/large.bin"];
+    if cfg!(feature = "mmap") {
+        paths.push("/mapped/large.bin");
+    }
+
+    let mut bodies = Vec::new();
+    for path in paths {
+        let mut resp = tc.get(path).send().await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        bodies.push(resp.body_bytes().await?);
+    }
+    std::fs::remove_dir_all(&dir)?;
+
+    for body in bodies {
+        assert_eq!(body.len(), data.len());
+        assert!(body == data, "body does not match file contents");
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_conditional() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let resp = tc.get("/static/sub/nested.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let headers = resp.as_ref().headers();
+    let etag = headers["etag"].to_str().unwrap().to_owned();
+    let last_modified = headers["last-modified"].to_str().unwrap().to_owned();
+
+    let mut resp = tc
+        .get("/static/sub/nested.txt")
+        .raw_header("if-none-match", &etag)?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    resp.assert_header("etag", &etag);
+    assert_eq!(resp.body_string().await?, "");
+
+    let resp = tc
+        .get("/static/sub/nested.txt")
+        .raw_header("if-modified-since", &last_modified)?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+    // If-None-Match takes precedence over If-Modified-Since
+    let mut resp = tc
+        .get("/static/sub/nested.txt")
+        .raw_header("if-none-match", "\"other\"")?
+        .raw_header("if-modified-since", &last_modified)?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "nested\n");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_send_file() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/download").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.assert_header("content-type", "text/plain");
+    let etag = resp.as_ref().headers()["etag"].to_str().unwrap().to_owned();
+    assert_eq!(resp.body_string().await?, "Hello World!\n");
+
+    let resp = tc
+        .get("/download")
+        .raw_header("if-none-match", &etag)?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_range() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/download").send().await?;
+    resp.assert_header("content-length", "13")
+        .assert_header("accept-ranges", "bytes");
+    let etag = resp.as_ref().headers()["etag"].to_str().unwrap().to_owned();
+
+    for (range, expected, content_range) in [
+        ("bytes=0-4", "Hello", "bytes 0-4/13"),
+        ("bytes=6-", "World!\n", "bytes 6-12/13"),
+        ("bytes=-2", "!\n", "bytes 11-12/13"),
+        ("bytes=6-100", "World!\n", "bytes 6-12/13"),
+    ] {
+        let mut resp = tc
+            .get("/download")
+            .raw_header("range", range)?
+            .send()
+            .await?;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT, "{}", range);
+        resp.assert_header("content-range", content_range)
+            .assert_header("content-length", &expected.len().to_string());
+        assert_eq!(resp.body_string().await?, expected);
+    }
+
+    let mut resp = tc
+        .get("/download")
+        .raw_header("range", "bytes=20-30")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    resp.assert_header("content-range", "bytes */13");
+
+    // multiple ranges are not supported, so the whole file is sent
+    let resp = tc
+        .get("/download")
+        .raw_header("range", "bytes=0-1,3-4")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // If-Range only sends the range if the file is unchanged
+    let resp = tc
+        .get("/download")
+        .raw_header("range", "bytes=0-4")?
+        .raw_header("if-range", &etag)?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+
+    let mut resp = tc
+        .get("/download")
+        .raw_header("range", "bytes=0-4")?
+        .raw_header("if-range", "\"stale\"")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "Hello World!\n");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_send_file_missing() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/missing").get(|req: Request<()>| async move {
+        req.send_file("tests/resources/no_such_file.txt").await
+    });
+    app.at("/dir")
+        .get(|req: Request<()>| async move { req.send_file("tests/resources").await });
+    let tc = app.test();
+
+    let resp = tc.get("/missing").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = tc.get("/dir").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_autoindex() -> highnoon::Result<()> {
+    let dir = std::env::temp_dir().join(format!("highnoon-index-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub dir"))?;
+    std::fs::write(dir.join("<b>&.txt"), "escaped")?;
+    std::fs::write(dir.join(".secret"), "hidden")?;
+    std::fs::write(dir.join("sub dir/nested.txt"), "nested")?;
+
+    let mut app = App::new(());
+    app.at("/static/*").static_files(dir.clone());
+    app.at("/index/*")
+        .static_files_with(dir.clone(), StaticFilesOptions::new().autoindex(true));
+    app.at("/hidden/*").static_files_with(
+        dir.clone(),
+        StaticFilesOptions::new().autoindex(true).show_hidden(true),
+    );
+    let tc = app.test();
+
+    let status = tc.get("/static/").send().await?.status();
+    let mut root = tc.get("/index/").send().await?;
+    let mut sub = tc.get("/index/sub%20dir").send().await?;
+    let mut hidden = tc.get("/hidden/").send().await?;
+    let outside = tc.get("/index/..").send().await?.status();
+
+    let root_status = root.status();
+    let root_type = root.as_ref().headers()["content-type"].clone();
+    let root = root.body_string().await?;
+    let sub = sub.body_string().await?;
+    let hidden = hidden.body_string().await?;
+    std::fs::remove_dir_all(&dir)?;
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(root_status, StatusCode::OK);
+    assert_eq!(root_type, "text/html; charset=utf-8");
+    assert!(root.contains(r#"<a href="/index/%3Cb%3E%26.txt">&lt;b&gt;&amp;.txt</a>"#));
+    assert!(root.contains(r#"<a href="/index/sub%20dir/">sub dir/</a>"#));
+    assert!(!root.contains(".secret"));
+    assert!(!root.contains("../"));
+    assert!(sub.contains(r#"<a href="/index/">../</a>"#));
+    assert!(sub.contains(r#"<a href="/index/sub%20dir/nested.txt">nested.txt</a>"#));
+    assert!(hidden.contains(".secret"));
+    assert_eq!(outside, StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_cache_control() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/static/*").static_files_with(
+        "tests/resources/static/",
+        StaticFilesOptions::new().cache_control(
+            CacheControl::new()
+                .public()
+                .max_age(Duration::from_secs(3600))
+                .immutable(),
+        ),
+    );
+    app.at("/private").get(|_req| async {
+        Response::ok().cache_control(CacheControl::new().private().no_cache().no_store())
+    });
+    let tc = app.test();
+
+    let resp = tc.get("/static/sub/nested.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.as_ref().headers()["cache-control"],
+        "public, immutable, max-age=3600"
+    );
+
+    let resp = tc.get("/static/missing.txt").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert!(!resp.as_ref().headers().contains_key("cache-control"));
+
+    let resp = tc.get("/private").send().await?;
+    assert_eq!(
+        resp.as_ref().headers()["cache-control"],
+        "no-cache, no-store, private"
+    );
+
+    Ok(())
+}
@@ -1,5 +1,22 @@
-use highnoon::{App, Json, Request, StatusCode};
+#![cfg(feature = "test-client")]
+
+mod common;
+
+use common::LogCapture;
+use headers::ContentType;
+use highnoon::endpoint::{blocking, by_method};
+use highnoon::{
+    anyhow_endpoint, App, Buf, CanonicalJson, Endpoint, Error, ForwardedHeader, Json,
+    JsonArrayStream, Method, Request, Response, StatusCode,
+};
+use hyper::header::HeaderValue;
+use hyper::{Body, HeaderMap};
 use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 fn make_app() -> App<()> {
     let mut app = App::new(());
@@ -21,6 +38,104 @@ fn make_app() -> App<()> {
         Ok(Json(greeting))
     });
 
+    app.at("/query").get(|req: Request<()>| async move {
+        let query: HashMap<String, String> = req.query()?;
+        let mut pairs: Vec<_> = query.into_iter().collect();
+        pairs.sort();
+        Ok(Json(pairs))
+    });
+
+    app.at("/form").post(|mut req: Request<()>| async move {
+        let content_type = req.content_type().map(|ct| ct.to_string());
+        let body = req.body_string().await?;
+        Ok(format!("{}: {}", content_type.unwrap_or_default(), body))
+    });
+
+    app.at("/input").post(|mut req: Request<()>| async move {
+        let input: HashMap<String, String> = req.body_input().await?;
+        Ok(format!(
+            "Hello {}",
+            input.get("name").map_or("", |s| s.as_str())
+        ))
+    });
+
+    app.at("/buf").post(|mut req: Request<()>| async move {
+        let mut buf = req.body_buf().await?;
+        let first_chunk = buf.chunk().len();
+        let data = buf.copy_to_bytes(buf.remaining());
+        Ok(format!(
+            "{} {}",
+            first_chunk,
+            String::from_utf8(data.to_vec())?
+        ))
+    });
+
+    app.at("/limited").post(|mut req: Request<()>| async move {
+        let mut body = String::new();
+        req.reader_limited(12).await?.read_to_string(&mut body)?;
+        highnoon::Result::Ok(body)
+    });
+
+    app.at("/contiguous")
+        .post(|mut req: Request<()>| async move {
+            let bytes = req.body_contiguous().await?;
+            Ok(format!(
+                "{} {}",
+                bytes.len(),
+                String::from_utf8(bytes.to_vec())?
+            ))
+        });
+
+    app.at("/html")
+        .get(|_req| async { (StatusCode::CREATED, ContentType::html(), "<p>created</p>") });
+
+    app.at("/anyhow")
+        .get(anyhow_endpoint(|req: Request<()>| async move {
+            let count: u32 = req.uri().query().unwrap_or_default().parse()?;
+            anyhow::Ok(format!("count is {}", count))
+        }));
+
+    app.at("/user/:name").get(|req: Request<()>| async move {
+        Ok(format!(
+            "{} {}",
+            req.param("name")?,
+            req.param_decoded("name")?
+        ))
+    });
+
+    app.at("/addr")
+        .get(|req: Request<()>| async move { req.remote_addr().to_string() });
+
+    app.at("/stream").get(|_req| async {
+        let (mut tx, body) = Body::channel();
+        tokio::spawn(async move {
+            tx.send_data("first".into()).await?;
+            tx.send_data("second".into()).await?;
+            // keep the stream open forever
+            std::future::pending::<()>().await;
+            Ok::<_, hyper::Error>(())
+        });
+        Response::ok().body(body)
+    });
+
+    app.at("/json_error").get(|_req| async {
+        Err::<StatusCode, _>(Error::json(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            json!({ "message": "invalid", "fields": ["name"] }),
+        ))
+    });
+
+    app.at("/trailers").get(|_req| async {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", HeaderValue::from_static("1234"));
+        Response::ok().body("data").trailers(trailers)
+    });
+
+    app.at("/resource")
+        .patch(|_req| async { "patched" })
+        .head(|_req| async { StatusCode::NO_CONTENT })
+        .options(|_req| async { "options" });
+
     app
 }
 
@@ -88,6 +203,1473 @@ pub async fn test_method_not_allowed() -> highnoon::Result<()> {
     let resp = tc.delete("/greeting").send().await?;
 
     assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(resp.as_ref().headers()["allow"], "GET, OPTIONS");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_other_methods() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.patch("/resource").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "patched");
+
+    let resp = tc.head("/resource").send().await?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let mut resp = tc.options("/resource").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "options");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_query() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc
+        .get("/query")
+        .query(json!({ "name": "Jane Doe", "page": 2 }))?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.body_string().await?,
+        r#"[["name","Jane Doe"],["page","2"]]"#
+    );
+
+    let mut resp = tc
+        .get("/query?sort=asc")
+        .query(json!({ "page": 2 }))?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.body_string().await?,
+        r#"[["page","2"],["sort","asc"]]"#
+    );
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_query_lenient() -> highnoon::Result<()> {
+    #[derive(Default, serde_derive::Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    struct Page {
+        page: u32,
+        sort: Option<String>,
+    }
+
+    let mut app = App::new(());
+    app.at("/strict").get(|req: Request<()>| async move {
+        let page: Page = req.query()?;
+        Ok(page.page.to_string())
+    });
+    app.at("/lenient").get(|req: Request<()>| async move {
+        let page: Page = req.query_lenient();
+        format!("{} {}", page.page, page.sort.unwrap_or_default())
+    });
+    let tc = app.test();
+
+    let query = "?page=abc&sort=name&utm_source=mail&sort=other";
+    tc.get(format!("/strict{}", query))
+        .send()
+        .await?
+        .assert_status(StatusCode::BAD_REQUEST)
+        .await;
+    tc.get(format!("/lenient{}", query))
+        .send()
+        .await?
+        .assert_body("0 name")
+        .await;
+
+    tc.get("/lenient?page=3&sort=%ZZ&utm_campaign=x")
+        .send()
+        .await?
+        .assert_body("3 %ZZ")
+        .await;
+    tc.get("/lenient").send().await?.assert_body("0 ").await;
+
+    // many bad parameters are handled quickly
+    let query: Vec<_> = (0..2000).map(|i| format!("x{}=y&page=z", i)).collect();
+    tc.get(format!("/lenient?sort=s&{}", query.join("&")))
+        .send()
+        .await?
+        .assert_body("0 s")
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_query_lenient_required_field() -> highnoon::Result<()> {
+    #[derive(Default, serde_derive::Deserialize)]
+    struct Item {
+        id: u32,
+        #[serde(default)]
+        page: u32,
+    }
+
+    let mut app = App::new(());
+    app.at("/item").get(|req: Request<()>| async move {
+        let item: Item = req.query_lenient();
+        format!("{} {}", item.id, item.page)
+    });
+    let tc = app.test();
+
+    tc.get("/item?page=2&id=5")
+        .send()
+        .await?
+        .assert_body("5 2")
+        .await;
+    tc.get("/item?id=5&page=x")
+        .send()
+        .await?
+        .assert_body("5 0")
+        .await;
+    tc.get("/item?page=2")
+        .send()
+        .await?
+        .assert_body("0 0")
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_query_pairs() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/search").get(|req: Request<()>| async move {
+        let tags: Vec<String> = req
+            .query_pairs()
+            .into_iter()
+            .filter(|(key, _)| key == "tag")
+            .map(|(_, value)| value)
+            .collect();
+        tags.join(",")
+    });
+    let tc = app.test();
+
+    tc.get("/search?tag=a&q=x&tag=b+c&tag=%F0%9F%A6%80")
+        .send()
+        .await?
+        .assert_body("a,b c,\u{1f980}")
+        .await;
+    tc.get("/search").send().await?.assert_body("").await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_form() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc
+        .post("/form")
+        .form([("greeting", "Hello World!")])?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.body_string().await?,
+        "application/x-www-form-urlencoded: greeting=Hello+World%21"
+    );
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_body_input() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    tc.post("/input")
+        .json(json!({ "name": "json" }))?
+        .send()
+        .await?
+        .assert_body("Hello json")
+        .await;
+
+    tc.post("/input")
+        .form([("name", "form")])?
+        .send()
+        .await?
+        .assert_body("Hello form")
+        .await;
+
+    tc.post("/input")
+        .header(ContentType::text())
+        .body("name=text")?
+        .send()
+        .await?
+        .assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+        .await;
+
+    tc.post("/input")
+        .body("name=none")?
+        .send()
+        .await?
+        .assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+        .await;
+
+    tc.post("/input")
+        .header(ContentType::json())
+        .body("{")?
+        .send()
+        .await?
+        .assert_status(StatusCode::BAD_REQUEST)
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_content_type() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/").post(|req: Request<()>| async move {
+        match req.content_type() {
+            Some(mime) => format!(
+                "{} {}",
+                mime.essence_str(),
+                mime.get_param(mime::CHARSET).map_or("-", |cs| cs.as_str())
+            ),
+            None => "none".to_owned(),
+        }
+    });
+    let tc = app.test();
+
+    tc.post("/")
+        .raw_header("content-type", "text/plain; charset=latin1")?
+        .send()
+        .await?
+        .assert_body("text/plain latin1")
+        .await;
+    tc.post("/")
+        .header(ContentType::json())
+        .send()
+        .await?
+        .assert_body("application/json -")
+        .await;
+    tc.post("/")
+        .raw_header("content-type", "not a mime type")?
+        .send()
+        .await?
+        .assert_body("none")
+        .await;
+    tc.post("/").send().await?.assert_body("none").await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_remote_addr() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/addr").send().await?;
+    assert_eq!(resp.body_string().await?, "127.0.0.1:8080");
+
+    let mut resp = tc
+        .get("/addr")
+        .remote_addr("10.1.2.3:4567".parse().unwrap())
+        .send()
+        .await?;
+    assert_eq!(resp.body_string().await?, "10.1.2.3:4567");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_real_ip() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.trust_proxies(["10.0.0.0/8".parse()?, "2001:db8::/32".parse()?]);
+    app.at("/ip")
+        .get(|req: Request<()>| async move { req.real_ip().to_string() });
+    let tc = app.test();
+
+    let real_ip = |addr: &str, headers: &[(&str, &str)]| {
+        let mut req = tc.get("/ip").remote_addr(addr.parse().unwrap());
+        for (name, value) in headers {
+            req = req.raw_header(*name, *value).unwrap();
+        }
+        async move { req.send().await?.body_string().await }
+    };
+
+    // forwarding headers from untrusted peers are ignored
+    let spoofed = [("x-forwarded-for", "1.2.3.4")];
+    assert_eq!(real_ip("192.0.2.1:80", &spoofed).await?, "192.0.2.1");
+    assert_eq!(real_ip("10.0.0.1:80", &[]).await?, "10.0.0.1");
+
+    // walk back through trusted proxies only
+    let chain = [("x-forwarded-for", "1.2.3.4, 192.0.2.9, 10.0.0.2")];
+    assert_eq!(real_ip("10.0.0.1:80", &chain).await?, "192.0.2.9");
+    let chain = [("x-forwarded-for", "1.2.3.4:5678, 10.9.9.9")];
+    assert_eq!(real_ip("10.0.0.1:80", &chain).await?, "1.2.3.4");
+
+    // only X-Forwarded-For is trusted by default, so a client can't add another header to
+    // pick its own address
+    let mixed = [
+        ("x-forwarded-for", "203.0.113.5"),
+        ("forwarded", "for=1.2.3.4"),
+        ("x-real-ip", "1.2.3.4"),
+    ];
+    assert_eq!(real_ip("10.0.0.1:80", &mixed).await?, "203.0.113.5");
+    let missing = [("forwarded", "for=1.2.3.4"), ("x-real-ip", "1.2.3.4")];
+    assert_eq!(real_ip("10.0.0.1:80", &missing).await?, "10.0.0.1");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_real_ip_forwarded_header() -> highnoon::Result<()> {
+    async fn real_ip(
+        header: ForwardedHeader,
+        headers: &[(&str, &str)],
+    ) -> highnoon::Result<String> {
+        let mut app = App::new(());
+        app.trust_proxies(["10.0.0.0/8".parse()?, "2001:db8::/32".parse()?]);
+        app.forwarded_header(header);
+        app.at("/ip")
+            .get(|req: Request<()>| async move { req.real_ip().to_string() });
+
+        let mut req = app
+            .test()
+            .get("/ip")
+            .remote_addr("[::ffff:10.0.0.1]:80".parse()?);
+        for (name, value) in headers {
+            req = req.raw_header(*name, *value)?;
+        }
+        req.send().await?.body_string().await
+    }
+
+    let forwarded = [
+        (
+            "forwarded",
+            r#"for=192.0.2.60;proto=http, for="[2001:db8::1]:4711""#,
+        ),
+        ("x-forwarded-for", "1.2.3.4"),
+    ];
+    assert_eq!(
+        real_ip(ForwardedHeader::Forwarded, &forwarded).await?,
+        "192.0.2.60"
+    );
+    let obfuscated = [("forwarded", "for=_hidden, for=10.0.0.2")];
+    assert_eq!(
+        real_ip(ForwardedHeader::Forwarded, &obfuscated).await?,
+        "10.0.0.2"
+    );
+    let spoofed = [("x-forwarded-for", "1.2.3.4")];
+    assert_eq!(
+        real_ip(ForwardedHeader::Forwarded, &spoofed).await?,
+        "10.0.0.1"
+    );
+
+    let real = [
+        ("x-real-ip", "198.51.100.7"),
+        ("x-forwarded-for", "1.2.3.4"),
+    ];
+    assert_eq!(
+        real_ip(ForwardedHeader::XRealIp, &real).await?,
+        "198.51.100.7"
+    );
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_next_chunk() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/stream").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.next_chunk().await.unwrap()?, "first");
+    assert_eq!(resp.next_chunk().await.unwrap()?, "second");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_assert_helpers() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    tc.get("/json")
+        .json(json!({
+            "greeting": "Hello World!"
+        }))?
+        .send()
+        .await?
+        .assert_status(StatusCode::OK)
+        .await
+        .assert_header("content-type", "application/json")
+        .assert_json(json!("Hello World!"))
+        .await
+        .assert_body("\"Hello World!\"")
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+#[should_panic(expected = "expected status 200 OK but got 404 Not Found")]
+pub async fn test_assert_status_fails() {
+    let tc = make_app().test();
+
+    tc.get("/no_such_route")
+        .send()
+        .await
+        .unwrap()
+        .assert_status(StatusCode::OK)
+        .await;
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_content_length_mismatch() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let resp = tc
+        .get("/reverse")
+        .raw_header("content-length", "10")?
+        .body("abc")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = tc
+        .get("/reverse")
+        .raw_header("content-length", "1")?
+        .body("abc")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = tc
+        .get("/reverse")
+        .raw_header("content-length", "3")?
+        .body("abc")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_json_error() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    tc.get("/json_error")
+        .send()
+        .await?
+        .assert_status(StatusCode::UNPROCESSABLE_ENTITY)
+        .await
+        .assert_header("content-type", "application/json")
+        .assert_json(json!({ "message": "invalid", "fields": ["name"] }))
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_debug_errors() -> highnoon::Result<()> {
+    let mut app = make_app();
+    app.at("/fail").get(|_req| async {
+        Err::<StatusCode, _>(Error::from(anyhow::anyhow!("something broke")))
+    });
+
+    let mut resp = app.test().get("/fail").send().await?;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(resp.body_string().await?, "");
+
+    let mut app = make_app();
+    app.debug(true);
+    app.at("/fail").get(|_req| async {
+        Err::<StatusCode, _>(Error::from(anyhow::anyhow!("something broke")))
+    });
+
+    let mut resp = app.test().get("/fail").send().await?;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(resp.body_string().await?.contains("something broke"));
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_trailers() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/trailers").send().await?;
+    resp.assert_header("trailer", "x-checksum");
+    assert_eq!(resp.next_chunk().await.unwrap()?, "data");
+
+    let trailers = resp.trailers().await?.expect("no trailers");
+    assert_eq!(trailers.get("x-checksum").unwrap(), "1234");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_trailers_outside_runtime() {
+    // nothing is spawned until the app sends the response
+    let mut trailers = HeaderMap::new();
+    trailers.insert("x-checksum", HeaderValue::from_static("1234"));
+    let resp = Response::ok().body("data").trailers(trailers);
+    assert_eq!(
+        resp.as_ref().headers().get("trailer").unwrap(),
+        "x-checksum"
+    );
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_param_decoding() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/user/John%20Doe").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "John%20Doe John Doe");
+
+    // an encoded slash does not split the segment
+    let mut resp = tc.get("/user/a%2Fb").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "a%2Fb a/b");
+
+    let resp = tc.get("/user/%FF").send().await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
+
+fn chunked_body() -> Body {
+    let chunks: Vec<std::result::Result<_, std::io::Error>> = vec![Ok("Hello "), Ok("World!")];
+    Body::wrap_stream(futures_util::stream::iter(chunks))
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_body_buf() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    // the buffer holds the chunks as they arrived
+    let mut resp = tc.post("/buf").body(chunked_body())?.send().await?;
+    assert_eq!(resp.body_string().await?, "6 Hello World!");
+
+    // contiguous bytes join the chunks together
+    let mut resp = tc.post("/contiguous").body(chunked_body())?.send().await?;
+    assert_eq!(resp.body_string().await?, "12 Hello World!");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_reader_limited() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    // exactly at the limit, streamed in chunks
+    let mut resp = tc.post("/limited").body(chunked_body())?.send().await?;
+    assert_eq!(resp.body_string().await?, "Hello World!");
+
+    // over the limit, with and without a Content-Length
+    let resp = tc.post("/limited").body("Hello World!!")?.send().await?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let chunks: Vec<std::result::Result<_, std::io::Error>> =
+        vec![Ok("Hello "), Ok("World!"), Ok("!")];
+    let body = Body::wrap_stream(futures_util::stream::iter(chunks));
+    let resp = tc.post("/limited").body(body)?.send().await?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_tuple_with_header() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/html").send().await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    resp.assert_header("content-type", "text/html");
+    assert_eq!(resp.body_string().await?, "<p>created</p>");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_anyhow_endpoint() -> highnoon::Result<()> {
+    let tc = make_app().test();
+
+    let mut resp = tc.get("/anyhow?3").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "count is 3");
+
+    let resp = tc.get("/anyhow?three").send().await?;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_fallback() -> highnoon::Result<()> {
+    let mut app = make_app();
+    app.at("/files/*").all(|_req| async { "files" });
+    app.fallback(|req: Request<()>| async move { format!("fallback {}", req.uri().path()) });
+    let tc = app.test();
+
+    let mut resp = tc.get("/greeting").send().await?;
+    assert_eq!(resp.body_string().await?, "Hello World!");
+
+    let mut resp = tc.post("/files/a/b").send().await?;
+    assert_eq!(resp.body_string().await?, "files");
+
+    let mut resp = tc.get("/no_such_route").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body_string().await?, "fallback /no_such_route");
+
+    // the path matches another method, so the fallback does not run
+    let resp = tc.delete("/greeting").send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_custom_not_found() -> highnoon::Result<()> {
+    let mut app = make_app();
+    app.not_found(|req: Request<()>| async move {
+        Response::status(StatusCode::NOT_FOUND).body(format!("no page at {}", req.uri().path()))
+    });
+    app.method_not_allowed(|req: Request<()>| async move {
+        Response::status(StatusCode::METHOD_NOT_ALLOWED).body(format!("can't {}", req.method()))
+    });
+    let tc = app.test();
+
+    let mut resp = tc.get("/missing").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.body_string().await?, "no page at /missing");
+
+    let mut resp = tc.delete("/greeting").send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    resp.assert_header("allow", "GET, OPTIONS");
+    assert_eq!(resp.body_string().await?, "can't DELETE");
+
+    // OPTIONS is still answered automatically
+    let resp = tc.options("/greeting").send().await?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_method_not_allowed_error() -> highnoon::Result<()> {
+    let mut app = make_app();
+    app.method_not_allowed(|_req: Request<()>| async move {
+        Err::<Response, _>(Error::http(StatusCode::METHOD_NOT_ALLOWED))
+    });
+    let tc = app.test();
+
+    let mut resp = tc.delete("/greeting").send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    resp.assert_header("allow", "GET, OPTIONS");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_multiple_methods() -> highnoon::Result<()> {
+    let mut app = make_app();
+    app.at("/search").methods(
+        &[Method::GET, Method::POST],
+        |req: Request<()>| async move { req.method().to_string() },
+    );
+    let tc = app.test();
+
+    let mut resp = tc.get("/search").send().await?;
+    assert_eq!(resp.body_string().await?, "GET");
+
+    let mut resp = tc.post("/search").send().await?;
+    assert_eq!(resp.body_string().await?, "POST");
+
+    let resp = tc.delete("/search").send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_shared_endpoint() -> highnoon::Result<()> {
+    struct Counter(AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl Endpoint<()> for Counter {
+        async fn call(&self, _req: Request<()>) -> highnoon::Result<Response> {
+            let count = self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(Response::ok().body(count.to_string()))
+        }
+    }
+
+    let counter = Arc::new(Counter(AtomicUsize::new(0)));
+    let endpoints: Vec<Arc<dyn Endpoint<()> + Send + Sync>> =
+        vec![counter.clone(), Arc::new(|_req| async { "other" })];
+
+    let mut app = App::new(());
+    app.at("/a").get(counter.clone());
+    app.at("/b").post(counter);
+    app.at("/c").get(endpoints[1].clone());
+    let tc = app.test();
+
+    let mut resp = tc.get("/a").send().await?;
+    assert_eq!(resp.body_string().await?, "0");
+    let mut resp = tc.post("/b").send().await?;
+    assert_eq!(resp.body_string().await?, "1");
+    let mut resp = tc.get("/c").send().await?;
+    assert_eq!(resp.body_string().await?, "other");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_vary() -> highnoon::Result<()> {
+    use hyper::header::{ACCEPT_ENCODING, ACCEPT_LANGUAGE, COOKIE};
+
+    let mut app = App::new(());
+    app.at("/vary").get(|_req| async {
+        let mut resp = Response::ok()
+            .raw_header("vary", "Cookie")?
+            .vary(ACCEPT_ENCODING);
+        // names are only listed once
+        resp.add_vary(ACCEPT_LANGUAGE);
+        resp.add_vary(COOKIE);
+        Ok(resp)
+    });
+    app.at("/star").get(|_req| async {
+        Ok(Response::ok()
+            .raw_header("vary", "*")?
+            .vary(ACCEPT_ENCODING))
+    });
+    let tc = app.test();
+
+    tc.get("/vary")
+        .send()
+        .await?
+        .assert_header("vary", "Cookie, accept-encoding, accept-language");
+    tc.get("/star").send().await?.assert_header("vary", "*");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_virtual_hosts() -> highnoon::Result<()> {
+    let mut api = App::new(());
+    api.at("/").get(|_req| async { "api" });
+
+    let mut app = App::new(());
+    app.at("/").get(|_req| async { "default" });
+    app.host("api.example.com", api);
+    let tc = app.test();
+
+    for host in [
+        "api.example.com",
+        "API.example.com:8080",
+        "api.example.com.",
+    ] {
+        tc.get("/")
+            .raw_header("host", host)?
+            .send()
+            .await?
+            .assert_body("api")
+            .await;
+    }
+
+    tc.get("/")
+        .raw_header("host", "www.example.com")?
+        .send()
+        .await?
+        .assert_body("default")
+        .await;
+    tc.get("/").send().await?.assert_body("default").await;
+    tc.get("http://api.example.com/")
+        .send()
+        .await?
+        .assert_body("api")
+        .await;
+    // the host in an absolute URI wins over the Host header
+    tc.get("http://api.example.com/")
+        .raw_header("host", "www.example.com")?
+        .send()
+        .await?
+        .assert_body("api")
+        .await;
+
+    // more than one Host header is rejected (the test client can't send this)
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    let mut app = App::new(());
+    app.host("api.example.com", App::new(()));
+    tokio::spawn(app.listen_on(tcp));
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    stream
+        .write_all(
+            b"GET / HTTP/1.1\r\nHost: api.example.com\r\nHost: www.example.com\r\n\
+              Connection: close\r\n\r\n",
+        )
+        .await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_reason_phrase() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/reason").get(|_req| async {
+        let mut resp = Response::status(StatusCode::ACCEPTED);
+        resp.set_reason("Queued For Later")?;
+        Ok(resp)
+    });
+    app.at("/invalid").get(|_req| async {
+        let mut resp = Response::ok();
+        resp.set_reason("Bad\r\nX-Injected: yes")?;
+        Ok(resp)
+    });
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on(tcp));
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    stream
+        .write_all(b"GET /reason HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    assert!(
+        response.starts_with("HTTP/1.1 202 Queued For Later\r\n"),
+        "{}",
+        response
+    );
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    stream
+        .write_all(b"GET /invalid HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    assert!(
+        response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+        "{}",
+        response
+    );
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_canonical_json() -> highnoon::Result<()> {
+    #[derive(serde_derive::Serialize)]
+    struct Event {
+        type_: &'static str,
+        data: HashMap<&'static str, u32>,
+        at: u64,
+    }
+
+    let mut app = App::new(());
+    app.at("/event").get(|_req| async {
+        let data = [("z", 1), ("m", 2), ("a", 3)].into_iter().collect();
+        CanonicalJson(json!([
+            Event { type_: "ping", data, at: 10 },
+            { "y": null, "x": { "d": "text", "c": 1.5 } },
+        ]))
+    });
+
+    app.test()
+        .get("/event")
+        .send()
+        .await?
+        .assert_header("content-type", "application/json")
+        .assert_body(
+            r#"[{"at":10,"data":{"a":3,"m":2,"z":1},"type_":"ping"},{"x":{"c":1.5,"d":"text"},"y":null}]"#,
+        )
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_json_array_stream() -> highnoon::Result<()> {
+    use futures_util::stream;
+
+    let mut app = App::new(());
+    app.at("/array").get(|req: Request<()>| async move {
+        let len: usize = req.uri().query().unwrap_or_default().parse()?;
+        let items = stream::iter((0..len).map(|i| highnoon::Result::Ok(json!({ "id": i }))));
+        highnoon::Result::Ok(JsonArrayStream(items))
+    });
+    app.at("/broken").get(|_req| async {
+        let items = stream::iter(vec![Ok(1), Err(anyhow::Error::msg("database went away"))]);
+        JsonArrayStream(items)
+    });
+    let tc = app.test();
+
+    let mut resp = tc.get("/array?3").send().await?;
+    resp.assert_header("content-type", "application/json");
+    let value: Value = resp.body_json().await?;
+    assert_eq!(value, json!([{"id": 0}, {"id": 1}, {"id": 2}]));
+
+    tc.get("/array?0").send().await?.assert_body("[]").await;
+
+    // errors can't change the status, so the body is aborted instead
+    let mut resp = tc.get("/broken").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.next_chunk().await.transpose()?.as_deref(),
+        Some(&b"["[..])
+    );
+    assert_eq!(
+        resp.next_chunk().await.transpose()?.as_deref(),
+        Some(&b"1"[..])
+    );
+    assert!(resp.next_chunk().await.unwrap().is_err());
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_by_method() -> highnoon::Result<()> {
+    let users = by_method()
+        .get(|_req: Request<()>| async { "list" })
+        .post(|_req: Request<()>| async { "create" })
+        .build();
+
+    let mut app = App::new(());
+    app.at("/users").all(users);
+    let tc = app.test();
+
+    tc.get("/users").send().await?.assert_body("list").await;
+    tc.post("/users").send().await?.assert_body("create").await;
+
+    let mut resp = tc.delete("/users").send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    resp.assert_header("allow", "GET, POST");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_default_text_content_type() -> highnoon::Result<()> {
+    let mut api = App::new(());
+    api.default_text_content_type(mime::TEXT_PLAIN_UTF_8);
+    api.at("/text").get(|_req| async { "plain" });
+
+    let mut app = App::new(());
+    app.default_text_content_type(mime::TEXT_HTML_UTF_8);
+    app.at("/html")
+        .get(|_req| async { "<h1>html</h1>".to_owned() });
+    app.at("/json").get(|_req| async { Json(vec![1]) });
+    app.at("/explicit")
+        .get(|_req| async { (StatusCode::OK, ContentType::xml(), "<xml/>") });
+    app.at("/api").mount(api);
+    let tc = app.test();
+
+    tc.get("/html")
+        .send()
+        .await?
+        .assert_header("content-type", "text/html; charset=utf-8");
+    tc.get("/json")
+        .send()
+        .await?
+        .assert_header("content-type", "application/json");
+    tc.get("/explicit")
+        .send()
+        .await?
+        .assert_header("content-type", "text/xml");
+    tc.get("/api/text")
+        .send()
+        .await?
+        .assert_header("content-type", "text/plain; charset=utf-8");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_options_star() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/a")
+        .get(|_req| async { "a" })
+        .post(|_req| async { "a" });
+    app.at("/*").all(|_req| async { "catch all" });
+    let tc = app.test();
+
+    let mut resp = tc.options("*").send().await?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    resp.assert_header("allow", "GET, OPTIONS, POST");
+
+    // an OPTIONS request for a path is still routed
+    tc.options("/b")
+        .send()
+        .await?
+        .assert_body("catch all")
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_auto_options() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/a")
+        .get(|_req| async { "a" })
+        .post(|_req| async { "a" });
+    app.at("/custom")
+        .get(|_req| async { "custom" })
+        .options(|_req| async { "custom options" });
+    let tc = app.test();
+
+    let mut resp = tc.options("/a").send().await?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    resp.assert_header("allow", "GET, OPTIONS, POST");
+
+    // OPTIONS is also allowed in a 405 response, but not listed twice
+    let mut resp = tc.delete("/a").send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    resp.assert_header("allow", "GET, OPTIONS, POST");
+    let mut resp = tc.delete("/custom").send().await?;
+    resp.assert_header("allow", "GET, OPTIONS");
+
+    tc.options("/custom")
+        .send()
+        .await?
+        .assert_body("custom options")
+        .await;
+    tc.options("/missing")
+        .send()
+        .await?
+        .assert_status(StatusCode::NOT_FOUND)
+        .await;
+
+    let mut app = App::new(());
+    app.at("/a").get(|_req| async { "a" });
+    app.auto_options(false);
+    let mut resp = app.test().options("/a").send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    resp.assert_header("allow", "GET");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_error_pages() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.error_page(
+        StatusCode::NOT_FOUND,
+        "<h1>Not here</h1>",
+        mime::TEXT_HTML_UTF_8,
+    );
+    app.error_page(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        r#"{"error":"too large"}"#,
+        mime::APPLICATION_JSON,
+    );
+    app.at("/missing")
+        .get(|_req| async { (StatusCode::NOT_FOUND, "no such user") });
+    app.at("/upload").post(|mut req: Request<()>| async move {
+        let mut body = String::new();
+        req.reader_limited(4).await?.read_to_string(&mut body)?;
+        highnoon::Result::Ok(body)
+    });
+    let tc = app.test();
+
+    let mut resp = tc.get("/nowhere").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    resp.assert_header("content-type", "text/html; charset=utf-8")
+        .assert_body("<h1>Not here</h1>")
+        .await;
+
+    // responses from endpoints are left alone
+    tc.get("/missing")
+        .send()
+        .await?
+        .assert_body("no such user")
+        .await;
+
+    // statuses without a page are unchanged
+    let mut resp = tc.post("/missing").send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    resp.assert_body("").await;
+
+    let mut resp = tc.post("/upload").body("too long")?.send().await?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    resp.assert_header("content-type", "application/json")
+        .assert_body(r#"{"error":"too large"}"#)
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_server_header() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.server_header(HeaderValue::from_static("highnoon"));
+    app.at("/").get(|_req| async { "hello" });
+    app.at("/custom")
+        .get(|_req| async { Response::ok().raw_header("server", "custom") });
+
+    let tc = app.test();
+    tc.get("/")
+        .send()
+        .await?
+        .assert_header("server", "highnoon");
+    // also applies to responses generated by highnoon
+    tc.get("/nowhere")
+        .send()
+        .await?
+        .assert_header("server", "highnoon");
+    tc.get("/custom")
+        .send()
+        .await?
+        .assert_header("server", "custom");
+
+    let mut app = App::new(());
+    app.no_server_header();
+    app.at("/custom")
+        .get(|_req| async { Response::ok().raw_header("server", "custom") });
+
+    let resp = app.test().get("/custom").send().await?;
+    assert!(resp.as_ref().headers().get("server").is_none());
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_blocking_endpoint() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/sum").post(blocking(|mut req: Request<()>| {
+        // simulate blocking work, which would stall a single threaded runtime
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let body = tokio::runtime::Handle::current().block_on(req.body_string())?;
+        let sum: u32 = body.split(',').map(|n| n.parse::<u32>().unwrap_or(0)).sum();
+        highnoon::Result::Ok(sum.to_string())
+    }));
+    app.at("/panic")
+        .get(blocking(|_req: Request<()>| -> &'static str {
+            panic!("blocking handler failed")
+        }));
+    let tc = app.test();
+
+    tc.post("/sum")
+        .body("1,2,3")?
+        .send()
+        .await?
+        .assert_body("6")
+        .await;
+    tc.get("/panic")
+        .send()
+        .await?
+        .assert_status(StatusCode::INTERNAL_SERVER_ERROR)
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_detailed_body_errors() -> highnoon::Result<()> {
+    #[derive(serde_derive::Deserialize)]
+    struct Secret {
+        #[allow(dead_code)]
+        internal_field_name: u32,
+    }
+
+    async fn parse(mut req: Request<()>) -> highnoon::Result<&'static str> {
+        let _: Secret = req.body_input().await?;
+        Ok("ok")
+    }
+
+    let mut app = App::new(());
+    app.at("/").post(parse);
+    let tc = app.test();
+
+    let mut resp = tc.post("/").json(json!({}))?.send().await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert!(resp.body_string().await?.contains("internal_field_name"));
+
+    let mut app = App::new(());
+    app.detailed_body_errors(false);
+    app.at("/").post(parse);
+    let tc = app.test();
+
+    for req in [
+        tc.post("/").json(json!({}))?,
+        tc.post("/").form([("other", "1")])?,
+    ] {
+        req.send()
+            .await?
+            .assert_status(StatusCode::BAD_REQUEST)
+            .await
+            .assert_body("invalid request body")
+            .await;
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_uri_scheme_and_host() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/").get(|req: Request<()>| async move {
+        format!("{} {:?}", req.uri_scheme(), req.uri_host())
+    });
+    let tc = app.test();
+
+    tc.get("/")
+        .raw_header("host", "example.com:8080")?
+        .send()
+        .await?
+        .assert_body("http Some(\"example.com\")")
+        .await;
+    tc.get("https://proxied.example.com/")
+        .raw_header("host", "example.com")?
+        .send()
+        .await?
+        .assert_body("https Some(\"proxied.example.com\")")
+        .await;
+    tc.get("/").send().await?.assert_body("http None").await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_json_with_status() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/users").post(|_req| async {
+        Response::json_with_status(StatusCode::CREATED, serde_json::json!({ "id": 42 }))
+    });
+    let tc = app.test();
+
+    tc.post("/users")
+        .send()
+        .await?
+        .assert_status(StatusCode::CREATED)
+        .await
+        .assert_header("content-type", "application/json")
+        .assert_json(serde_json::json!({ "id": 42 }))
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_is_upgrade() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/")
+        .get(|req: Request<()>| async move { req.is_upgrade().to_string() });
+    let tc = app.test();
+
+    tc.get("/")
+        .raw_header("connection", "keep-alive, Upgrade")?
+        .raw_header("upgrade", "websocket")?
+        .send()
+        .await?
+        .assert_body("true")
+        .await;
+    tc.get("/")
+        .raw_header("upgrade", "websocket")?
+        .send()
+        .await?
+        .assert_body("false")
+        .await;
+    tc.get("/")
+        .raw_header("connection", "upgrade")?
+        .send()
+        .await?
+        .assert_body("false")
+        .await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_buffer_body() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/twice").post(|mut req: Request<()>| async move {
+        req.buffer_body().await?;
+        let first = req.body_string().await?;
+        let value: serde_json::Value = req.body_json().await?;
+        Ok(format!(
+            "{} {} {}",
+            first.len(),
+            value["n"],
+            req.body_bytes().await?.len()
+        ))
+    });
+    app.at("/limited").post(|mut req: Request<()>| async move {
+        req.buffer_body_limited(4).await?;
+        Ok("")
+    });
+    let tc = app.test();
+
+    tc.post("/twice")
+        .body(r#"{"n":1}"#)?
+        .send()
+        .await?
+        .assert_body("7 1 7")
+        .await;
+    tc.post("/limited")
+        .body("too long")?
+        .send()
+        .await?
+        .assert_status(StatusCode::PAYLOAD_TOO_LARGE)
+        .await;
+
+    Ok(())
+}
+
+fn cookie_app() -> App<()> {
+    use highnoon::cookie::Cookie;
+
+    let mut app = App::new(());
+    app.at("/login").post(|_req| async {
+        let mut admin = Cookie::new("admin", "yes");
+        admin.set_path("/admin");
+        let mut other = Cookie::new("other", "no");
+        other.set_domain("other.example");
+
+        let mut resp = Response::ok();
+        resp.set_cookie(Cookie::new("user", "steve"))?;
+        resp.set_cookie(admin)?;
+        resp.set_cookie(other)?;
+        Ok(resp)
+    });
+    app.at("/logout").post(|_req| async {
+        let mut removal = Cookie::new("user", "");
+        removal.make_removal();
+        Response::ok().cookie(removal)
+    });
+    app.at("/*").get(|req: Request<()>| async move {
+        req.headers()
+            .get("cookie")
+            .map(|c| c.to_str().unwrap().to_owned())
+            .unwrap_or_default()
+    });
+    app
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_cookie_jar() -> highnoon::Result<()> {
+    // without a jar cookies aren't remembered
+    let tc = cookie_app().test();
+    tc.post("/login").send().await?;
+    tc.get("/me").send().await?.assert_body("").await;
+
+    let tc = cookie_app().test().with_cookies();
+    tc.post("/login").send().await?;
+    tc.get("/me").send().await?.assert_body("user=steve").await;
+    tc.get("/admin/panel")
+        .send()
+        .await?
+        .assert_body("admin=yes; user=steve")
+        .await;
+    tc.get("http://www.other.example/me")
+        .send()
+        .await?
+        .assert_body("other=no; user=steve")
+        .await;
+
+    // explicit cookies are kept
+    tc.get("/me")
+        .raw_header("cookie", "extra=1")?
+        .send()
+        .await?
+        .assert_body("extra=1; user=steve")
+        .await;
+
+    tc.post("/logout").send().await?;
+    tc.get("/me").send().await?.assert_body("").await;
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_warn_unread_body() -> highnoon::Result<()> {
+    let logs = LogCapture::default();
+    let _guard = logs.set_default();
+
+    let mut app = App::new(());
+    app.warn_unread_body(true);
+    app.at("/ignore").post(|_req| async { "ignored" });
+    app.at("/read")
+        .post(|mut req: Request<()>| async move { req.body_string().await });
+    let tc = app.test();
+
+    tc.post("/ignore")
+        .body("unread")?
+        .send()
+        .await?
+        .assert_body("ignored")
+        .await;
+    let output = logs.take();
+    assert!(
+        output.contains("request body was not read by the handler"),
+        "{}",
+        output
+    );
+    assert!(output.contains("/ignore"), "{}", output);
+
+    tc.post("/read")
+        .body("read")?
+        .send()
+        .await?
+        .assert_body("read")
+        .await;
+    let output = logs.take();
+    assert!(!output.contains("request body was not read"), "{}", output);
 
     Ok(())
 }
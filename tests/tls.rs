@@ -0,0 +1,208 @@
+#![cfg(feature = "tls")]
+
+use highnoon::rustls::server::AllowAnyAnonymousOrAuthenticatedClient;
+use highnoon::rustls::{
+    Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig, ServerName,
+};
+#[cfg(feature = "client")]
+use highnoon::HttpClient;
+use highnoon::{App, Request};
+use rcgen::{BasicConstraints, CertificateParams, DistinguishedName, DnType, IsCa};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::TlsConnector;
+
+struct Pki {
+    ca: Certificate,
+    server: (Certificate, PrivateKey),
+    client: (Certificate, PrivateKey),
+}
+
+fn make_cert(name: &str, signer: &rcgen::Certificate) -> anyhow::Result<(Certificate, PrivateKey)> {
+    let mut params = CertificateParams::new(vec![name.to_owned()]);
+    params.distinguished_name = DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, name);
+    let cert = rcgen::Certificate::from_params(params)?;
+
+    Ok((
+        Certificate(cert.serialize_der_with_signer(signer)?),
+        PrivateKey(cert.serialize_private_key_der()),
+    ))
+}
+
+fn make_pki() -> anyhow::Result<Pki> {
+    let mut params = CertificateParams::new(vec![]);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "Test CA");
+    let ca = rcgen::Certificate::from_params(params)?;
+
+    Ok(Pki {
+        server: make_cert("localhost", &ca)?,
+        client: make_cert("client", &ca)?,
+        ca: Certificate(ca.serialize_der()?),
+    })
+}
+
+async fn start_server(pki: &Pki) -> anyhow::Result<SocketAddr> {
+    let mut roots = RootCertStore::empty();
+    roots.add(&pki.ca)?;
+
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots))
+        .with_single_cert(vec![pki.server.0.clone()], pki.server.1.clone())?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    let mut app = App::new(());
+    app.at("/cert").get(|req: Request<()>| async move {
+        match req.client_cert() {
+            Some(cert) => format!("{} {}", cert.subject(), cert.fingerprint().len()),
+            None => "anonymous".to_owned(),
+        }
+    });
+    app.at("/session").get(|req: Request<()>| async move {
+        format!(
+            "{} {}",
+            req.sni_hostname().unwrap_or("-"),
+            req.alpn_protocol().unwrap_or("-")
+        )
+    });
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on_tls(tcp, config));
+
+    Ok(addr)
+}
+
+async fn get(addr: SocketAddr, pki: &Pki, client_cert: bool, path: &str) -> anyhow::Result<String> {
+    let mut roots = RootCertStore::empty();
+    roots.add(&pki.ca)?;
+
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+    let mut config = if client_cert {
+        builder.with_single_cert(vec![pki.client.0.clone()], pki.client.1.clone())?
+    } else {
+        builder.with_no_client_auth()
+    };
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    let tcp = tokio::net::TcpStream::connect(addr).await?;
+    let mut stream = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost")?, tcp)
+        .await?;
+
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+
+    let (_, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow::Error::msg("malformed response"))?;
+    Ok(body.to_owned())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_client_cert() -> anyhow::Result<()> {
+    let pki = make_pki()?;
+    let addr = start_server(&pki).await?;
+
+    assert_eq!(get(addr, &pki, true, "/cert").await?, "CN=client 64");
+    assert_eq!(get(addr, &pki, false, "/cert").await?, "anonymous");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_sni_and_alpn() -> anyhow::Result<()> {
+    let pki = make_pki()?;
+    let addr = start_server(&pki).await?;
+
+    assert_eq!(
+        get(addr, &pki, false, "/session").await?,
+        "localhost http/1.1"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "client")]
+#[tokio::main]
+#[test]
+pub async fn test_http_client_tls() -> highnoon::Result<()> {
+    let pki = make_pki()?;
+    let addr = start_server(&pki).await?;
+
+    let mut roots = RootCertStore::empty();
+    roots.add(&pki.ca)?;
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let client = HttpClient::with_tls(config);
+    let resp = client
+        .get(&format!("https://localhost:{}/session", addr.port()))
+        .await?;
+    assert_eq!(
+        hyper::body::to_bytes(resp.into_body()).await?,
+        "localhost -"
+    );
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_tls_config_from_pem() -> anyhow::Result<()> {
+    let pki = make_pki()?;
+
+    // a new CA and server certificate, written out as PEM files
+    let mut params = CertificateParams::new(vec![]);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = rcgen::Certificate::from_params(params)?;
+    let server =
+        rcgen::Certificate::from_params(CertificateParams::new(vec!["localhost".to_owned()]))?;
+
+    let dir = std::env::temp_dir().join(format!("highnoon-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    std::fs::write(
+        &cert_path,
+        server.serialize_pem_with_signer(&ca)? + &ca.serialize_pem()?,
+    )?;
+    std::fs::write(&key_path, server.serialize_private_key_pem())?;
+
+    let config = highnoon::tls_config_from_pem(&cert_path, &key_path)?;
+    assert!(highnoon::tls_config_from_pem(&key_path, &key_path).is_err());
+    assert!(highnoon::tls_config_from_pem(dir.join("missing.pem"), &key_path).is_err());
+    std::fs::remove_dir_all(&dir)?;
+
+    let mut app = App::new(());
+    app.at("/session")
+        .get(|req: Request<()>| async move { req.alpn_protocol().unwrap_or("-").to_owned() });
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on_tls(tcp, config));
+
+    let pki = Pki {
+        ca: Certificate(ca.serialize_der()?),
+        ..pki
+    };
+    assert_eq!(get(addr, &pki, false, "/session").await?, "http/1.1");
+
+    Ok(())
+}
@@ -0,0 +1,360 @@
+#![cfg(all(feature = "websocket", feature = "test-client"))]
+
+use futures_util::{SinkExt, StreamExt};
+use highnoon::ws::{self, Broadcast, WsConfig, WsCounters};
+use highnoon::{App, Message, Request};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::main]
+#[test]
+pub async fn test_ws_metrics() -> anyhow::Result<()> {
+    let counters = Arc::new(WsCounters::default());
+
+    let mut app = App::new(());
+    app.at("/echo").get(
+        ws::endpoint(|_req, mut tx, mut rx| async move {
+            while let Some(msg) = rx.recv().await? {
+                if msg.is_close() {
+                    break;
+                }
+                tx.send(msg).await?;
+            }
+            Ok(())
+        })
+        .metrics(counters.clone()),
+    );
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on(tcp));
+
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let (mut client, _) =
+        tokio_tungstenite::client_async(format!("ws://{}/echo", addr), stream).await?;
+
+    client.send(Message::text("one")).await?;
+    client.send(Message::text("two")).await?;
+    assert_eq!(client.next().await.transpose()?, Some(Message::text("one")));
+    assert_eq!(client.next().await.transpose()?, Some(Message::text("two")));
+
+    assert_eq!(counters.opened(), 1);
+    assert_eq!(counters.active(), 1);
+
+    client.close(None).await?;
+    while client.next().await.is_some() {}
+
+    // the handler finishes on the server's task, so wait for it to drop the connection
+    for _ in 0..50 {
+        if counters.active() == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(counters.active(), 0);
+    assert_eq!(counters.opened(), 1);
+    assert_eq!(counters.messages_sent(), 2);
+    assert_eq!(counters.messages_received(), 3);
+    assert!(counters.connected_time() > Duration::ZERO);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_ws_echo() -> anyhow::Result<()> {
+    let mut app = App::new(());
+    app.at("/echo").ws_echo();
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on(tcp));
+
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let (mut client, resp) =
+        tokio_tungstenite::client_async(format!("ws://{}/echo", addr), stream).await?;
+    assert_eq!(resp.status(), 101);
+
+    client.send(Message::text("hello")).await?;
+    assert_eq!(
+        client.next().await.transpose()?,
+        Some(Message::text("hello"))
+    );
+
+    client.send(Message::binary(vec![1, 2, 3])).await?;
+    assert_eq!(
+        client.next().await.transpose()?,
+        Some(Message::binary(vec![1, 2, 3]))
+    );
+
+    client.send(Message::Ping(b"ping".to_vec())).await?;
+    assert_eq!(
+        client.next().await.transpose()?,
+        Some(Message::Pong(b"ping".to_vec()))
+    );
+
+    client.close(None).await?;
+    assert!(matches!(
+        client.next().await.transpose()?,
+        Some(Message::Close(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_ws_accept_headers() -> anyhow::Result<()> {
+    let mut app = App::new(());
+    app.at("/ws").ws_with_headers(
+        |resp| resp.set_raw_header("x-session", "abc123").unwrap(),
+        |_req, _tx, _rx| async { Ok(()) },
+    );
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on(tcp));
+
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let (_client, resp) =
+        tokio_tungstenite::client_async(format!("ws://{}/ws", addr), stream).await?;
+    assert_eq!(resp.status(), 101);
+    assert_eq!(resp.headers()["x-session"], "abc123");
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_ws_keepalive() -> anyhow::Result<()> {
+    let (result_tx, mut results) = tokio::sync::mpsc::unbounded_channel();
+
+    let mut app = App::new(());
+    app.at("/ws").ws_with(
+        WsConfig {
+            ping_interval: Some(Duration::from_millis(50)),
+            pong_timeout: Duration::from_millis(100),
+        },
+        move |_req, mut tx, mut rx| {
+            let result_tx = result_tx.clone();
+            async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(Some(msg)) if msg.is_text() => tx.send(msg).await?,
+                        Ok(Some(_)) => {}
+                        Ok(None) => break,
+                        Err(err) => {
+                            result_tx.send(err.to_string()).unwrap();
+                            break;
+                        }
+                    }
+                }
+                Ok(())
+            }
+        },
+    );
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on(tcp));
+
+    // a client which keeps reading answers the pings and stays connected
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let (mut client, _) =
+        tokio_tungstenite::client_async(format!("ws://{}/ws", addr), stream).await?;
+    for _ in 0..4 {
+        assert!(client.next().await.transpose()?.unwrap().is_ping());
+    }
+    client.send(Message::text("still here")).await?;
+    loop {
+        let msg = client.next().await.transpose()?.unwrap();
+        if !msg.is_ping() {
+            assert_eq!(msg, Message::text("still here"));
+            break;
+        }
+    }
+    client.close(None).await?;
+    while client.next().await.is_some() {}
+    assert!(results.try_recv().is_err());
+
+    // a client which stops reading is disconnected, and the handler sees an error
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let (_idle, _) = tokio_tungstenite::client_async(format!("ws://{}/ws", addr), stream).await?;
+    let err = tokio::time::timeout(Duration::from_secs(5), results.recv())
+        .await?
+        .unwrap();
+    assert!(err.contains("ping timed out"), "{}", err);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_ws_upgrade_error() -> highnoon::Result<()> {
+    let (error_tx, mut errors) = tokio::sync::mpsc::unbounded_channel();
+    let (called_tx, mut called) = tokio::sync::mpsc::unbounded_channel::<()>();
+
+    let mut app = App::new(());
+    app.at("/ws").get(
+        ws::endpoint(move |_req, _tx, _rx| {
+            let called_tx = called_tx.clone();
+            async move {
+                called_tx.send(()).unwrap();
+                Ok(())
+            }
+        })
+        .on_upgrade_error(move |err| error_tx.send(err.to_string()).unwrap()),
+    );
+
+    // the test client has no connection to upgrade
+    let resp = app
+        .test()
+        .get("/ws")
+        .raw_header("connection", "upgrade")?
+        .raw_header("upgrade", "websocket")?
+        .raw_header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")?
+        .send()
+        .await?;
+    assert_eq!(resp.status(), 101);
+
+    let err = tokio::time::timeout(Duration::from_secs(5), errors.recv()).await?;
+    assert!(err.is_some());
+    assert!(called.try_recv().is_err());
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_ws_broadcast() -> anyhow::Result<()> {
+    struct State {
+        chat: Broadcast<String>,
+    }
+
+    impl highnoon::State for State {
+        type Context = ();
+        fn new_context(&self) {}
+    }
+
+    let mut app = App::new(State {
+        chat: Broadcast::new(16),
+    });
+    app.at("/chat")
+        .ws(|req: Request<State>, mut tx, mut rx| async move {
+            let chat = &req.state().chat;
+            let _forwarding = chat.forward(tx.clone());
+            tx.send(Message::text("joined")).await?;
+            while let Some(msg) = rx.recv().await? {
+                if let Message::Text(text) = msg {
+                    chat.send(text);
+                }
+            }
+            Ok(())
+        });
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on(tcp));
+
+    let mut clients = Vec::new();
+    for _ in 0..2 {
+        let stream = tokio::net::TcpStream::connect(addr).await?;
+        let (mut client, _) =
+            tokio_tungstenite::client_async(format!("ws://{}/chat", addr), stream).await?;
+        // wait until the handler has subscribed
+        assert_eq!(
+            client.next().await.transpose()?,
+            Some(Message::text("joined"))
+        );
+        clients.push(client);
+    }
+
+    clients[0].send(Message::text("hello all")).await?;
+    for client in &mut clients {
+        assert_eq!(
+            client.next().await.transpose()?,
+            Some(Message::text("hello all"))
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_ws_json() -> anyhow::Result<()> {
+    #[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    let mut app = App::new(());
+    app.at("/json").ws(|_req, mut tx, mut rx| async move {
+        loop {
+            match rx.recv_json::<Point>().await {
+                Ok(Some(p)) => tx.send_json(&Point { x: p.y, y: p.x }).await?,
+                Ok(None) => break,
+                Err(_) => tx.send(Message::text("bad")).await?,
+            }
+        }
+        Ok(())
+    });
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = tcp.local_addr()?;
+    tokio::spawn(app.listen_on(tcp));
+
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let (mut client, _) =
+        tokio_tungstenite::client_async(format!("ws://{}/json", addr), stream).await?;
+
+    client.send(Message::Ping(b"hi".to_vec())).await?;
+    client.send(Message::text(r#"{"x": 1, "y": 2}"#)).await?;
+    client
+        .send(Message::binary(br#"{"x": 3, "y": 4}"#.to_vec()))
+        .await?;
+    client.send(Message::text("not json")).await?;
+
+    let mut replies = Vec::new();
+    while replies.len() < 3 {
+        match client.next().await.transpose()?.unwrap() {
+            Message::Pong(_) => {}
+            msg => replies.push(msg),
+        }
+    }
+    assert_eq!(
+        replies,
+        vec![
+            Message::text(r#"{"x":2,"y":1}"#),
+            Message::text(r#"{"x":4,"y":3}"#),
+            Message::text("bad"),
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+pub async fn test_ws_test_client() -> highnoon::Result<()> {
+    let mut app = App::new(());
+    app.at("/echo").ws_echo();
+    app.at("/plain").get(|_req| async { "not a websocket" });
+
+    let client = app.test();
+
+    let mut ws = client.ws("/echo").await?;
+    ws.send(Message::text("hello")).await?;
+    ws.send(Message::binary(vec![1, 2, 3])).await?;
+    assert_eq!(ws.recv().await?, Some(Message::text("hello")));
+    assert_eq!(ws.recv().await?, Some(Message::binary(vec![1, 2, 3])));
+
+    ws.close().await?;
+    while ws.recv().await?.is_some() {}
+
+    assert!(client.ws("/plain").await.is_err());
+
+    Ok(())
+}